@@ -0,0 +1,627 @@
+use bevy::prelude::*;
+use std::collections::HashMap;
+use crate::components::*;
+use crate::resources::*;
+use crate::spatial::{Boundary, SpatialGrid};
+use crate::render::{ColorMode, RenderSettings};
+
+/// Settings for grouping particles into clusters.
+#[derive(Resource)]
+pub struct ClusterSettings {
+    // Two particles closer than this belong to the same cluster
+    pub link_distance: f32,
+    // Clusters smaller than this are not listed or catalogued (single particles are not structures)
+    pub min_size: usize,
+    // Relabel every frame instead of on request (always on while colouring by cluster)
+    pub live: bool,
+    pub compute_requested: bool,
+}
+
+impl Default for ClusterSettings {
+    fn default() -> Self {
+        Self { link_distance: 30.0, min_size: 2, live: false, compute_requested: false }
+    }
+}
+
+/// Measured properties of one cluster.
+#[derive(Debug, Clone)]
+pub struct ClusterStats {
+    pub id: usize,
+    pub size: usize,
+    // Particle count per ParticleTypeID
+    pub composition: Vec<usize>,
+    pub center_of_mass: Vec2,
+    // Largest member distance from the centre of mass
+    pub radius: f32,
+    // Centre of mass velocity
+    pub velocity: Vec2,
+    // Angular velocity about the centre of mass (L / I), counter-clockwise positive
+    pub spin: f32,
+}
+
+/// How often a composition has been seen, for the "periodic table" of structures.
+#[derive(Debug, Clone, Default)]
+pub struct StructureRecord {
+    // Analysed frames in which at least one cluster had this composition
+    pub frames_observed: u64,
+    // Most clusters with this composition present at once
+    pub peak_count: usize,
+}
+
+/// Results of the latest cluster analysis.
+#[derive(Resource, Default)]
+pub struct ClusterAnalysis {
+    // Clusters of at least `min_size`, largest first
+    pub clusters: Vec<ClusterStats>,
+    // Composition -> record. Cleared when the number of particle types changes.
+    pub catalog: HashMap<Vec<usize>, StructureRecord>,
+}
+
+/// Minimal union-find (disjoint set) with path halving.
+pub struct UnionFind {
+    parent: Vec<usize>,
+}
+
+impl UnionFind {
+    pub fn new(size: usize) -> Self {
+        Self { parent: (0..size).collect() }
+    }
+
+    pub fn find(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    pub fn union(&mut self, a: usize, b: usize) {
+        let ra = self.find(a);
+        let rb = self.find(b);
+        if ra != rb {
+            self.parent[rb] = ra;
+        }
+    }
+}
+
+/// Groups positions into clusters of particles linked within `link_distance`.
+/// Returns a cluster index per position; indices are consecutive in order of first appearance.
+pub fn label_clusters(positions: &[Vec2], link_distance: f32) -> Vec<usize> {
+    let mut uf = UnionFind::new(positions.len());
+    let grid = SpatialGrid::new(positions, link_distance, Boundary::Open);
+    grid.for_each_pair_within(positions, link_distance, |i, j, _| uf.union(i, j));
+
+    // Compact root indices into consecutive cluster ids (in iteration order, so ids are stable-ish)
+    let mut root_to_id = HashMap::new();
+    (0..positions.len())
+        .map(|i| {
+            let root = uf.find(i);
+            let next_id = root_to_id.len();
+            *root_to_id.entry(root).or_insert(next_id)
+        })
+        .collect()
+}
+
+/// Number of clusters with at least `min_size` particles.
+pub fn count_clusters(positions: &[Vec2], link_distance: f32, min_size: usize) -> usize {
+    let labels = label_clusters(positions, link_distance);
+    let mut sizes = vec![0usize; labels.iter().max().map_or(0, |m| m + 1)];
+    for label in labels {
+        sizes[label] += 1;
+    }
+    sizes.iter().filter(|&&size| size >= min_size).count()
+}
+
+/// Computes composition, centre of mass, radius, velocity and spin of every labelled cluster.
+pub fn cluster_stats(
+    labels: &[usize],
+    positions: &[Vec2],
+    velocities: &[Vec2],
+    masses: &[f32],
+    type_ids: &[usize],
+    num_types: usize,
+) -> Vec<ClusterStats> {
+    let num_clusters = labels.iter().max().map_or(0, |m| m + 1);
+    let mut stats: Vec<ClusterStats> = (0..num_clusters)
+        .map(|id| ClusterStats {
+            id,
+            size: 0,
+            composition: vec![0; num_types],
+            center_of_mass: Vec2::ZERO,
+            radius: 0.0,
+            velocity: Vec2::ZERO,
+            spin: 0.0,
+        })
+        .collect();
+    let mut total_mass = vec![0.0_f32; num_clusters];
+
+    // 1. Totals: size, composition, mass-weighted position and velocity
+    for (i, &label) in labels.iter().enumerate() {
+        let cluster = &mut stats[label];
+        cluster.size += 1;
+        if let Some(count) = cluster.composition.get_mut(type_ids[i]) {
+            *count += 1;
+        }
+        cluster.center_of_mass += positions[i] * masses[i];
+        cluster.velocity += velocities[i] * masses[i];
+        total_mass[label] += masses[i];
+    }
+    for (cluster, mass) in stats.iter_mut().zip(total_mass.iter()) {
+        if *mass > 0.0 {
+            cluster.center_of_mass /= *mass;
+            cluster.velocity /= *mass;
+        }
+    }
+
+    // 2. Radius and rotation relative to the centre of mass
+    let mut angular_momentum = vec![0.0_f32; num_clusters];
+    let mut inertia = vec![0.0_f32; num_clusters];
+    for (i, &label) in labels.iter().enumerate() {
+        let cluster = &mut stats[label];
+        let r = positions[i] - cluster.center_of_mass;
+        let v = velocities[i] - cluster.velocity;
+        cluster.radius = cluster.radius.max(r.length());
+        angular_momentum[label] += masses[i] * r.perp_dot(v);
+        inertia[label] += masses[i] * r.length_squared();
+    }
+    for (label, cluster) in stats.iter_mut().enumerate() {
+        if inertia[label] > f32::EPSILON {
+            cluster.spin = angular_momentum[label] / inertia[label];
+        }
+    }
+
+    stats
+}
+
+/// Labels every particle with a `ClusterId` and refreshes `ClusterAnalysis`, when live,
+/// on request, or while the particles are coloured by cluster.
+#[allow(clippy::type_complexity)]
+pub fn cluster_labeling_system(
+    mut commands: Commands,
+    mut settings: ResMut<ClusterSettings>,
+    render_settings: Res<RenderSettings>,
+    alchemy: Res<AlchemyRules>,
+    mut analysis: ResMut<ClusterAnalysis>,
+    mut query: Query<(Entity, &Transform, &Velocity, &Mass, &ParticleTypeID, Option<&mut ClusterId>), (With<Particle>, Without<Quarantined>)>,
+) {
+    let coloring = render_settings.color_mode == ColorMode::Cluster;
+    if !settings.live && !settings.compute_requested && !coloring { return; }
+    settings.compute_requested = false;
+
+    let mut positions = Vec::new();
+    let mut velocities = Vec::new();
+    let mut masses = Vec::new();
+    let mut type_ids = Vec::new();
+    for (_, transform, velocity, mass, type_id, _) in query.iter() {
+        positions.push(transform.translation.truncate());
+        velocities.push(velocity.0);
+        masses.push(mass.0);
+        type_ids.push(type_id.0);
+    }
+
+    let labels = label_clusters(&positions, settings.link_distance);
+
+    for ((entity, _, _, _, _, cluster), &id) in query.iter_mut().zip(labels.iter()) {
+        match cluster {
+            Some(mut cluster) => {
+                if cluster.0 != id { cluster.0 = id; }
+            }
+            None => { commands.entity(entity).insert(ClusterId(id)); }
+        }
+    }
+
+    let num_types = alchemy.particle_types.len();
+    let mut clusters = cluster_stats(&labels, &positions, &velocities, &masses, &type_ids, num_types);
+    clusters.retain(|cluster| cluster.size >= settings.min_size.max(1));
+    clusters.sort_by(|a, b| b.size.cmp(&a.size).then(a.id.cmp(&b.id)));
+
+    // Periodic table: count each composition once per frame
+    if analysis.catalog.keys().next().is_some_and(|key| key.len() != num_types) {
+        analysis.catalog.clear();
+    }
+    let mut present: HashMap<&Vec<usize>, usize> = HashMap::new();
+    for cluster in clusters.iter() {
+        *present.entry(&cluster.composition).or_default() += 1;
+    }
+    for (composition, count) in present {
+        let record = analysis.catalog.entry(composition.clone()).or_default();
+        record.frames_observed += 1;
+        record.peak_count = record.peak_count.max(count);
+    }
+
+    analysis.clusters = clusters;
+}
+
+/// Human-readable composition, e.g. "2 Proton + 1 Electron".
+pub fn composition_label(composition: &[usize], alchemy: &AlchemyRules) -> String {
+    composition.iter()
+        .enumerate()
+        .filter(|(_, &count)| count > 0)
+        .map(|(type_id, count)| {
+            let name = alchemy.particle_types.get(type_id).map_or("?", |def| def.name.as_str());
+            format!("{} {}", count, name)
+        })
+        .collect::<Vec<_>>()
+        .join(" + ")
+}
+
+/// Settings for the structural (phase) analysis.
+#[derive(Resource)]
+pub struct StructureSettings {
+    // Largest distance covered by g(r)
+    pub r_max: f32,
+    pub bins: usize,
+    // Particles closer than this count as nearest neighbors (for counts and ψ6)
+    pub neighbor_cutoff: f32,
+    // Analyse as a periodic box centred on the origin. The simulation itself is unbounded,
+    // so this is for samples arranged in a box (e.g. loaded or scripted setups).
+    pub periodic: bool,
+    pub box_size: Vec2,
+    // Recompute every frame instead of on request
+    pub live: bool,
+    pub compute_requested: bool,
+    // CSV files are written as <prefix>_rdf.csv and <prefix>_particles.csv
+    pub export_prefix: String,
+}
+
+impl Default for StructureSettings {
+    fn default() -> Self {
+        Self {
+            r_max: 150.0,
+            bins: 75,
+            neighbor_cutoff: 30.0,
+            periodic: false,
+            box_size: Vec2::new(1000.0, 1000.0),
+            live: false,
+            compute_requested: false,
+            export_prefix: "structure".to_string(),
+        }
+    }
+}
+
+impl StructureSettings {
+    pub fn boundary(&self) -> Boundary {
+        if self.periodic { Boundary::Periodic(self.box_size) } else { Boundary::Open }
+    }
+}
+
+/// Radial distribution function of one type pair.
+#[derive(Debug, Clone)]
+pub struct RdfCurve {
+    pub type_a: usize,
+    pub type_b: usize,
+    pub g: Vec<f32>,
+}
+
+/// Per-particle structural measures.
+#[derive(Debug, Clone)]
+pub struct ParticleOrder {
+    pub position: Vec2,
+    pub type_id: usize,
+    pub neighbors: usize,
+    // |ψ6| of the local neighborhood (1 = perfect hexagonal order)
+    pub psi6: f32,
+}
+
+/// Results of the last structural analysis.
+#[derive(Resource, Default)]
+pub struct StructureAnalysis {
+    pub bin_width: f32,
+    // One curve per type pair a <= b. Empty when g(r) is undefined: with an open boundary,
+    // particles spanning no area (e.g. all on one line) have no density to normalize by.
+    pub rdf: Vec<RdfCurve>,
+    // neighbor_histogram[k] = number of particles with k nearest neighbors
+    pub neighbor_histogram: Vec<usize>,
+    pub mean_neighbors_per_type: Vec<f32>,
+    // |<ψ6>| over all particles with neighbors (global orientational order)
+    pub psi6_global: f32,
+    // <|ψ6|> (local order, insensitive to grain orientation)
+    pub psi6_local_mean: f32,
+    pub particles: Vec<ParticleOrder>,
+}
+
+/// Computes g(r) per type pair, nearest-neighbor counts and bond-orientational order ψ6.
+pub fn compute_structure(
+    positions: &[Vec2],
+    type_ids: &[usize],
+    num_types: usize,
+    settings: &StructureSettings,
+) -> StructureAnalysis {
+    let boundary = settings.boundary();
+    let bins = settings.bins.max(1);
+    let mut r_max = settings.r_max.max(settings.neighbor_cutoff).max(f32::EPSILON);
+    if let Boundary::Periodic(size) = boundary {
+        // Beyond half the box the minimum image is ambiguous
+        r_max = r_max.min(size.min_element() / 2.0);
+    }
+    let bin_width = settings.r_max.min(r_max).max(f32::EPSILON) / bins as f32;
+
+    // Ordered pair histograms hist[a * num_types + b][bin], counted from a's side
+    let mut hist = vec![vec![0usize; bins]; num_types * num_types];
+    let mut neighbors = vec![0usize; positions.len()];
+    let mut psi_sum = vec![Vec2::ZERO; positions.len()]; // complex number as (re, im)
+
+    let grid = SpatialGrid::new(positions, r_max, boundary);
+    grid.for_each_pair_within(positions, r_max, |i, j, delta| {
+        let distance = delta.length();
+        let (a, b) = (type_ids[i], type_ids[j]);
+
+        let bin = (distance / bin_width) as usize;
+        if bin < bins && a < num_types && b < num_types {
+            hist[a * num_types + b][bin] += 1;
+            hist[b * num_types + a][bin] += 1;
+        }
+
+        if distance <= settings.neighbor_cutoff {
+            neighbors[i] += 1;
+            neighbors[j] += 1;
+            // exp(6iθ) is the same for delta and -delta
+            let angle = 6.0 * delta.y.atan2(delta.x);
+            let bond = Vec2::new(angle.cos(), angle.sin());
+            psi_sum[i] += bond;
+            psi_sum[j] += bond;
+        }
+    });
+
+    // Normalize by the ideal-gas expectation: N_a * rho_b * shell area
+    let area = boundary.area(positions);
+    let mut counts = vec![0usize; num_types];
+    for &t in type_ids.iter().filter(|&&t| t < num_types) {
+        counts[t] += 1;
+    }
+    let mut rdf = Vec::new();
+    let rdf_types = if area > f32::EPSILON { num_types } else { 0 };
+    for a in 0..rdf_types {
+        for b in a..num_types {
+            let others = if a == b { counts[b].saturating_sub(1) } else { counts[b] };
+            let density = others as f32 / area;
+            let g = hist[a * num_types + b].iter()
+                .enumerate()
+                .map(|(bin, &n)| {
+                    let r = (bin as f32 + 0.5) * bin_width;
+                    let expected = counts[a] as f32 * density * std::f32::consts::TAU * r * bin_width;
+                    if expected > 0.0 { n as f32 / expected } else { 0.0 }
+                })
+                .collect();
+            rdf.push(RdfCurve { type_a: a, type_b: b, g });
+        }
+    }
+
+    let max_neighbors = neighbors.iter().copied().max().unwrap_or(0);
+    let mut neighbor_histogram = vec![0usize; max_neighbors + 1];
+    let mut neighbor_totals = vec![0usize; num_types];
+    for (i, &n) in neighbors.iter().enumerate() {
+        neighbor_histogram[n] += 1;
+        if let Some(total) = neighbor_totals.get_mut(type_ids[i]) {
+            *total += n;
+        }
+    }
+    let mean_neighbors_per_type = neighbor_totals.iter()
+        .zip(counts.iter())
+        .map(|(&total, &count)| if count > 0 { total as f32 / count as f32 } else { 0.0 })
+        .collect();
+
+    let particles: Vec<ParticleOrder> = (0..positions.len())
+        .map(|i| ParticleOrder {
+            position: positions[i],
+            type_id: type_ids[i],
+            neighbors: neighbors[i],
+            psi6: if neighbors[i] > 0 { (psi_sum[i] / neighbors[i] as f32).length() } else { 0.0 },
+        })
+        .collect();
+
+    let ordered: Vec<usize> = (0..positions.len()).filter(|&i| neighbors[i] > 0).collect();
+    let (psi6_global, psi6_local_mean) = if ordered.is_empty() {
+        (0.0, 0.0)
+    } else {
+        let n = ordered.len() as f32;
+        let global = ordered.iter().map(|&i| psi_sum[i] / neighbors[i] as f32).sum::<Vec2>() / n;
+        let local = ordered.iter().map(|&i| particles[i].psi6).sum::<f32>() / n;
+        (global.length(), local)
+    };
+
+    StructureAnalysis {
+        bin_width,
+        rdf,
+        neighbor_histogram,
+        mean_neighbors_per_type,
+        psi6_global,
+        psi6_local_mean,
+        particles,
+    }
+}
+
+impl StructureAnalysis {
+    /// Writes `<prefix>_rdf.csv` (r and one g column per type pair) and
+    /// `<prefix>_particles.csv` (position, type, neighbor count, |ψ6|).
+    pub fn write_csv(&self, prefix: &str, alchemy: &AlchemyRules) -> std::io::Result<()> {
+        use std::fmt::Write;
+        let name = |t: usize| alchemy.particle_types.get(t).map_or_else(|| t.to_string(), |def| def.name.clone());
+
+        let mut rdf = String::from("r");
+        for curve in self.rdf.iter() {
+            let _ = write!(rdf, ",g_{}_{}", name(curve.type_a), name(curve.type_b));
+        }
+        rdf.push('\n');
+        let bins = self.rdf.first().map_or(0, |curve| curve.g.len());
+        for bin in 0..bins {
+            let _ = write!(rdf, "{}", (bin as f32 + 0.5) * self.bin_width);
+            for curve in self.rdf.iter() {
+                let _ = write!(rdf, ",{}", curve.g[bin]);
+            }
+            rdf.push('\n');
+        }
+        std::fs::write(format!("{}_rdf.csv", prefix), rdf)?;
+
+        let mut particles = String::from("x,y,type,neighbors,psi6\n");
+        for p in self.particles.iter() {
+            let _ = writeln!(particles, "{},{},{},{},{}", p.position.x, p.position.y, name(p.type_id), p.neighbors, p.psi6);
+        }
+        std::fs::write(format!("{}_particles.csv", prefix), particles)
+    }
+}
+
+/// Recomputes the structural analysis when live or on request.
+#[allow(clippy::type_complexity)]
+pub fn structure_analysis_system(
+    mut settings: ResMut<StructureSettings>,
+    mut analysis: ResMut<StructureAnalysis>,
+    alchemy: Res<AlchemyRules>,
+    query: Query<(&Transform, &ParticleTypeID), (With<Particle>, Without<Quarantined>)>,
+) {
+    if !settings.live && !settings.compute_requested { return; }
+    settings.compute_requested = false;
+
+    let (positions, type_ids): (Vec<Vec2>, Vec<usize>) = query.iter()
+        .map(|(transform, type_id)| (transform.translation.truncate(), type_id.0))
+        .unzip();
+    *analysis = compute_structure(&positions, &type_ids, alchemy.particle_types.len(), &settings);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn separate_groups_get_separate_labels() {
+        let positions = [Vec2::new(0.0, 0.0), Vec2::new(100.0, 0.0), Vec2::new(5.0, 0.0), Vec2::new(105.0, 0.0)];
+        assert_eq!(label_clusters(&positions, 10.0), vec![0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn chains_link_through_intermediate_particles() {
+        // The ends are 16 apart, but each is within 10 of the middle one
+        let positions = [Vec2::new(0.0, 0.0), Vec2::new(16.0, 0.0), Vec2::new(8.0, 0.0)];
+        assert_eq!(label_clusters(&positions, 10.0), vec![0, 0, 0]);
+        assert_eq!(label_clusters(&positions, 7.0), vec![0, 1, 2]);
+
+        let mut uf = UnionFind::new(4);
+        uf.union(0, 1);
+        uf.union(2, 3);
+        uf.union(1, 3);
+        assert_eq!(uf.find(0), uf.find(2));
+    }
+
+    #[test]
+    fn small_clusters_are_not_counted() {
+        let positions = [
+            Vec2::new(0.0, 0.0), Vec2::new(5.0, 0.0),
+            Vec2::new(100.0, 0.0), Vec2::new(105.0, 0.0), Vec2::new(110.0, 0.0),
+            Vec2::new(-100.0, 0.0),
+        ];
+        assert_eq!(count_clusters(&positions, 10.0, 1), 3);
+        assert_eq!(count_clusters(&positions, 10.0, 2), 2);
+        assert_eq!(count_clusters(&positions, 10.0, 3), 1);
+        assert_eq!(count_clusters(&positions, 10.0, 4), 0);
+    }
+
+    #[test]
+    fn cluster_stats_weigh_by_mass() {
+        let positions = [Vec2::new(0.0, 0.0), Vec2::new(4.0, 0.0)];
+        let velocities = [Vec2::new(4.0, 0.0), Vec2::ZERO];
+        let stats = cluster_stats(&[0, 0], &positions, &velocities, &[1.0, 3.0], &[0, 1], 2);
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].size, 2);
+        assert_eq!(stats[0].composition, vec![1, 1]);
+        assert_eq!(stats[0].center_of_mass, Vec2::new(3.0, 0.0));
+        assert_eq!(stats[0].velocity, Vec2::new(1.0, 0.0));
+        assert_eq!(stats[0].radius, 3.0);
+    }
+
+    #[test]
+    fn spin_follows_the_sense_of_rotation() {
+        let ring: Vec<Vec2> = (0..8).map(|i| Vec2::from_angle(i as f32 * std::f32::consts::TAU / 8.0) * 10.0 + Vec2::new(50.0, 20.0)).collect();
+        let center = Vec2::new(50.0, 20.0);
+        for omega in [2.0, -0.5] {
+            // Rigid rotation at angular velocity omega (counter-clockwise positive), drifting as a whole
+            let velocities: Vec<Vec2> = ring.iter().map(|p| (*p - center).perp() * omega + Vec2::new(3.0, -1.0)).collect();
+            let stats = cluster_stats(&[0; 8], &ring, &velocities, &[1.0; 8], &[0; 8], 1);
+            assert!((stats[0].spin - omega).abs() < 1e-4, "spin {} for omega {}", stats[0].spin, omega);
+            assert!((stats[0].center_of_mass - center).length() < 1e-4);
+            assert!((stats[0].radius - 10.0).abs() < 1e-4);
+        }
+    }
+
+    fn periodic(box_size: f32, r_max: f32, bins: usize) -> StructureSettings {
+        StructureSettings { periodic: true, box_size: Vec2::splat(box_size), r_max, bins, ..default() }
+    }
+
+    /// Triangular lattice with spacing `a`, `n` × `n` points.
+    fn hexagonal(n: usize, a: f32) -> Vec<Vec2> {
+        let row_height = a * 3f32.sqrt() / 2.0;
+        (0..n).flat_map(|row| (0..n).map(move |col| {
+            let shift = if row % 2 == 1 { a / 2.0 } else { 0.0 };
+            Vec2::new(col as f32 * a + shift, row as f32 * row_height)
+        })).collect()
+    }
+
+    #[test]
+    fn rdf_of_a_uniform_gas_is_one() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let positions: Vec<Vec2> = (0..2000)
+            .map(|_| Vec2::new(rng.gen_range(-500.0..500.0), rng.gen_range(-500.0..500.0)))
+            .collect();
+        let structure = compute_structure(&positions, &vec![0; positions.len()], 1, &periodic(1000.0, 100.0, 20));
+        let g = &structure.rdf[0].g;
+        for (bin, &value) in g.iter().enumerate().skip(4) {
+            assert!((value - 1.0).abs() < 0.1, "g in bin {} is {}", bin, value);
+        }
+    }
+
+    #[test]
+    fn rdf_of_a_periodic_lattice_averages_to_one() {
+        // Square lattice filling the box exactly, so it tiles across the boundary
+        let positions: Vec<Vec2> = (0..20)
+            .flat_map(|i| (0..20).map(move |j| Vec2::new(i as f32 * 10.0 - 100.0, j as f32 * 10.0 - 100.0)))
+            .collect();
+        let structure = compute_structure(&positions, &vec![0; positions.len()], 1, &periodic(200.0, 100.0, 20));
+        let bin_width = structure.bin_width;
+        // The peaks are sharp, but weighted by shell area they average out to the density
+        let (weighted, weights) = structure.rdf[0].g.iter().enumerate().skip(4)
+            .map(|(bin, &g)| ((bin as f32 + 0.5) * bin_width, g))
+            .fold((0.0, 0.0), |(sum, total), (r, g)| (sum + g * r, total + r));
+        assert!((weighted / weights - 1.0).abs() < 0.05, "mean g = {}", weighted / weights);
+    }
+
+    #[test]
+    fn psi6_is_one_for_a_hexagonal_lattice() {
+        let positions = hexagonal(12, 10.0);
+        let settings = StructureSettings { neighbor_cutoff: 11.0, ..default() };
+        let structure = compute_structure(&positions, &vec![0; positions.len()], 1, &settings);
+        assert!(structure.psi6_global > 0.99, "{}", structure.psi6_global);
+        assert!(structure.psi6_local_mean > 0.99, "{}", structure.psi6_local_mean);
+        // Interior particles have six neighbours
+        assert!(structure.neighbor_histogram[6] >= 10 * 10);
+
+        // Square order has no sixfold symmetry
+        let square: Vec<Vec2> = (0..12).flat_map(|i| (0..12).map(move |j| Vec2::new(i as f32, j as f32) * 10.0)).collect();
+        let structure = compute_structure(&square, &vec![0; square.len()], 1, &settings);
+        assert!(structure.psi6_local_mean < 0.5, "{}", structure.psi6_local_mean);
+    }
+
+    #[test]
+    fn periodic_boxes_use_the_minimum_image() {
+        // 10 apart across the boundary of a 200 box, 190 apart inside it
+        let positions = [Vec2::new(-95.0, 0.0), Vec2::new(95.0, 0.0)];
+        let settings = StructureSettings { neighbor_cutoff: 15.0, ..periodic(200.0, 50.0, 10) };
+        let structure = compute_structure(&positions, &[0, 0], 1, &settings);
+        assert_eq!(structure.particles[0].neighbors, 1);
+        let bin = (10.0 / structure.bin_width) as usize;
+        assert!(structure.rdf[0].g[bin] > 0.0);
+
+        let open = StructureSettings { periodic: false, ..settings };
+        let structure = compute_structure(&positions, &[0, 0], 1, &open);
+        assert_eq!(structure.particles[0].neighbors, 0);
+    }
+
+    #[test]
+    fn rdf_is_undefined_for_particles_on_a_line() {
+        let positions: Vec<Vec2> = (0..10).map(|i| Vec2::new(i as f32 * 5.0, 0.0)).collect();
+        let structure = compute_structure(&positions, &[0; 10], 1, &StructureSettings::default());
+        assert!(structure.rdf.is_empty());
+        // The neighbour measures do not depend on the area
+        assert_eq!(structure.particles[5].neighbors, 9);
+    }
+}
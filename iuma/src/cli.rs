@@ -0,0 +1,377 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use crate::resources::*;
+use crate::share;
+use crate::evolution::{self, EvolutionConfig, FITNESS_NAMES};
+use crate::diagnostics::SimDiagnostics;
+use crate::headless::{self, HeadlessSim};
+use crate::snapshot::Snapshot;
+use crate::sweep::{self, SweepAxis, SweepConfig, SweepParam, SweepTable};
+use rand::SeedableRng;
+use serde::Serialize;
+
+const USAGE: &str = "\
+Usage: iuma [COMMAND] [OPTIONS]
+
+Without a command, opens the interactive simulation window.
+
+Commands:
+  run       Simulate headlessly and write the final state and diagnostics
+  sweep     Run once per value (or pair of values) of parameters and tabulate the results
+  validate  Check a rules file (and optionally a snapshot against it)
+  evolve    Evolve rulesets toward a target behaviour (headless)
+  help      Show this message
+
+Initial state options (run, sweep; evolve uses --particles, --radius and --seed):
+  --rules FILE            Rules (RON). Default: built-in rules
+  --snapshot FILE         Starting particles (RON). Default: a random disc of particles
+  --particles N           Particles per type for the random disc. Default: 30
+  --radius R              Radius of the random disc. Default: 300
+  --seed N                Seed for the random disc (and evolution). Default: 0
+
+run options:
+  --steps N               Default: 1000
+  --out FILE              Result (RON): final state, diagnostics, history. Default: result.ron
+  --sample-every N        Record diagnostics every N steps in the history (0 = off). Default: 0
+  --script FILE           Rhai script with setup() / on_step(step) hooks (see README)
+
+sweep options:
+  --param PATH            light_speed | time_scale | interactions[a,b] |
+                          particle_types[i].emission_shape.max_radius |
+                          particle_types[i].emission_shape.strength_scale |
+                          particle_types[i].default_mass
+  --from X --to Y         Value range (inclusive)
+  --count N               Number of values. Default: 5
+  --param2 PATH           Optional second parameter; every combination is run
+  --from2 X --to2 Y       Range of the second parameter
+  --count2 N              Number of values of the second parameter. Default: 5
+  --steps N               Steps per run. Default: 1000
+  --link-distance D       Distance linking particles into a cluster. Default: 30
+  --threads N             Parallel runs (0 = all cores). Default: 0
+  --out FILE              Table of values, final diagnostics and cluster count.
+                          Format by extension: .csv, .json, otherwise RON. Default: sweep.ron
+
+validate options:
+  --rules FILE            Rules file to check (required)
+  --snapshot FILE         Also check that the snapshot only uses existing types
+
+evolve options:
+  --out FILE              Where to save the best rules. Default: best_rules.ron
+  --fitness NAME          clusters | stability | entropy. Default: clusters
+  --target-clusters N     Cluster count the 'clusters' fitness aims for. Default: 5
+  --link-distance D       Distance linking particles into a cluster. Default: 30
+  --population N          Candidates per generation. Default: 16
+  --generations N         Default: 20
+  --elite N               Best candidates kept unchanged. Default: 2
+  --steps N               Simulation steps per candidate. Default: 600
+  --mutation A            Mutation amount (0..1). Default: 0.1
+  --threads N             Candidates evaluated in parallel (0 = all cores). Default: 0
+";
+
+/// `--key value` options of a subcommand.
+struct Options {
+    values: HashMap<String, String>,
+}
+
+impl Options {
+    /// Parses `--key value` pairs, rejecting keys not in `allowed`.
+    fn parse(args: &[String], allowed: &[&str]) -> Result<Self, String> {
+        let mut values = HashMap::new();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let key = arg.strip_prefix("--").ok_or_else(|| format!("unexpected argument '{}'", arg))?;
+            if !allowed.contains(&key) {
+                return Err(format!("unknown option '--{}'", key));
+            }
+            let value = iter.next().ok_or_else(|| format!("option '--{}' needs a value", key))?;
+            values.insert(key.to_string(), value.clone());
+        }
+        Ok(Self { values })
+    }
+
+    fn get<T: FromStr>(&self, key: &str, default: T) -> Result<T, String> {
+        match self.values.get(key) {
+            Some(value) => value.parse().map_err(|_| format!("invalid value '{}' for '--{}'", value, key)),
+            None => Ok(default),
+        }
+    }
+
+    fn path(&self, key: &str) -> Option<PathBuf> {
+        self.values.get(key).map(PathBuf::from)
+    }
+}
+
+/// Runs a CLI subcommand and returns the process exit code.
+pub fn run(args: &[String]) -> i32 {
+    let result = match args[0].as_str() {
+        "run" => run_command(&args[1..]),
+        "sweep" => sweep_command(&args[1..]),
+        "validate" => validate(&args[1..]),
+        "evolve" => evolve(&args[1..]),
+        "help" | "--help" | "-h" => {
+            print!("{}", USAGE);
+            Ok(())
+        }
+        other => Err(format!("unknown command '{}'\n\n{}", other, USAGE)),
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            1
+        }
+    }
+}
+
+/// Loads `--rules` if given, otherwise the built-in defaults.
+fn load_rules_option(options: &Options) -> Result<share::SharedRuleset, String> {
+    match options.path("rules") {
+        Some(path) => share::load_rules(&path).map_err(|e| format!("{}: {}", path.display(), e)),
+        None => Ok(share::SharedRuleset {
+            rules: AlchemyRules::default(),
+            constants: GlobalConstants::default(),
+        }),
+    }
+}
+
+/// Loads `--snapshot` if given, otherwise scatters `--particles` per type in a `--radius` disc.
+fn initial_snapshot(options: &Options, rules: &AlchemyRules) -> Result<Snapshot, String> {
+    let snapshot = match options.path("snapshot") {
+        Some(path) => Snapshot::load(&path).map_err(|e| format!("{}: {}", path.display(), e))?,
+        None => {
+            let mut rng = rand::rngs::StdRng::seed_from_u64(options.get("seed", 0)?);
+            Snapshot {
+                step: 0,
+                particles: headless::random_particles(rules, options.get("particles", 30)?, options.get("radius", 300.0)?, &mut rng),
+                obstacles: Vec::new(),
+                external_fields: Vec::new(),
+                sources: Vec::new(),
+                sinks: Vec::new(),
+            }
+        }
+    };
+    check_snapshot_types(&snapshot, rules)?;
+    Ok(snapshot)
+}
+
+fn check_snapshot_types(snapshot: &Snapshot, rules: &AlchemyRules) -> Result<(), String> {
+    let num_types = rules.particle_types.len();
+    match snapshot.particles.iter().find(|p| p.type_id >= num_types) {
+        Some(p) => Err(format!("snapshot uses particle type {} but the rules only define {} types", p.type_id, num_types)),
+        None => Ok(()),
+    }
+}
+
+fn write_ron(path: &std::path::Path, value: &impl Serialize) -> Result<(), String> {
+    let text = ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default()).map_err(|e| e.to_string())?;
+    std::fs::write(path, text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn print_diagnostics(d: &SimDiagnostics) {
+    let quarantined = if d.quarantined > 0 { format!("  quarantined {}", d.quarantined) } else { String::new() };
+    let collisions = if d.collisions > 0 { format!("  collisions {}", d.collisions) } else { String::new() };
+    let flow = if d.spawned + d.absorbed + d.expired > 0 {
+        format!("  spawned {}  absorbed {}  expired {}", d.spawned, d.absorbed, d.expired)
+    } else {
+        String::new()
+    };
+    eprintln!(
+        "step {}  particles {}  kinetic energy {:.3}  momentum ({:.3}, {:.3})  injection {:.3}  mean speed {:.3}  max speed {:.3}  temperature {:.3}{}{}{}",
+        d.step, d.particle_count, d.kinetic_energy, d.momentum.x, d.momentum.y, d.momentum_injection.length(),
+        d.mean_speed, d.max_speed, d.temperature, quarantined, collisions, flow,
+    );
+}
+
+/// Output of the `run` command.
+#[derive(Serialize)]
+struct RunResult {
+    steps: usize,
+    diagnostics: SimDiagnostics,
+    history: Vec<SimDiagnostics>,
+    final_state: Snapshot,
+}
+
+fn run_command(args: &[String]) -> Result<(), String> {
+    let options = Options::parse(args, &[
+        "rules", "snapshot", "particles", "radius", "seed", "steps", "out", "sample-every", "script",
+    ])?;
+    let start = load_rules_option(&options)?;
+    let initial = initial_snapshot(&options, &start.rules)?;
+    let steps: usize = options.get("steps", 1000)?;
+    let sample_every: usize = options.get("sample-every", 0)?;
+    let out = options.path("out").unwrap_or_else(|| PathBuf::from("result.ron"));
+
+    let mut sim = HeadlessSim::new(start.rules, start.constants, &initial);
+    if let Some(script) = options.path("script") {
+        sim.load_script(script)?;
+    }
+    let mut history = Vec::new();
+    for i in 1..=steps {
+        sim.step();
+        if let Some(error) = sim.script_error() {
+            return Err(error.to_string());
+        }
+        if sample_every > 0 && i % sample_every == 0 {
+            history.push(sim.diagnostics().clone());
+        }
+    }
+
+    let result = RunResult {
+        steps,
+        diagnostics: sim.diagnostics().clone(),
+        history,
+        final_state: sim.snapshot(),
+    };
+    print_diagnostics(&result.diagnostics);
+    write_ron(&out, &result)?;
+    eprintln!("Result written to {}", out.display());
+    Ok(())
+}
+
+/// Reads `--param{suffix}`, `--from{suffix}`, `--to{suffix}` and `--count{suffix}` into an axis.
+fn sweep_axis(options: &Options, suffix: &str, rules: &AlchemyRules) -> Result<SweepAxis, String> {
+    let required = |key: &str| {
+        let key = format!("{}{}", key, suffix);
+        options.values.get(&key).cloned().ok_or_else(|| format!("missing --{}", key))
+    };
+    let number = |key: &str| -> Result<f32, String> {
+        required(key)?.parse().map_err(|_| format!("invalid --{}{}", key, suffix))
+    };
+
+    let param: SweepParam = required("param")?.parse()?;
+    param.check(rules)?;
+    let values = sweep::linspace(number("from")?, number("to")?, options.get(&format!("count{}", suffix), 5)?)
+        .map_err(|e| format!("--param{}: {}", suffix, e))?;
+    Ok(SweepAxis { param, values })
+}
+
+fn sweep_command(args: &[String]) -> Result<(), String> {
+    let options = Options::parse(args, &[
+        "rules", "snapshot", "particles", "radius", "seed", "param", "from", "to", "count",
+        "param2", "from2", "to2", "count2", "steps", "link-distance", "threads", "out",
+    ])?;
+    let start = load_rules_option(&options)?;
+    let initial = initial_snapshot(&options, &start.rules)?;
+
+    let mut axes = vec![sweep_axis(&options, "", &start.rules)?];
+    if options.values.contains_key("param2") {
+        axes.push(sweep_axis(&options, "2", &start.rules)?);
+    }
+    let config = SweepConfig {
+        steps: options.get("steps", 1000)?,
+        link_distance: options.get("link-distance", 30.0)?,
+        threads: options.get("threads", 0)?,
+    };
+    let out = options.path("out").unwrap_or_else(|| PathBuf::from("sweep.ron"));
+
+    let names: Vec<String> = axes.iter().map(|axis| axis.param.to_string()).collect();
+    let total: usize = axes.iter().map(|axis| axis.values.len()).product();
+    eprintln!("Sweeping {} over {} runs, {} steps each", names.join(" x "), total, config.steps);
+
+    let mut done = 0;
+    let points = sweep::run_sweep(&start.rules, &start.constants, &initial, &axes, &config, |point| {
+        done += 1;
+        let assignments: Vec<String> = names.iter().zip(point.values.iter())
+            .map(|(name, value)| format!("{} = {}", name, value))
+            .collect();
+        eprint!("[{}/{}] {}, {} clusters: ", done, total, assignments.join(", "), point.clusters);
+        print_diagnostics(&point.diagnostics);
+    });
+
+    let table = SweepTable::new(&axes, &points);
+    match out.extension().and_then(|ext| ext.to_str()) {
+        Some("csv") => sweep::write_csv(&out, &axes, &points).map_err(|e| format!("{}: {}", out.display(), e))?,
+        Some("json") => {
+            let text = serde_json::to_string_pretty(&table).map_err(|e| e.to_string())?;
+            std::fs::write(&out, text).map_err(|e| format!("{}: {}", out.display(), e))?;
+        }
+        _ => write_ron(&out, &table)?,
+    }
+    eprintln!("Sweep written to {}", out.display());
+    Ok(())
+}
+
+fn validate(args: &[String]) -> Result<(), String> {
+    let options = Options::parse(args, &["rules", "snapshot"])?;
+    let path = options.path("rules").ok_or("missing --rules")?;
+    let loaded = match share::load_rules(&path) {
+        Ok(loaded) => loaded,
+        Err(share::RonFileError::Invalid(errors)) => {
+            for error in errors.iter() {
+                eprintln!("{}: {}", path.display(), error);
+            }
+            return Err(format!("{}: {} problem(s) found", path.display(), errors.len()));
+        }
+        Err(e) => return Err(format!("{}: {}", path.display(), e)),
+    };
+
+    let rules = &loaded.rules;
+    eprintln!("{}: OK", path.display());
+    eprintln!("  {} particle types, {} interactions", rules.particle_types.len(), rules.interactions.len());
+    for (idx, def) in rules.particle_types.iter().enumerate() {
+        eprintln!(
+            "  [{}] {}: mass {}, emits {}, radius {}, {} curve points",
+            idx, def.name, def.default_mass,
+            def.emits_field.map_or("nothing".to_string(), |f| format!("field {}", f)),
+            def.emission_shape.max_radius, def.emission_shape.points.len(),
+        );
+    }
+
+    if let Some(snapshot_path) = options.path("snapshot") {
+        let snapshot = Snapshot::load(&snapshot_path).map_err(|e| format!("{}: {}", snapshot_path.display(), e))?;
+        check_snapshot_types(&snapshot, rules).map_err(|e| format!("{}: {}", snapshot_path.display(), e))?;
+        eprintln!("{}: OK ({} particles, {} obstacles, {} external fields, {} sources and {} sinks at step {})",
+            snapshot_path.display(), snapshot.particles.len(), snapshot.obstacles.len(), snapshot.external_fields.len(),
+            snapshot.sources.len(), snapshot.sinks.len(), snapshot.step);
+    }
+    Ok(())
+}
+
+fn evolve(args: &[String]) -> Result<(), String> {
+    let options = Options::parse(args, &[
+        "rules", "out", "fitness", "target-clusters", "link-distance", "population",
+        "generations", "elite", "steps", "particles", "radius", "mutation", "seed", "threads",
+    ])?;
+
+    let start = load_rules_option(&options)?;
+    let out = options.path("out").unwrap_or_else(|| PathBuf::from("best_rules.ron"));
+
+    let fitness_name: String = options.get("fitness", "clusters".to_string())?;
+    let fitness = evolution::fitness_by_name(
+        &fitness_name,
+        options.get("target-clusters", 5)?,
+        options.get("link-distance", 30.0)?,
+    ).ok_or_else(|| format!("unknown fitness '{}' (expected one of: {})", fitness_name, FITNESS_NAMES.join(", ")))?;
+
+    let defaults = EvolutionConfig::default();
+    let mut config = EvolutionConfig {
+        population: options.get("population", defaults.population)?,
+        generations: options.get("generations", defaults.generations)?.max(1),
+        elite: options.get("elite", defaults.elite)?,
+        steps: options.get("steps", defaults.steps)?,
+        particles_per_type: options.get("particles", defaults.particles_per_type)?,
+        spawn_radius: options.get("radius", defaults.spawn_radius)?,
+        seed: options.get("seed", defaults.seed)?,
+        mutation: defaults.mutation,
+        threads: options.get("threads", defaults.threads)?,
+    };
+    config.mutation.mutation_amount = options.get("mutation", config.mutation.mutation_amount)?;
+
+    eprintln!(
+        "Evolving {} types with fitness '{}': {} generations x {} candidates x {} steps",
+        start.rules.particle_types.len(), fitness.name(), config.generations, config.population, config.steps,
+    );
+
+    let (best, score) = evolution::evolve(&start.rules, &start.constants, &config, fitness.as_ref(), |report| {
+        eprintln!(
+            "generation {:>3}/{}  best {:.4}  mean {:.4}  best so far {:.4}",
+            report.generation + 1, config.generations, report.best_score, report.mean_score, report.best_so_far,
+        );
+    });
+
+    share::save_rules(&out, &best, &start.constants).map_err(|e| format!("{}: {}", out.display(), e))?;
+    eprintln!("Best score {:.4}, rules saved to {}", score, out.display());
+    Ok(())
+}
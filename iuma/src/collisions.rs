@@ -0,0 +1,212 @@
+use bevy::prelude::*;
+use crate::components::*;
+use crate::resources::*;
+use crate::diagnostics::SimDiagnostics;
+use crate::spatial::{Boundary, SpatialGrid};
+use crate::obstacles::{move_with_collisions, Obstacle};
+
+/// What one collision pass changed, per particle index.
+pub struct CollisionOutcome {
+    // Total collision impulse received (zero if the particle was not hit)
+    pub impulses: Vec<Vec2>,
+    // Whether the overlap correction moved the particle
+    pub moved: Vec<bool>,
+    pub collisions: usize,
+}
+
+/// Resolves the overlapping pairs of hard cores of radius `radii` in flat particle arrays.
+///
+/// Overlapping pairs are pushed apart along the line between their centres (split by mass),
+/// swept against `obstacles` so the push never carries a particle through a wall, and approaching
+/// pairs exchange the impulse of a collision with `restitution`. Pairs are resolved in a fixed order.
+pub fn resolve_collisions(
+    positions: &mut [Vec2],
+    velocities: &[Vec2],
+    masses: &[f32],
+    radii: &[f32],
+    restitution: f32,
+    obstacles: &[&Obstacle],
+) -> CollisionOutcome {
+    let count = positions.len();
+    let mut outcome = CollisionOutcome { impulses: vec![Vec2::ZERO; count], moved: vec![false; count], collisions: 0 };
+    let max_radius = radii.iter().copied().fold(0.0, f32::max);
+    if max_radius <= 0.0 { return outcome; }
+
+    // 1. Candidate pairs: no two cores can touch farther apart than twice the largest radius
+    let reach = 2.0 * max_radius;
+    let grid = SpatialGrid::new(positions, reach, Boundary::Open);
+    let mut pairs = Vec::new();
+    grid.for_each_pair_within(positions, reach, |i, j, _| {
+        if positions[i].distance(positions[j]) < radii[i] + radii[j] {
+            pairs.push((i, j));
+        }
+    });
+    pairs.sort_unstable();
+
+    // 2. Resolve
+    let mut velocities = velocities.to_vec();
+    let restitution = restitution.clamp(0.0, 1.0);
+    for (i, j) in pairs {
+        let delta = positions[j] - positions[i];
+        let distance = delta.length();
+        let contact = radii[i] + radii[j];
+        // Earlier corrections may already have separated the pair
+        if distance >= contact { continue; }
+        // Coincident particles are separated along an arbitrary axis
+        let normal = if distance > f32::EPSILON { delta / distance } else { Vec2::X };
+
+        let inv_i = 1.0 / masses[i];
+        let inv_j = 1.0 / masses[j];
+        let inv_sum = inv_i + inv_j;
+
+        // Lighter particles move further. A wall in the way stops the push (the overlap then
+        // persists until the pair moves apart) rather than letting it through.
+        let overlap = contact - distance;
+        positions[i] = move_with_collisions(positions[i], -normal * overlap * inv_i / inv_sum, 1.0, obstacles).0;
+        positions[j] = move_with_collisions(positions[j], normal * overlap * inv_j / inv_sum, 1.0, obstacles).0;
+        outcome.moved[i] = true;
+        outcome.moved[j] = true;
+
+        let approach = (velocities[j] - velocities[i]).dot(normal);
+        if approach < 0.0 {
+            let impulse = normal * (-(1.0 + restitution) * approach / inv_sum);
+            velocities[i] -= impulse * inv_i;
+            velocities[j] += impulse * inv_j;
+            outcome.impulses[i] -= impulse;
+            outcome.impulses[j] += impulse;
+            outcome.collisions += 1;
+        }
+    }
+    outcome
+}
+
+/// Resolves overlaps between hard cores after the integration step (see `resolve_collisions`).
+///
+/// Collision impulses go into the particles' `Momentum` like any other impulse, so the speed
+/// limit applies as usual. Particles that were neither moved nor hit are left untouched.
+/// Does nothing while every type is a point particle.
+#[allow(clippy::type_complexity)]
+pub fn collision_system(
+    mut query: Query<(&mut Transform, &mut Velocity, &mut Momentum, &Mass, &ParticleTypeID), (With<Particle>, Without<Quarantined>)>,
+    obstacle_query: Query<&Obstacle>,
+    alchemy: Res<AlchemyRules>,
+    global_consts: Res<GlobalConstants>,
+    mut diagnostics: ResMut<SimDiagnostics>,
+) {
+    let radius_of = |type_id: usize| alchemy.particle_types.get(type_id).map_or(0.0, |def| def.hard_core_radius);
+    if alchemy.particle_types.iter().all(|def| def.hard_core_radius <= 0.0) {
+        diagnostics.collisions = 0;
+        return;
+    }
+
+    // Gather into flat arrays
+    let mut particles: Vec<_> = query.iter_mut().collect();
+    let mut positions: Vec<Vec2> = particles.iter().map(|(t, ..)| t.translation.truncate()).collect();
+    let velocities: Vec<Vec2> = particles.iter().map(|(_, v, ..)| v.0).collect();
+    let masses: Vec<f32> = particles.iter().map(|(_, _, _, m, _)| m.0).collect();
+    let radii: Vec<f32> = particles.iter().map(|(.., type_id)| radius_of(type_id.0)).collect();
+    let obstacles: Vec<&Obstacle> = obstacle_query.iter().collect();
+
+    let outcome = resolve_collisions(
+        &mut positions, &velocities, &masses, &radii, global_consts.collision_restitution, &obstacles,
+    );
+
+    // Write back only what changed
+    let c = global_consts.light_speed;
+    for (k, (transform, velocity, momentum, ..)) in particles.iter_mut().enumerate() {
+        if outcome.moved[k] {
+            transform.translation.x = positions[k].x;
+            transform.translation.y = positions[k].y;
+        }
+        if outcome.impulses[k] != Vec2::ZERO {
+            velocity.0 = global_consts.speed_limit.apply_impulse(&mut momentum.0, outcome.impulses[k], masses[k], c);
+        }
+    }
+    diagnostics.collisions = outcome.collisions;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+    use crate::obstacles::ObstacleShape;
+
+    #[test]
+    fn overlapping_cores_are_pushed_to_contact() {
+        let mut positions = vec![Vec2::new(-1.0, 0.0), Vec2::new(1.0, 0.0)];
+        let outcome = resolve_collisions(&mut positions, &[Vec2::ZERO; 2], &[1.0, 1.0], &[5.0, 5.0], 1.0, &[]);
+        assert!((positions[0] - Vec2::new(-5.0, 0.0)).length() < 1e-4, "{:?}", positions);
+        assert!((positions[1] - Vec2::new(5.0, 0.0)).length() < 1e-4, "{:?}", positions);
+        assert_eq!(outcome.moved, vec![true, true]);
+        // Resting cores only separate
+        assert_eq!(outcome.collisions, 0);
+        assert_eq!(outcome.impulses, vec![Vec2::ZERO; 2]);
+    }
+
+    #[test]
+    fn collision_impulses_are_equal_and_opposite() {
+        let mut positions = vec![Vec2::new(-4.0, 0.0), Vec2::new(4.0, 0.0)];
+        let velocities = [Vec2::new(10.0, 0.0), Vec2::new(-10.0, 0.0)];
+        let masses = [1.0, 3.0];
+        let outcome = resolve_collisions(&mut positions, &velocities, &masses, &[5.0, 5.0], 1.0, &[]);
+        assert_eq!(outcome.collisions, 1);
+        assert_eq!(outcome.impulses[0] + outcome.impulses[1], Vec2::ZERO);
+
+        // Elastic: kinetic energy is kept
+        let after: Vec<Vec2> = (0..2).map(|k| velocities[k] + outcome.impulses[k] / masses[k]).collect();
+        let energy = |v: &[Vec2]| 0.5 * (masses[0] * v[0].length_squared() + masses[1] * v[1].length_squared());
+        assert!((energy(&after) - energy(&velocities)).abs() < 1e-3);
+        assert!(after[0].x < 0.0 && after[1].x > after[0].x, "the pair must separate: {:?}", after);
+    }
+
+    #[test]
+    fn overlap_correction_does_not_push_through_walls() {
+        let wall = Obstacle {
+            shape: ObstacleShape::Segment { a: Vec2::new(0.0, -20.0), b: Vec2::new(0.0, 20.0) },
+            restitution: 1.0,
+            emits_field: None,
+        };
+        // A light core just right of the wall, overlapped from the right by a heavy one
+        let mut positions = vec![Vec2::new(0.3, 0.0), Vec2::new(1.0, 0.0)];
+        resolve_collisions(&mut positions, &[Vec2::ZERO; 2], &[1.0, 100.0], &[5.0, 5.0], 1.0, &[&wall]);
+        assert!(positions[0].x > 0.0, "pushed through the wall to {:?}", positions[0]);
+
+        // Without the wall it would have crossed
+        let mut free = vec![Vec2::new(0.3, 0.0), Vec2::new(1.0, 0.0)];
+        resolve_collisions(&mut free, &[Vec2::ZERO; 2], &[1.0, 100.0], &[5.0, 5.0], 1.0, &[]);
+        assert!(free[0].x < 0.0);
+    }
+
+    #[test]
+    fn particles_that_do_not_collide_keep_their_momentum() {
+        for speed_limit in [SpeedLimit::Relativistic, SpeedLimit::Tanh] {
+            let mut world = World::new();
+            let mut alchemy = AlchemyRules::default();
+            alchemy.particle_types[0].hard_core_radius = 5.0;
+            let constants = GlobalConstants { speed_limit, ..default() };
+            let (m, c) = (1.0, constants.light_speed);
+
+            // Ultra-relativistic and far from everything
+            let p = Vec2::new(100.0 * m * c, 0.0);
+            let v = speed_limit.velocity(p, m, c);
+            let mut fast = ParticleBundle::new(0, Vec2::new(1000.0, 0.0), v, m);
+            fast.momentum = Momentum(p);
+            let fast = world.spawn(fast).id();
+            // A colliding pair elsewhere, so the pass runs
+            for x in [-1.0, 1.0] {
+                let mut bundle = ParticleBundle::new(0, Vec2::new(x, 0.0), Vec2::new(-x * 10.0, 0.0), m);
+                bundle.momentum = Momentum(bundle.velocity.0 * m);
+                world.spawn(bundle);
+            }
+            world.insert_resource(alchemy);
+            world.insert_resource(constants);
+            world.init_resource::<SimDiagnostics>();
+
+            world.run_system_once(collision_system);
+
+            assert_eq!(world.resource::<SimDiagnostics>().collisions, 1);
+            assert_eq!(world.get::<Momentum>(fast).unwrap().0, p, "{:?}", speed_limit);
+            assert_eq!(world.get::<Velocity>(fast).unwrap().0, v, "{:?}", speed_limit);
+        }
+    }
+}
@@ -6,6 +6,11 @@ pub struct Velocity(pub Vec2);
 #[derive(Component, Debug, Clone, Copy)]
 pub struct Mass(pub f32);
 
+/// Momentum under the active `SpeedLimit` model. The state the forces integrate; `Velocity` is derived
+/// from it. Systems that set the velocity directly (collisions, thermostats) update it to match.
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct Momentum(pub Vec2);
+
 #[derive(Component, Debug, Clone, Copy)]
 pub struct ParticleTypeID(pub usize);
 
@@ -13,6 +18,72 @@ pub struct ParticleTypeID(pub usize);
 #[derive(Component)]
 pub struct Particle;
 
+/// The simulation components of a particle. Spawned as-is by headless runs;
+/// the windowed app attaches visuals (sprite, field halo) in `render::attach_particle_visuals`.
+#[derive(Bundle)]
+pub struct ParticleBundle {
+    pub transform: TransformBundle,
+    pub particle: Particle,
+    pub type_id: ParticleTypeID,
+    pub mass: Mass,
+    pub velocity: Velocity,
+    pub momentum: Momentum,
+    pub net_force: NetForce,
+    pub trail: Trail,
+    pub history: PositionHistory,
+    pub age: Age,
+}
+
+impl ParticleBundle {
+    pub fn new(type_id: usize, position: Vec2, velocity: Vec2, mass: f32) -> Self {
+        Self {
+            transform: TransformBundle::from_transform(Transform::from_translation(position.extend(0.0))),
+            particle: Particle,
+            type_id: ParticleTypeID(type_id),
+            mass: Mass(mass),
+            velocity: Velocity(velocity),
+            // Derived from the velocity by `physics::momentum_sync_system` once spawned, unless set to nonzero
+            momentum: Momentum::default(),
+            net_force: NetForce::default(),
+            trail: Trail::default(),
+            history: PositionHistory::default(),
+            age: Age::default(),
+        }
+    }
+}
+
 // Define a wrapper for Field Types to avoid confusion with raw integers
+#[allow(dead_code)] // Not yet threaded through AlchemyRules, which still keys fields by usize
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct FieldTypeID(pub usize);
+
+/// Net force acting on a particle during the last interaction step.
+/// Written by the physics, read by visualization and diagnostics.
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct NetForce(pub Vec2);
+
+/// Set by the physics on particles it cannot simulate (unknown type, bad mass, non-finite state).
+/// Quarantined particles are frozen and left out of forces, analysis and diagnostics.
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct Quarantined;
+
+/// Simulated seconds a particle has existed, for per-type lifetimes. Advanced by `sources::source_sink_system`.
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct Age(pub f32);
+
+/// Index of the cluster a particle currently belongs to (see `analysis`)
+#[derive(Component, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ClusterId(pub usize);
+
+/// Recent positions of a particle, newest last. Filled by the integration step.
+#[derive(Component, Debug, Clone, Default)]
+pub struct Trail {
+    pub points: std::collections::VecDeque<Vec2>,
+}
+
+/// Past positions of a particle for retarded fields, newest first: `positions[k]` is where it was
+/// `k + 1` steps ago. Only filled while `GlobalConstants::retarded_fields` is on.
+#[derive(Component, Debug, Clone, Default)]
+pub struct PositionHistory {
+    pub positions: std::collections::VecDeque<Vec2>,
+}
@@ -0,0 +1,74 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::components::*;
+use crate::resources::*;
+
+/// Whole-universe measurements, refreshed after every physics step.
+#[derive(Resource, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SimDiagnostics {
+    // Physics steps taken since start
+    pub step: u64,
+    pub particle_count: usize,
+    // Under the active speed limit model (relativistic (γ-1)mc² when relativistic)
+    pub kinetic_energy: f32,
+    // Total momentum (sum of p, which is m * v when clamping)
+    pub momentum: Vec2,
+    pub mean_speed: f32,
+    pub max_speed: f32,
+    // Mean Newtonian kinetic energy ½mv² per particle, as controlled by the thermostats
+    #[serde(default)]
+    pub temperature: f32,
+    // Particles frozen by the physics for invalid data (not included above)
+    #[serde(default)]
+    pub quarantined: usize,
+    // Hard-core collisions resolved in the last step (written by `collisions::collision_system`)
+    #[serde(default)]
+    pub collisions: usize,
+    // Net impulse the pair forces added in the last step (zero when they obey action = reaction)
+    #[serde(default)]
+    pub momentum_injection: Vec2,
+    // Totals since start of particles created by sources, removed by sinks and despawned at the end
+    // of their lifetime (written by `sources::source_sink_system`)
+    #[serde(default)]
+    pub spawned: u64,
+    #[serde(default)]
+    pub absorbed: u64,
+    #[serde(default)]
+    pub expired: u64,
+}
+
+/// Counts the step and measures energy, momentum and speeds.
+#[allow(clippy::type_complexity)]
+pub fn diagnostics_system(
+    mut diagnostics: ResMut<SimDiagnostics>,
+    query: Query<(&Velocity, &Momentum, &Mass), (With<Particle>, Without<Quarantined>)>,
+    global_consts: Res<GlobalConstants>,
+    quarantined: Query<(), With<Quarantined>>,
+) {
+    let (model, c) = (global_consts.speed_limit, global_consts.light_speed);
+    let mut count = 0;
+    let mut kinetic_energy = 0.0;
+    let mut momentum = Vec2::ZERO;
+    let mut speed_sum = 0.0;
+    let mut max_speed = 0.0_f32;
+    let mut newtonian_energy = 0.0;
+
+    for (velocity, particle_momentum, mass) in query.iter() {
+        let speed = velocity.0.length();
+        count += 1;
+        kinetic_energy += model.kinetic_energy(particle_momentum.0, mass.0, c);
+        momentum += particle_momentum.0;
+        speed_sum += speed;
+        max_speed = max_speed.max(speed);
+        newtonian_energy += 0.5 * mass.0 * speed * speed;
+    }
+
+    diagnostics.step += 1;
+    diagnostics.particle_count = count;
+    diagnostics.kinetic_energy = kinetic_energy;
+    diagnostics.momentum = momentum;
+    diagnostics.mean_speed = if count > 0 { speed_sum / count as f32 } else { 0.0 };
+    diagnostics.max_speed = max_speed;
+    diagnostics.temperature = if count > 0 { newtonian_energy / count as f32 } else { 0.0 };
+    diagnostics.quarantined = quarantined.iter().count();
+}
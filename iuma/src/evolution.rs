@@ -0,0 +1,319 @@
+use bevy::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::resources::*;
+use crate::analysis;
+use crate::generator::{self, RandomizerSettings};
+use crate::headless::{self, HeadlessSim};
+use crate::snapshot::{ParticleState, Snapshot};
+
+/// What a candidate run leaves behind for the fitness functions to score.
+pub struct RunRecord {
+    pub final_particles: Vec<ParticleState>,
+    // Total kinetic energy after every step
+    pub kinetic_energy: Vec<f32>,
+    // Radius of the disc the particles started in
+    pub spawn_radius: f32,
+}
+
+/// A scoring rule for evolved rulesets. Higher is better.
+pub trait Fitness: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn score(&self, run: &RunRecord) -> f32;
+}
+
+/// Rewards ending with `target` clusters of at least two particles.
+pub struct ClusterCountFitness {
+    pub target: usize,
+    pub link_distance: f32,
+}
+
+impl Fitness for ClusterCountFitness {
+    fn name(&self) -> &'static str { "clusters" }
+
+    fn score(&self, run: &RunRecord) -> f32 {
+        let positions: Vec<Vec2> = run.final_particles.iter().map(|p| p.position).collect();
+        let clusters = analysis::count_clusters(&positions, self.link_distance, 2);
+
+        1.0 / (1.0 + clusters.abs_diff(self.target) as f32)
+    }
+}
+
+/// Rewards a kinetic energy that stays steady over the second half of the run.
+/// A universe that has come to a complete standstill scores zero.
+pub struct EnergyStabilityFitness;
+
+impl Fitness for EnergyStabilityFitness {
+    fn name(&self) -> &'static str { "stability" }
+
+    fn score(&self, run: &RunRecord) -> f32 {
+        let tail = &run.kinetic_energy[run.kinetic_energy.len() / 2..];
+        if tail.is_empty() { return 0.0; }
+
+        let mean = tail.iter().sum::<f32>() / tail.len() as f32;
+        if mean <= f32::EPSILON || !mean.is_finite() { return 0.0; }
+
+        let variance = tail.iter().map(|e| (e - mean).powi(2)).sum::<f32>() / tail.len() as f32;
+        let coefficient_of_variation = variance.sqrt() / mean;
+        1.0 / (1.0 + coefficient_of_variation)
+    }
+}
+
+/// Rewards spatial structure: one minus the normalized Shannon entropy of particle
+/// positions binned on a grid over twice the spawn disc. Clumped = high, uniform gas = low.
+pub struct SpatialEntropyFitness {
+    pub bins: usize,
+}
+
+impl Fitness for SpatialEntropyFitness {
+    fn name(&self) -> &'static str { "entropy" }
+
+    fn score(&self, run: &RunRecord) -> f32 {
+        let bins = self.bins.max(2);
+        let count = run.final_particles.len();
+        if count == 0 { return 0.0; }
+
+        let extent = run.spawn_radius * 2.0;
+        let mut histogram = vec![0usize; bins * bins];
+        for p in run.final_particles.iter() {
+            // Particles outside the region land in the edge bins
+            let cell = ((p.position + extent) / (2.0 * extent) * bins as f32)
+                .clamp(Vec2::ZERO, Vec2::splat(bins as f32 - 1.0));
+            histogram[cell.y as usize * bins + cell.x as usize] += 1;
+        }
+
+        let entropy: f32 = histogram.iter()
+            .filter(|&&n| n > 0)
+            .map(|&n| {
+                let p = n as f32 / count as f32;
+                -p * p.ln()
+            })
+            .sum();
+        let max_entropy = ((bins * bins).min(count) as f32).ln();
+        if max_entropy <= 0.0 { return 1.0; }
+
+        1.0 - entropy / max_entropy
+    }
+}
+
+/// Builds a fitness function from its CLI name.
+pub fn fitness_by_name(name: &str, target_clusters: usize, link_distance: f32) -> Option<Box<dyn Fitness>> {
+    match name {
+        "clusters" => Some(Box::new(ClusterCountFitness { target: target_clusters, link_distance })),
+        "stability" => Some(Box::new(EnergyStabilityFitness)),
+        "entropy" => Some(Box::new(SpatialEntropyFitness { bins: 16 })),
+        _ => None,
+    }
+}
+
+pub const FITNESS_NAMES: [&str; 3] = ["clusters", "stability", "entropy"];
+
+#[derive(Debug, Clone)]
+pub struct EvolutionConfig {
+    pub population: usize,
+    pub generations: usize,
+    // Best candidates carried over unchanged into the next generation
+    pub elite: usize,
+    // Simulation steps per candidate
+    pub steps: usize,
+    pub particles_per_type: usize,
+    pub spawn_radius: f32,
+    pub seed: u64,
+    // Mutation amount, weight range and symmetry are taken from here
+    pub mutation: RandomizerSettings,
+    // Candidates evaluated in parallel; 0 = one per available core
+    pub threads: usize,
+}
+
+impl Default for EvolutionConfig {
+    fn default() -> Self {
+        Self {
+            population: 16,
+            generations: 20,
+            elite: 2,
+            steps: 600,
+            particles_per_type: 30,
+            spawn_radius: 300.0,
+            seed: 0,
+            mutation: RandomizerSettings::default(),
+            threads: 0,
+        }
+    }
+}
+
+/// Progress report after each generation.
+pub struct GenerationReport {
+    pub generation: usize,
+    pub best_score: f32,
+    pub mean_score: f32,
+    pub best_so_far: f32,
+}
+
+/// Runs one candidate from `initial` for `steps` and records what the fitness needs.
+pub fn evaluate(
+    rules: &AlchemyRules,
+    constants: &GlobalConstants,
+    initial: &Snapshot,
+    steps: usize,
+    spawn_radius: f32,
+) -> RunRecord {
+    let mut sim = HeadlessSim::new(rules.clone(), constants.clone(), initial);
+    let mut kinetic_energy = Vec::with_capacity(steps);
+    for _ in 0..steps {
+        sim.step();
+        kinetic_energy.push(sim.diagnostics().kinetic_energy);
+    }
+
+    RunRecord {
+        final_particles: sim.particles(),
+        kinetic_energy,
+        spawn_radius,
+    }
+}
+
+/// Evolves a population of mutated copies of `base` and returns the best rules found with their score.
+/// Every candidate starts from the same seeded particle layout so scores are comparable.
+/// Candidates of a generation are evaluated in parallel (`config.threads`).
+pub fn evolve(
+    base: &AlchemyRules,
+    constants: &GlobalConstants,
+    config: &EvolutionConfig,
+    fitness: &dyn Fitness,
+    mut on_generation: impl FnMut(&GenerationReport),
+) -> (AlchemyRules, f32) {
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let initial = Snapshot {
+        step: 0,
+        particles: headless::random_particles(base, config.particles_per_type, config.spawn_radius, &mut rng),
+        obstacles: Vec::new(),
+        external_fields: Vec::new(),
+        sources: Vec::new(),
+        sinks: Vec::new(),
+    };
+
+    let population_size = config.population.max(2);
+    let mut population: Vec<AlchemyRules> = std::iter::once(base.clone())
+        .chain((1..population_size).map(|_| generator::mutate_with(base, &config.mutation, &mut rng)))
+        .collect();
+
+    let mut best = (base.clone(), f32::NEG_INFINITY);
+
+    for generation in 0..config.generations {
+        let scores = headless::run_parallel(&population, config.threads, |rules| {
+            let run = evaluate(rules, constants, &initial, config.steps, config.spawn_radius);
+            let score = fitness.score(&run);
+            // NaN (e.g. from exploding energies) ranks last
+            if score.is_nan() { f32::NEG_INFINITY } else { score }
+        }, |_| {});
+        let mut scored: Vec<(f32, AlchemyRules)> = scores.into_iter().zip(population).collect();
+        scored.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+
+        if scored[0].0 > best.1 {
+            best = (scored[0].1.clone(), scored[0].0);
+        }
+        let finite: Vec<f32> = scored.iter().map(|(s, _)| *s).filter(|s| s.is_finite()).collect();
+        on_generation(&GenerationReport {
+            generation,
+            best_score: scored[0].0,
+            mean_score: finite.iter().sum::<f32>() / finite.len().max(1) as f32,
+            best_so_far: best.1,
+        });
+
+        // Elites survive unchanged; the rest are mutated children of the better half
+        let elite = config.elite.min(population_size);
+        let parents = (population_size / 2).max(1);
+        population = scored.iter().take(elite).map(|(_, rules)| rules.clone()).collect();
+        while population.len() < population_size {
+            let parent = &scored[rng.gen_range(0..parents)].1;
+            population.push(generator::mutate_with(parent, &config.mutation, &mut rng));
+        }
+    }
+
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_with(positions: &[Vec2], kinetic_energy: Vec<f32>) -> RunRecord {
+        RunRecord {
+            final_particles: positions.iter()
+                .map(|&position| ParticleState { type_id: 0, position, velocity: Vec2::ZERO, mass: 1.0, momentum: None, age: 0.0 })
+                .collect(),
+            kinetic_energy,
+            spawn_radius: 100.0,
+        }
+    }
+
+    #[test]
+    fn cluster_fitness_peaks_at_the_target_count() {
+        // Three pairs and a loner
+        let positions: Vec<Vec2> = [0.0, 5.0, 100.0, 105.0, 200.0, 205.0, 400.0]
+            .iter().map(|&x| Vec2::new(x, 0.0)).collect();
+        let run = run_with(&positions, Vec::new());
+        let fitness = |target| ClusterCountFitness { target, link_distance: 10.0 }.score(&run);
+        assert_eq!(fitness(3), 1.0);
+        assert_eq!(fitness(5), 1.0 / 3.0);
+        assert_eq!(fitness(2), 0.5);
+    }
+
+    #[test]
+    fn stability_fitness_rewards_steady_energy() {
+        let score = |energy: Vec<f32>| EnergyStabilityFitness.score(&run_with(&[], energy));
+        assert_eq!(score(vec![50.0; 10]), 1.0);
+        // Only the second half counts
+        assert_eq!(score(vec![0.0, 999.0, 7.0, 50.0, 50.0, 50.0, 50.0, 50.0]), 1.0);
+        let wobbling = score((0..12).map(|i| if i % 2 == 0 { 40.0 } else { 60.0 }).collect());
+        assert!((wobbling - 1.0 / 1.2).abs() < 1e-5, "{}", wobbling);
+        // A standstill, an empty run and an explosion score nothing
+        assert_eq!(score(vec![0.0; 10]), 0.0);
+        assert_eq!(score(Vec::new()), 0.0);
+        assert_eq!(score(vec![f32::INFINITY; 4]), 0.0);
+    }
+
+    #[test]
+    fn entropy_fitness_separates_clumps_from_a_spread() {
+        let fitness = SpatialEntropyFitness { bins: 16 };
+        let clumped = run_with(&[Vec2::new(10.0, -20.0); 256], Vec::new());
+        assert_eq!(fitness.score(&clumped), 1.0);
+
+        // One particle at the centre of every bin over twice the spawn disc
+        let extent = 200.0;
+        let cell = 2.0 * extent / 16.0;
+        let spread: Vec<Vec2> = (0..16)
+            .flat_map(|i| (0..16).map(move |j| Vec2::new(i as f32 + 0.5, j as f32 + 0.5) * cell - Vec2::splat(extent)))
+            .collect();
+        assert!(fitness.score(&run_with(&spread, Vec::new())).abs() < 1e-5);
+    }
+
+    #[test]
+    fn evolution_is_repeatable_and_never_loses_its_best() {
+        let config = EvolutionConfig {
+            population: 6,
+            generations: 4,
+            elite: 1,
+            steps: 20,
+            particles_per_type: 5,
+            seed: 11,
+            threads: 2,
+            ..default()
+        };
+        let fitness = EnergyStabilityFitness;
+        let base = AlchemyRules::default();
+        let constants = GlobalConstants::default();
+
+        let mut best_so_far = Vec::new();
+        let (best, score) = evolve(&base, &constants, &config, &fitness, |report| {
+            assert!(report.best_so_far >= report.best_score);
+            best_so_far.push(report.best_so_far);
+        });
+        assert_eq!(best_so_far.len(), config.generations);
+        assert!(best_so_far.windows(2).all(|w| w[1] >= w[0]), "{:?}", best_so_far);
+        assert_eq!(score, *best_so_far.last().unwrap());
+
+        let (again, again_score) = evolve(&base, &constants, &EvolutionConfig { threads: 1, ..config.clone() }, &fitness, |_| {});
+        assert!(again == best);
+        assert_eq!(again_score, score);
+    }
+}
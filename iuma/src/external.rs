@@ -0,0 +1,210 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// The force pattern of an external field, per unit weight.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ExternalShape {
+    // The same force everywhere (gravity, a push in one direction)
+    Uniform { force: Vec2 },
+    // Constant pull towards `center` (push away when negative)
+    Central { center: Vec2, strength: f32 },
+    // Constant push around `center`, counter-clockwise when positive
+    Vortex { center: Vec2, strength: f32 },
+    // Push along x growing with the height above `center`: (rate · (y - center.y), 0)
+    Shear { center: Vec2, rate: f32 },
+}
+
+/// Where an external field acts.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Region {
+    Everywhere,
+    Rect { min: Vec2, max: Vec2 },
+    Circle { center: Vec2, radius: f32 },
+}
+
+/// A force that does not come from particles. Evaluated with the field forces; saved in snapshots.
+#[derive(Component, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExternalField {
+    pub shape: ExternalShape,
+    pub region: Region,
+    // Multiplier per particle type, indexed by ParticleTypeID (missing entries count as 1)
+    pub weights: Vec<f32>,
+}
+
+impl ExternalShape {
+    pub const NAMES: [&'static str; 4] = ["Uniform", "Central", "Vortex", "Shear"];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExternalShape::Uniform { .. } => Self::NAMES[0],
+            ExternalShape::Central { .. } => Self::NAMES[1],
+            ExternalShape::Vortex { .. } => Self::NAMES[2],
+            ExternalShape::Shear { .. } => Self::NAMES[3],
+        }
+    }
+
+    /// A default field of the named kind, for the editor.
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "Central" => ExternalShape::Central { center: Vec2::ZERO, strength: 100.0 },
+            "Vortex" => ExternalShape::Vortex { center: Vec2::ZERO, strength: 100.0 },
+            "Shear" => ExternalShape::Shear { center: Vec2::ZERO, rate: 1.0 },
+            _ => ExternalShape::Uniform { force: Vec2::new(0.0, -100.0) },
+        }
+    }
+
+    /// Force per unit weight at `pos`.
+    pub fn force_at(&self, pos: Vec2) -> Vec2 {
+        match *self {
+            ExternalShape::Uniform { force } => force,
+            ExternalShape::Central { center, strength } => (center - pos).normalize_or_zero() * strength,
+            ExternalShape::Vortex { center, strength } => (pos - center).perp().normalize_or_zero() * strength,
+            ExternalShape::Shear { center, rate } => Vec2::new(rate * (pos.y - center.y), 0.0),
+        }
+    }
+}
+
+impl Region {
+    pub fn contains(&self, pos: Vec2) -> bool {
+        match *self {
+            Region::Everywhere => true,
+            Region::Rect { min, max } => pos.cmpge(min).all() && pos.cmple(max).all(),
+            Region::Circle { center, radius } => pos.distance_squared(center) <= radius * radius,
+        }
+    }
+}
+
+impl ExternalField {
+    pub fn weight(&self, type_id: usize) -> f32 {
+        self.weights.get(type_id).copied().unwrap_or(1.0)
+    }
+
+    /// Force on a particle of type `type_id` at `pos`.
+    pub fn force_at(&self, pos: Vec2, type_id: usize) -> Vec2 {
+        let weight = self.weight(type_id);
+        if weight == 0.0 || !self.region.contains(pos) { return Vec2::ZERO; }
+        self.shape.force_at(pos) * weight
+    }
+}
+
+// World units of arrow per unit force, and the arrow grid in screen pixels
+const ARROW_SCALE: f32 = 0.3;
+const ARROW_SPACING_PX: f32 = 120.0;
+
+/// Outlines each field's region and draws a sparse grid of arrows showing its direction.
+pub fn draw_external_fields_system(
+    mut gizmos: Gizmos,
+    fields: Query<&ExternalField>,
+    camera_query: Query<(&Transform, &OrthographicProjection), With<Camera>>,
+) {
+    let Ok((camera_transform, projection)) = camera_query.get_single() else { return; };
+    let view_min = camera_transform.translation.truncate() + projection.area.min;
+    let view_max = camera_transform.translation.truncate() + projection.area.max;
+    let spacing = ARROW_SPACING_PX * projection.scale;
+    let color = Color::rgba(0.7, 0.5, 1.0, 0.6);
+
+    for field in fields.iter() {
+        match field.region {
+            Region::Everywhere => {}
+            Region::Rect { min, max } => {
+                gizmos.rect_2d((min + max) / 2.0, 0.0, max - min, color);
+            }
+            Region::Circle { center, radius } => {
+                gizmos.circle_2d(center, radius, color).segments(64);
+            }
+        }
+
+        // Arrows on a grid anchored to the world, so they do not swim when panning
+        let start = (view_min / spacing).ceil() * spacing;
+        let mut y = start.y;
+        while y < view_max.y {
+            let mut x = start.x;
+            while x < view_max.x {
+                let pos = Vec2::new(x, y);
+                if field.region.contains(pos) {
+                    // Capped at most of a grid cell so neighbouring arrows stay apart
+                    let arrow = (field.shape.force_at(pos) * ARROW_SCALE).clamp_length_max(spacing * 0.8);
+                    if arrow.length_squared() > 1.0 {
+                        gizmos.arrow_2d(pos, pos + arrow, color);
+                    }
+                }
+                x += spacing;
+            }
+            y += spacing;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Vec2, b: Vec2) -> bool {
+        (a - b).length() < 1e-4
+    }
+
+    #[test]
+    fn uniform_fields_push_the_same_everywhere() {
+        let shape = ExternalShape::Uniform { force: Vec2::new(0.0, -9.8) };
+        for pos in [Vec2::ZERO, Vec2::new(1e4, -3e3)] {
+            assert_eq!(shape.force_at(pos), Vec2::new(0.0, -9.8));
+        }
+    }
+
+    #[test]
+    fn central_fields_pull_towards_the_centre_with_constant_size() {
+        let shape = ExternalShape::Central { center: Vec2::new(10.0, 0.0), strength: 5.0 };
+        assert!(close(shape.force_at(Vec2::new(0.0, 0.0)), Vec2::new(5.0, 0.0)));
+        assert!(close(shape.force_at(Vec2::new(10.0, 100.0)), Vec2::new(0.0, -5.0)));
+        // Negative strength pushes away; the centre itself feels nothing
+        let repulsive = ExternalShape::Central { center: Vec2::ZERO, strength: -2.0 };
+        assert!(close(repulsive.force_at(Vec2::new(3.0, 4.0)), Vec2::new(1.2, 1.6)));
+        assert_eq!(repulsive.force_at(Vec2::ZERO), Vec2::ZERO);
+    }
+
+    #[test]
+    fn vortex_fields_push_counter_clockwise_when_positive() {
+        let shape = ExternalShape::Vortex { center: Vec2::ZERO, strength: 4.0 };
+        assert!(close(shape.force_at(Vec2::new(10.0, 0.0)), Vec2::new(0.0, 4.0)));
+        assert!(close(shape.force_at(Vec2::new(0.0, 10.0)), Vec2::new(-4.0, 0.0)));
+        let clockwise = ExternalShape::Vortex { center: Vec2::ZERO, strength: -4.0 };
+        assert!(close(clockwise.force_at(Vec2::new(10.0, 0.0)), Vec2::new(0.0, -4.0)));
+        // Always tangential
+        let pos = Vec2::new(3.0, -7.0);
+        assert!(shape.force_at(pos).dot(pos).abs() < 1e-4);
+    }
+
+    #[test]
+    fn shear_grows_with_the_height_above_the_centre() {
+        let shape = ExternalShape::Shear { center: Vec2::new(0.0, 10.0), rate: 0.5 };
+        assert_eq!(shape.force_at(Vec2::new(-50.0, 10.0)), Vec2::ZERO);
+        assert_eq!(shape.force_at(Vec2::new(0.0, 30.0)), Vec2::new(10.0, 0.0));
+        assert_eq!(shape.force_at(Vec2::new(99.0, -10.0)), Vec2::new(-10.0, 0.0));
+    }
+
+    #[test]
+    fn regions_contain_their_inside_and_boundary() {
+        let rect = Region::Rect { min: Vec2::new(-1.0, 0.0), max: Vec2::new(1.0, 2.0) };
+        assert!(rect.contains(Vec2::new(0.0, 1.0)) && rect.contains(Vec2::new(1.0, 2.0)));
+        assert!(!rect.contains(Vec2::new(0.0, -0.1)) && !rect.contains(Vec2::new(1.5, 1.0)));
+        let circle = Region::Circle { center: Vec2::new(5.0, 5.0), radius: 2.0 };
+        assert!(circle.contains(Vec2::new(6.0, 6.0)) && circle.contains(Vec2::new(7.0, 5.0)));
+        assert!(!circle.contains(Vec2::new(7.0, 7.0)));
+        assert!(Region::Everywhere.contains(Vec2::splat(1e9)));
+    }
+
+    #[test]
+    fn fields_act_only_inside_their_region_and_by_weight() {
+        let field = ExternalField {
+            shape: ExternalShape::Uniform { force: Vec2::new(2.0, 0.0) },
+            region: Region::Circle { center: Vec2::ZERO, radius: 10.0 },
+            weights: vec![1.0, 0.0, -0.5],
+        };
+        assert_eq!(field.force_at(Vec2::new(5.0, 0.0), 0), Vec2::new(2.0, 0.0));
+        assert_eq!(field.force_at(Vec2::new(20.0, 0.0), 0), Vec2::ZERO);
+        assert_eq!(field.force_at(Vec2::new(5.0, 0.0), 1), Vec2::ZERO);
+        assert_eq!(field.force_at(Vec2::new(5.0, 0.0), 2), Vec2::new(-1.0, 0.0));
+        // Types beyond the list feel the full field
+        assert_eq!(field.force_at(Vec2::new(5.0, 0.0), 7), Vec2::new(2.0, 0.0));
+    }
+}
@@ -0,0 +1,252 @@
+use bevy::prelude::*;
+use std::collections::{HashMap, HashSet};
+use crate::components::*;
+use crate::resources::*;
+
+// Explicit diffusion is stable for D·dt/h² ≤ 0.25; substeps keep each one below this
+const MAX_DIFFUSION_NUMBER: f32 = 0.2;
+// Bounds the work per step when the diffusion is huge compared to the cell size
+const MAX_SUBSTEPS: usize = 64;
+
+/// Persistent concentration grids of the fields in `FieldMode::Grid`, keyed by field id.
+///
+/// All grids cover the same square centred on the origin. Particles outside it neither
+/// deposit into nor feel the grids. Edges are closed (no flux leaves the grid).
+#[derive(Resource)]
+pub struct FieldGrids {
+    // World units per cell
+    pub cell_size: f32,
+    // Cells per side
+    pub cells: usize,
+    // Row-major, row 0 at the bottom (world -y)
+    pub grids: HashMap<usize, Vec<f32>>,
+    // Fields whose last step needed more than MAX_SUBSTEPS, so they diffused slower than set
+    pub capped: HashSet<usize>,
+}
+
+impl Default for FieldGrids {
+    fn default() -> Self {
+        Self {
+            cell_size: 8.0,
+            cells: 256,
+            grids: HashMap::new(),
+            capped: HashSet::new(),
+        }
+    }
+}
+
+impl FieldGrids {
+    /// World position of the bottom-left corner.
+    pub fn origin(&self) -> Vec2 {
+        Vec2::splat(-0.5 * self.cells as f32 * self.cell_size)
+    }
+
+    /// Continuous cell coordinates of `pos`, with cell centres at whole numbers.
+    fn cell_coords(&self, pos: Vec2) -> Vec2 {
+        (pos - self.origin()) / self.cell_size - Vec2::splat(0.5)
+    }
+
+    /// The four cells around `pos` with their bilinear weights, or None outside the grid.
+    fn bilinear(&self, pos: Vec2) -> Option<[(usize, f32); 4]> {
+        let coords = self.cell_coords(pos);
+        let max = (self.cells - 1) as f32;
+        if !(coords.x >= 0.0 && coords.y >= 0.0 && coords.x <= max && coords.y <= max) { return None; }
+        Some(self.corners(coords))
+    }
+
+    /// The four cells around cell coordinates `coords` (within the grid) with their bilinear weights.
+    fn corners(&self, coords: Vec2) -> [(usize, f32); 4] {
+        let x0 = (coords.x.floor() as usize).min(self.cells - 2);
+        let y0 = (coords.y.floor() as usize).min(self.cells - 2);
+        let (fx, fy) = (coords.x - x0 as f32, coords.y - y0 as f32);
+        let idx = |x: usize, y: usize| y * self.cells + x;
+        [
+            (idx(x0, y0), (1.0 - fx) * (1.0 - fy)),
+            (idx(x0 + 1, y0), fx * (1.0 - fy)),
+            (idx(x0, y0 + 1), (1.0 - fx) * fy),
+            (idx(x0 + 1, y0 + 1), fx * fy),
+        ]
+    }
+
+    /// Concentration of field `field_id` at `pos` (0 without a grid or outside it).
+    pub fn sample(&self, field_id: usize, pos: Vec2) -> f32 {
+        let Some(grid) = self.grids.get(&field_id) else { return 0.0; };
+        self.bilinear(pos)
+            .map_or(0.0, |corners| corners.iter().map(|&(idx, w)| grid[idx] * w).sum())
+    }
+
+    /// Concentration gradient of field `field_id` at `pos` by central differences, per world unit
+    /// (0 without a grid or outside it). The samples stay on the grid, so within a cell of the
+    /// edge the difference is one-sided instead of reading the missing outside as 0.
+    pub fn gradient(&self, field_id: usize, pos: Vec2) -> Vec2 {
+        let Some(grid) = self.grids.get(&field_id) else { return Vec2::ZERO; };
+        if self.bilinear(pos).is_none() { return Vec2::ZERO; }
+
+        let coords = self.cell_coords(pos);
+        let max = Vec2::splat((self.cells - 1) as f32);
+        let value = |at: Vec2| self.corners(at).iter().map(|&(idx, w)| grid[idx] * w).sum::<f32>();
+        let along = |axis: Vec2| {
+            let ahead = (coords + axis).clamp(Vec2::ZERO, max);
+            let behind = (coords - axis).clamp(Vec2::ZERO, max);
+            let span = (ahead - behind).dot(axis) * self.cell_size;
+            if span > 0.0 { (value(ahead) - value(behind)) / span } else { 0.0 }
+        };
+        Vec2::new(along(Vec2::X), along(Vec2::Y))
+    }
+
+    /// Adds `amount` of concentration at `pos`, split over the four nearest cells.
+    fn deposit(&mut self, field_id: usize, pos: Vec2, amount: f32) {
+        let Some(corners) = self.bilinear(pos) else { return; };
+        let Some(grid) = self.grids.get_mut(&field_id) else { return; };
+        for (idx, w) in corners {
+            grid[idx] += amount * w;
+        }
+    }
+
+    /// Diffuses and decays field `field_id` over `dt` seconds.
+    /// Returns true if the diffusion needed more than `MAX_SUBSTEPS` and was slowed down.
+    fn evolve(&mut self, field_id: usize, diffusion: f32, decay: f32, dt: f32) -> bool {
+        let n = self.cells;
+        let h = self.cell_size;
+        let Some(grid) = self.grids.get_mut(&field_id) else { return false; };
+
+        let number = diffusion * dt / (h * h);
+        let needed = (number / MAX_DIFFUSION_NUMBER).ceil();
+        let substeps = (needed as usize).clamp(1, MAX_SUBSTEPS);
+        let alpha = (number / substeps as f32).min(0.25);
+
+        if alpha > 0.0 {
+            let mut next = vec![0.0; grid.len()];
+            for _ in 0..substeps {
+                for y in 0..n {
+                    for x in 0..n {
+                        let here = grid[y * n + x];
+                        // Missing neighbours mirror the cell, so nothing flows across the edge
+                        let left = if x > 0 { grid[y * n + x - 1] } else { here };
+                        let right = if x + 1 < n { grid[y * n + x + 1] } else { here };
+                        let down = if y > 0 { grid[(y - 1) * n + x] } else { here };
+                        let up = if y + 1 < n { grid[(y + 1) * n + x] } else { here };
+                        next[y * n + x] = here + alpha * (left + right + down + up - 4.0 * here);
+                    }
+                }
+                std::mem::swap(grid, &mut next);
+            }
+        }
+
+        if decay > 0.0 {
+            let factor = (-decay * dt).exp();
+            grid.iter_mut().for_each(|v| *v *= factor);
+        }
+        needed > MAX_SUBSTEPS as f32
+    }
+}
+
+/// Advances every grid field: emitters deposit `strength_scale` concentration per second
+/// at their position, then the grid diffuses and decays with its field's coefficients.
+/// Grids of fields no longer in grid mode are dropped.
+#[allow(clippy::type_complexity)]
+pub fn field_grid_system(
+    mut grids: ResMut<FieldGrids>,
+    query: Query<(&Transform, &ParticleTypeID), (With<Particle>, Without<Quarantined>)>,
+    alchemy: Res<AlchemyRules>,
+    global_consts: Res<GlobalConstants>,
+    time: Res<Time>,
+) {
+    // 1. Which fields are grids, and with which coefficients
+    let mut grid_fields = HashMap::new();
+    for def in alchemy.particle_types.iter() {
+        let Some(field_id) = def.emits_field else { continue; };
+        if let Some(FieldShape { mode: FieldMode::Grid { diffusion, decay }, .. }) = alchemy.field_shape(field_id) {
+            grid_fields.insert(field_id, (*diffusion, *decay));
+        }
+    }
+
+    grids.grids.retain(|field_id, _| grid_fields.contains_key(field_id));
+    grids.capped.retain(|field_id| grid_fields.contains_key(field_id));
+    let cells = grids.cells.max(2);
+    grids.cells = cells;
+    for field_id in grid_fields.keys() {
+        let grid = grids.grids.entry(*field_id).or_default();
+        // A resized grid starts over empty
+        if grid.len() != cells * cells {
+            *grid = vec![0.0; cells * cells];
+        }
+    }
+    if grid_fields.is_empty() { return; }
+
+    let dt = time.delta_seconds() * global_consts.time_scale;
+
+    // 2. Deposit
+    for (transform, type_id) in query.iter() {
+        let Some(def) = alchemy.particle_types.get(type_id.0) else { continue; };
+        let Some(field_id) = def.emits_field else { continue; };
+        if grid_fields.contains_key(&field_id) {
+            grids.deposit(field_id, transform.translation.truncate(), def.emission_shape.strength_scale * dt);
+        }
+    }
+
+    // 3. Diffuse and decay
+    for (field_id, (diffusion, decay)) in grid_fields {
+        if grids.evolve(field_id, diffusion, decay, dt) {
+            grids.capped.insert(field_id);
+        } else {
+            grids.capped.remove(&field_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid_with_spike() -> FieldGrids {
+        let mut grids = FieldGrids { cells: 16, ..default() };
+        grids.grids.insert(0, vec![0.0; 16 * 16]);
+        grids.deposit(0, Vec2::ZERO, 100.0);
+        grids
+    }
+
+    #[test]
+    fn constant_fields_have_no_gradient_up_to_the_edge() {
+        let mut grids = FieldGrids { cells: 16, ..default() };
+        grids.grids.insert(0, vec![5.0; 16 * 16]);
+        let (min, max) = (grids.origin(), -grids.origin());
+        let h = grids.cell_size;
+        for pos in [Vec2::ZERO, min + Vec2::splat(0.6 * h), Vec2::new(max.x - 0.7 * h, 0.0), Vec2::new(0.0, min.y + 0.5 * h)] {
+            assert!(grids.gradient(0, pos).length() < 1e-5, "{:?} at {:?}", grids.gradient(0, pos), pos);
+        }
+        // Outside the grid nothing is felt
+        assert_eq!(grids.gradient(0, max + Vec2::splat(h)), Vec2::ZERO);
+    }
+
+    #[test]
+    fn linear_ramps_have_the_same_gradient_at_the_edge() {
+        let mut grids = FieldGrids { cells: 16, ..default() };
+        // Rising by 2 per cell along x
+        grids.grids.insert(0, (0..16 * 16).map(|i| 2.0 * (i % 16) as f32).collect());
+        let h = grids.cell_size;
+        let expected = Vec2::new(2.0 / h, 0.0);
+        let (min, max) = (grids.origin(), -grids.origin());
+        for pos in [Vec2::ZERO, Vec2::new(min.x + 0.6 * h, 0.0), Vec2::new(max.x - 0.6 * h, 10.0)] {
+            assert!((grids.gradient(0, pos) - expected).length() < 1e-4, "{:?} at {:?}", grids.gradient(0, pos), pos);
+        }
+    }
+
+    #[test]
+    fn diffusion_within_the_substep_budget_is_not_capped() {
+        let mut grids = grid_with_spike();
+        // D·dt/h² = 200 / 64 / 60, well below one substep
+        assert!(!grids.evolve(0, 200.0, 0.0, 1.0 / 60.0));
+        let total: f32 = grids.grids[&0].iter().sum();
+        assert!((total - 100.0).abs() < 1e-3, "closed edges keep the total, got {}", total);
+    }
+
+    #[test]
+    fn diffusion_beyond_the_substep_budget_is_capped() {
+        let mut grids = grid_with_spike();
+        // D·dt/h² = 5000 · 1 / 4 needs thousands of substeps
+        grids.cell_size = 2.0;
+        assert!(grids.evolve(0, 5000.0, 0.0, 1.0));
+        assert!(grids.grids[&0].iter().all(|v| v.is_finite() && *v >= 0.0));
+    }
+}
@@ -0,0 +1,282 @@
+use bevy::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use std::collections::HashMap;
+use crate::resources::*;
+
+/// Family of curve shapes the generator draws from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CurveFamily {
+    // Monotonic decrease from a random strength to zero
+    Falloff,
+    // Repulsive core, attractive shell, zero at the edge (Lennard-Jones-like)
+    CoreShell,
+    // Any value at every point, zero at the edge
+    Free,
+}
+
+impl CurveFamily {
+    pub const ALL: [CurveFamily; 3] = [CurveFamily::Falloff, CurveFamily::CoreShell, CurveFamily::Free];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CurveFamily::Falloff => "Falloff",
+            CurveFamily::CoreShell => "Core / Shell",
+            CurveFamily::Free => "Free",
+        }
+    }
+}
+
+/// Parameters for generating a random universe.
+#[derive(Resource, Debug, Clone)]
+pub struct RandomizerSettings {
+    pub num_types: usize,
+    // Interaction weights are drawn from -weight_range..=weight_range
+    pub weight_range: f32,
+    // Fraction of interactions forced to zero
+    pub sparsity: f32,
+    // If true, w[a,b] = w[b,a]
+    pub symmetric: bool,
+    pub curve_family: CurveFamily,
+    pub curve_points: usize,
+    pub radius_min: f32,
+    pub radius_max: f32,
+    pub strength_min: f32,
+    pub strength_max: f32,
+    pub seed: u64,
+    // Relative size of the changes made by `mutate`
+    pub mutation_amount: f32,
+}
+
+impl Default for RandomizerSettings {
+    fn default() -> Self {
+        Self {
+            num_types: 4,
+            weight_range: 2.0,
+            sparsity: 0.2,
+            symmetric: false,
+            curve_family: CurveFamily::Falloff,
+            curve_points: 4,
+            radius_min: 100.0,
+            radius_max: 400.0,
+            strength_min: 500.0,
+            strength_max: 2000.0,
+            seed: 0,
+            mutation_amount: 0.1,
+        }
+    }
+}
+
+/// Generates a complete random ruleset. Every type emits its own field.
+pub fn generate(settings: &RandomizerSettings) -> AlchemyRules {
+    let mut rng = StdRng::seed_from_u64(settings.seed);
+    let num_types = settings.num_types.max(1);
+
+    // Spread hues evenly, with a random offset so colours differ between seeds
+    let hue_offset = rng.gen_range(0.0..360.0);
+    let particle_types = (0..num_types)
+        .map(|i| {
+            let hue = (hue_offset + i as f32 * 360.0 / num_types as f32) % 360.0;
+            let mut shape = FieldShape {
+                max_radius: random_between(&mut rng, settings.radius_min, settings.radius_max),
+                strength_scale: random_between(&mut rng, settings.strength_min, settings.strength_max),
+                points: random_curve(&mut rng, settings.curve_family, settings.curve_points),
+                mode: FieldMode::default(),
+                softening: Softening::default(),
+                lut: Vec::new(),
+            };
+            shape.bake_lut();
+
+            ParticleTypeDefinition {
+                name: format!("Type {}", type_letter(i)),
+                default_mass: 1.0,
+                default_color: Color::hsl(hue, rng.gen_range(0.6..0.9), rng.gen_range(0.45..0.65)),
+                emits_field: Some(i),
+                emission_shape: shape,
+                hard_core_radius: 0.0,
+                lifetime: None,
+                field_texture: None,
+            }
+        })
+        .collect();
+
+    let mut interactions = HashMap::new();
+    for a in 0..num_types {
+        for b in 0..num_types {
+            if settings.symmetric && b < a {
+                let mirrored = interactions[&(b, a)];
+                interactions.insert((a, b), mirrored);
+                continue;
+            }
+            let weight = if rng.gen::<f32>() < settings.sparsity {
+                0.0
+            } else {
+                rng.gen_range(-settings.weight_range..=settings.weight_range)
+            };
+            interactions.insert((a, b), weight);
+        }
+    }
+
+    AlchemyRules { particle_types, interactions }
+}
+
+/// Returns a slightly changed copy of `rules`: weights, curve points and radii are nudged
+/// by up to `settings.mutation_amount` of their range. Types and colours are kept.
+pub fn mutate(rules: &AlchemyRules, settings: &RandomizerSettings) -> AlchemyRules {
+    let mut rng = StdRng::seed_from_u64(settings.seed);
+    mutate_with(rules, settings, &mut rng)
+}
+
+/// Same as `mutate`, but draws from a caller-provided RNG (used by the evolutionary search).
+pub fn mutate_with(rules: &AlchemyRules, settings: &RandomizerSettings, rng: &mut impl Rng) -> AlchemyRules {
+    let amount = settings.mutation_amount.max(0.0);
+    let mut mutated = rules.clone();
+
+    // Visit keys in a fixed order so a seed always gives the same result
+    let mut keys: Vec<(usize, usize)> = mutated.interactions.keys().copied().collect();
+    keys.sort_unstable();
+    for key in keys.iter() {
+        if settings.symmetric && key.1 < key.0 { continue; }
+        let delta = rng.gen_range(-1.0..=1.0) * amount * settings.weight_range;
+        let range = settings.weight_range.max(f32::EPSILON);
+        let weight = (mutated.interactions[key] + delta).clamp(-range, range);
+        mutated.interactions.insert(*key, weight);
+        if settings.symmetric {
+            mutated.interactions.insert((key.1, key.0), weight);
+        }
+    }
+
+    for p_def in mutated.particle_types.iter_mut() {
+        let shape = &mut p_def.emission_shape;
+        for point in shape.points.iter_mut() {
+            point.y = (point.y + rng.gen_range(-1.0..=1.0) * amount).clamp(-1.0, 1.0);
+        }
+        let radius_span = (settings.radius_max - settings.radius_min).abs().max(1.0);
+        shape.max_radius = (shape.max_radius + rng.gen_range(-1.0..=1.0) * amount * radius_span).max(1.0);
+        shape.bake_lut();
+    }
+
+    mutated
+}
+
+fn random_curve(rng: &mut impl Rng, family: CurveFamily, count: usize) -> Vec<CurvePoint> {
+    let count = count.max(2);
+    let last = (count - 1) as f32;
+
+    match family {
+        CurveFamily::Falloff => {
+            let start = rng.gen_range(0.2..=1.0) * if rng.gen_bool(0.5) { 1.0 } else { -1.0 };
+            // Random decreasing fractions of the start value
+            let mut fractions: Vec<f32> = (1..count - 1).map(|_| rng.gen_range(0.0..1.0)).collect();
+            fractions.sort_by(|a, b| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+            let mut points = vec![CurvePoint { x: 0.0, y: start }];
+            for (i, f) in fractions.into_iter().enumerate() {
+                points.push(CurvePoint { x: (i + 1) as f32 / last, y: start * f });
+            }
+            points.push(CurvePoint { x: 1.0, y: 0.0 });
+            points
+        }
+        CurveFamily::CoreShell => {
+            let core = rng.gen_range(-1.0..=-0.3);
+            let shell = rng.gen_range(0.1..=1.0);
+            let crossing = rng.gen_range(0.1..=0.5);
+            (0..count)
+                .map(|i| {
+                    let x = i as f32 / last;
+                    let y = if i == count - 1 {
+                        0.0
+                    } else if x < crossing {
+                        core * (1.0 - x / crossing)
+                    } else {
+                        shell * (1.0 - (x - crossing) / (1.0 - crossing))
+                    };
+                    CurvePoint { x, y }
+                })
+                .collect()
+        }
+        CurveFamily::Free => (0..count)
+            .map(|i| CurvePoint {
+                x: i as f32 / last,
+                y: if i == count - 1 { 0.0 } else { rng.gen_range(-1.0..=1.0) },
+            })
+            .collect(),
+    }
+}
+
+fn random_between(rng: &mut impl Rng, a: f32, b: f32) -> f32 {
+    let (lo, hi) = if a <= b { (a, b) } else { (b, a) };
+    if hi - lo <= f32::EPSILON { lo } else { rng.gen_range(lo..hi) }
+}
+
+/// A, B, ..., Z, AA, AB, ...
+fn type_letter(mut i: usize) -> String {
+    let mut name = String::new();
+    loop {
+        name.insert(0, (b'A' + (i % 26) as u8) as char);
+        if i < 26 { break; }
+        i = i / 26 - 1;
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(seed: u64) -> RandomizerSettings {
+        RandomizerSettings { num_types: 5, seed, ..default() }
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_rules() {
+        assert!(generate(&settings(7)) == generate(&settings(7)));
+        assert!(generate(&settings(7)) != generate(&settings(8)));
+        let rules = generate(&settings(7));
+        assert!(mutate(&rules, &settings(3)) == mutate(&rules, &settings(3)));
+    }
+
+    #[test]
+    fn generated_rules_are_valid() {
+        for family in CurveFamily::ALL {
+            let rules = generate(&RandomizerSettings { curve_family: family, ..settings(1) });
+            assert_eq!(rules.particle_types.len(), 5);
+            assert!(rules.validate().is_empty(), "{:?}", family);
+        }
+    }
+
+    #[test]
+    fn symmetric_rules_have_mirrored_weights() {
+        let rules = generate(&RandomizerSettings { symmetric: true, sparsity: 0.0, ..settings(2) });
+        for a in 0..5 {
+            for b in 0..5 {
+                assert_eq!(rules.interactions[&(a, b)], rules.interactions[&(b, a)]);
+            }
+        }
+        let mutated = mutate(&rules, &RandomizerSettings { symmetric: true, mutation_amount: 0.5, ..settings(3) });
+        for (&(a, b), weight) in mutated.interactions.iter() {
+            assert_eq!(*weight, mutated.interactions[&(b, a)]);
+        }
+    }
+
+    #[test]
+    fn full_sparsity_zeroes_every_weight() {
+        let rules = generate(&RandomizerSettings { sparsity: 1.0, ..settings(4) });
+        assert_eq!(rules.interactions.len(), 25);
+        assert!(rules.interactions.values().all(|&w| w == 0.0));
+    }
+
+    #[test]
+    fn mutation_keeps_types_and_weight_range() {
+        let base = settings(5);
+        let mut rules = generate(&base);
+        let mut rng = StdRng::seed_from_u64(9);
+        // Large steps, many rounds: the weights must hit the bounds and stay there
+        let mutation = RandomizerSettings { mutation_amount: 1.0, ..base.clone() };
+        for _ in 0..50 {
+            rules = mutate_with(&rules, &mutation, &mut rng);
+            assert_eq!(rules.particle_types.len(), base.num_types);
+            assert!(rules.interactions.values().all(|w| w.abs() <= base.weight_range), "{:?}", rules.interactions);
+        }
+        assert!(rules.validate().is_empty());
+    }
+}
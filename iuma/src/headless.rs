@@ -0,0 +1,227 @@
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use rand::Rng;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::time::Duration;
+use crate::components::*;
+use crate::resources::*;
+use crate::physics::{PhysicsPlugin, PhysicsSet};
+use crate::scripting::{self, ScriptHost};
+use crate::diagnostics::SimDiagnostics;
+use crate::snapshot::{ParticleState, Snapshot};
+use crate::obstacles::Obstacle;
+use crate::external::ExternalField;
+use crate::sources::{ParticleSource, Sink};
+
+/// Simulated time per headless step. Matches the fixed dt of the interaction step.
+pub const HEADLESS_STEP: Duration = Duration::from_millis(16);
+
+/// A windowless Bevy app running only the physics, stepped manually with a fixed dt.
+pub struct HeadlessSim {
+    app: App,
+}
+
+impl HeadlessSim {
+    pub fn new(rules: AlchemyRules, constants: GlobalConstants, snapshot: &Snapshot) -> Self {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugins(PhysicsPlugin)
+            .insert_resource(rules)
+            .insert_resource(constants)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(HEADLESS_STEP));
+        app.finish();
+        app.cleanup();
+
+        // The first update only initializes the clock (its delta is zero), so run it empty
+        app.update();
+
+        for state in snapshot.particles.iter() {
+            app.world.spawn(state.bundle());
+        }
+        for obstacle in snapshot.obstacles.iter() {
+            app.world.spawn(obstacle.clone());
+        }
+        for field in snapshot.external_fields.iter() {
+            app.world.spawn(field.clone());
+        }
+        for source in snapshot.sources.iter() {
+            app.world.spawn(source.clone());
+        }
+        for sink in snapshot.sinks.iter() {
+            app.world.spawn(sink.clone());
+        }
+        app.world.resource_mut::<SimDiagnostics>().step = snapshot.step;
+
+        Self { app }
+    }
+
+    /// Advances the simulation by one fixed step.
+    pub fn step(&mut self) {
+        self.app.update();
+    }
+
+    /// Attaches a Rhai script whose hooks run before every step.
+    pub fn load_script(&mut self, path: PathBuf) -> Result<(), String> {
+        if !self.app.world.contains_resource::<ScriptHost>() {
+            self.app.init_resource::<ScriptHost>()
+                .add_systems(Update, scripting::script_system.before(PhysicsSet));
+        }
+        scripting::load_script(&mut self.app.world, path)
+    }
+
+    /// The latest error raised by the script, if any.
+    pub fn script_error(&self) -> Option<&str> {
+        self.app.world.get_resource::<ScriptHost>().and_then(|host| host.error.as_deref())
+    }
+
+    pub fn diagnostics(&self) -> &SimDiagnostics {
+        self.app.world.resource::<SimDiagnostics>()
+    }
+
+    /// Captures the current state as a snapshot.
+    pub fn snapshot(&mut self) -> Snapshot {
+        Snapshot {
+            step: self.diagnostics().step,
+            particles: self.particles(),
+            obstacles: self.obstacles(),
+            external_fields: self.external_fields(),
+            sources: self.sources(),
+            sinks: self.sinks(),
+        }
+    }
+
+    pub fn sources(&mut self) -> Vec<ParticleSource> {
+        let mut query = self.app.world.query::<&ParticleSource>();
+        query.iter(&self.app.world).cloned().collect()
+    }
+
+    pub fn sinks(&mut self) -> Vec<Sink> {
+        let mut query = self.app.world.query::<&Sink>();
+        query.iter(&self.app.world).cloned().collect()
+    }
+
+    pub fn external_fields(&mut self) -> Vec<ExternalField> {
+        let mut query = self.app.world.query::<&ExternalField>();
+        query.iter(&self.app.world).cloned().collect()
+    }
+
+    pub fn obstacles(&mut self) -> Vec<Obstacle> {
+        let mut query = self.app.world.query::<&Obstacle>();
+        query.iter(&self.app.world).cloned().collect()
+    }
+
+    /// Reads the current particle states back out of the world.
+    pub fn particles(&mut self) -> Vec<ParticleState> {
+        let mut query = self.app.world.query_filtered::<(&Transform, &Velocity, &Mass, &Momentum, &ParticleTypeID, &Age), With<Particle>>();
+        query.iter(&self.app.world)
+            .map(|(transform, velocity, mass, momentum, type_id, age)| ParticleState {
+                type_id: type_id.0,
+                position: transform.translation.truncate(),
+                velocity: velocity.0,
+                mass: mass.0,
+                momentum: Some(momentum.0),
+                age: age.0,
+            })
+            .collect()
+    }
+}
+
+/// Scatters `per_type` resting particles of every type uniformly inside a disc of `radius`.
+pub fn random_particles(rules: &AlchemyRules, per_type: usize, radius: f32, rng: &mut impl Rng) -> Vec<ParticleState> {
+    let mut particles = Vec::with_capacity(per_type * rules.particle_types.len());
+    for (type_id, def) in rules.particle_types.iter().enumerate() {
+        for _ in 0..per_type {
+            // sqrt for a uniform density over the disc area
+            let r = radius * rng.gen::<f32>().sqrt();
+            let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+            particles.push(ParticleState {
+                type_id,
+                position: Vec2::from_angle(angle) * r,
+                velocity: Vec2::ZERO,
+                mass: def.default_mass,
+                momentum: None,
+                age: 0.0,
+            });
+        }
+    }
+    particles
+}
+
+/// Calls `work` on every item, spread over worker threads (`threads` = 0: one per available core).
+/// `on_result` is called on the calling thread as items finish (in completion order);
+/// the returned results are in item order.
+pub fn run_parallel<T: Sync, R: Send>(
+    items: &[T],
+    threads: usize,
+    work: impl Fn(&T) -> R + Sync,
+    mut on_result: impl FnMut(&R),
+) -> Vec<R> {
+    let threads = match threads {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    }.min(items.len()).max(1);
+
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    let mut results: Vec<Option<R>> = items.iter().map(|_| None).collect();
+
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            let sender = sender.clone();
+            let (next, work) = (&next, &work);
+            scope.spawn(move || loop {
+                let idx = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(idx) else { break; };
+                if sender.send((idx, work(item))).is_err() { break; }
+            });
+        }
+        // Drop our sender so the loop below ends once every worker is done
+        drop(sender);
+
+        for (idx, result) in receiver {
+            on_result(&result);
+            results[idx] = Some(result);
+        }
+    });
+
+    results.into_iter().flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parallel_results_keep_the_item_order() {
+        let items: Vec<u64> = (0..50).collect();
+        let mut reported = 0;
+        let results = run_parallel(&items, 4, |&i| {
+            // Uneven work so items finish out of order
+            std::thread::sleep(Duration::from_micros((50 - i) * 20));
+            i * i
+        }, |_| reported += 1);
+        assert_eq!(reported, items.len());
+        assert_eq!(results, items.iter().map(|i| i * i).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn restored_momentum_survives_past_the_speed_cap() {
+        let constants = GlobalConstants { speed_limit: SpeedLimit::Relativistic, ..default() };
+        let c = constants.light_speed;
+        // Lorentz factor around 200, far beyond what the capped velocity can express
+        let momentum = Vec2::new(200.0 * c, 0.0);
+        let velocity = SpeedLimit::Relativistic.velocity(momentum, 1.0, c);
+        let snapshot = Snapshot {
+            particles: vec![ParticleState {
+                type_id: 0, position: Vec2::ZERO, velocity, mass: 1.0, momentum: Some(momentum), age: 0.0,
+            }],
+            ..default()
+        };
+        let mut sim = HeadlessSim::new(AlchemyRules::default(), constants, &snapshot);
+        sim.step();
+        let restored = sim.particles()[0].momentum.unwrap();
+        assert!((restored - momentum).length() < momentum.length() * 1e-5, "{restored} != {momentum}");
+    }
+}
@@ -0,0 +1,227 @@
+use bevy::prelude::*;
+use crate::resources::*;
+
+/// Oldest entries are dropped beyond this many undo steps.
+const MAX_ENTRIES: usize = 200;
+
+/// The rules and constants as they were at one point in the edit history.
+#[derive(Clone)]
+pub struct EditState {
+    pub rules: AlchemyRules,
+    pub constants: GlobalConstants,
+}
+
+impl EditState {
+    fn matches(&self, rules: &AlchemyRules, constants: &GlobalConstants) -> bool {
+        self.rules == *rules && self.constants == *constants
+    }
+}
+
+/// An undo or redo step: the state to return to and what the edit did.
+pub struct HistoryEntry {
+    pub label: String,
+    pub state: EditState,
+}
+
+/// Undo/redo stacks of editor changes to `AlchemyRules` and `GlobalConstants`.
+///
+/// Edits are detected by comparing the state at the end of each frame with the last committed
+/// one, and only committed while no pointer button is held, so a whole slider drag is one entry.
+/// Changes made outside the editors (e.g. by a script) are adopted without an entry.
+#[derive(Resource, Default)]
+pub struct EditHistory {
+    // Most recent last
+    pub undo_entries: Vec<HistoryEntry>,
+    pub redo_entries: Vec<HistoryEntry>,
+    // Last committed state
+    current: Option<EditState>,
+    // State at the end of the previous frame, to tell editor changes from outside ones
+    last_seen: Option<EditState>,
+}
+
+impl EditHistory {
+    /// Called at the start of a frame, before the editors run.
+    pub fn begin_frame(&mut self, rules: &AlchemyRules, constants: &GlobalConstants) {
+        let unchanged = self.last_seen.as_ref().is_some_and(|seen| seen.matches(rules, constants));
+        if !unchanged {
+            let state = EditState { rules: rules.clone(), constants: constants.clone() };
+            self.current = Some(state.clone());
+            self.last_seen = Some(state);
+        }
+    }
+
+    /// Called at the end of a frame, after the editors ran. Commits an entry unless `interacting`.
+    pub fn end_frame(&mut self, rules: &AlchemyRules, constants: &GlobalConstants, interacting: bool) {
+        if !self.last_seen.as_ref().is_some_and(|seen| seen.matches(rules, constants)) {
+            self.last_seen = Some(EditState { rules: rules.clone(), constants: constants.clone() });
+        }
+        if interacting { return; }
+
+        let Some(current) = self.current.as_ref() else { return; };
+        if current.matches(rules, constants) { return; }
+
+        let label = describe_change(current, rules, constants);
+        let previous = std::mem::replace(&mut self.current, self.last_seen.clone()).unwrap();
+        self.undo_entries.push(HistoryEntry { label, state: previous });
+        if self.undo_entries.len() > MAX_ENTRIES {
+            self.undo_entries.remove(0);
+        }
+        self.redo_entries.clear();
+    }
+
+    /// Steps back one entry, returning the state to restore.
+    pub fn undo(&mut self) -> Option<EditState> {
+        let entry = self.undo_entries.pop()?;
+        Some(self.swap_in(entry, true))
+    }
+
+    /// Steps forward one entry, returning the state to restore.
+    pub fn redo(&mut self) -> Option<EditState> {
+        let entry = self.redo_entries.pop()?;
+        Some(self.swap_in(entry, false))
+    }
+
+    /// Makes `entry` current and pushes the state it replaces onto the opposite stack.
+    fn swap_in(&mut self, entry: HistoryEntry, undoing: bool) -> EditState {
+        let replaced = self.current.replace(entry.state.clone()).unwrap();
+        self.last_seen = Some(entry.state.clone());
+        let opposite = HistoryEntry { label: entry.label, state: replaced };
+        if undoing { self.redo_entries.push(opposite); } else { self.undo_entries.push(opposite); }
+        entry.state
+    }
+}
+
+/// A short human-readable summary of what changed between `old` and the new state.
+fn describe_change(old: &EditState, rules: &AlchemyRules, constants: &GlobalConstants) -> String {
+    let old_rules = &old.rules;
+    let names_changed = old_rules.particle_types.len() != rules.particle_types.len()
+        || old_rules.particle_types.iter().zip(rules.particle_types.iter()).any(|(a, b)| a.name != b.name);
+    if names_changed {
+        return "Replace ruleset".to_string();
+    }
+
+    let mut changes = Vec::new();
+    if old.constants.light_speed != constants.light_speed {
+        changes.push("Light speed".to_string());
+    }
+    if old.constants.time_scale != constants.time_scale {
+        changes.push("Time scale".to_string());
+    }
+    if old.constants != *constants && changes.is_empty() {
+        changes.push("Constants".to_string());
+    }
+
+    let weight = |r: &AlchemyRules, key: &(usize, usize)| r.interactions.get(key).copied().unwrap_or(0.0);
+    let mut changed_weights: Vec<(usize, usize)> = old_rules.interactions.keys()
+        .chain(rules.interactions.keys())
+        .filter(|key| weight(old_rules, key) != weight(rules, key))
+        .copied()
+        .collect();
+    changed_weights.sort();
+    changed_weights.dedup();
+    match changed_weights.as_slice() {
+        [] => {}
+        [(subject, field)] => {
+            let name = |idx: usize| rules.particle_types.get(idx).map_or("?", |def| def.name.as_str());
+            // Field ids are labelled by the first type emitting them
+            let source = rules.particle_types.iter().position(|def| def.emits_field == Some(*field));
+            changes.push(format!("Weight {} ← {} field", name(*subject), source.map_or("?", name)));
+        }
+        many => changes.push(format!("{} weights", many.len())),
+    }
+
+    for (old_def, def) in old_rules.particle_types.iter().zip(rules.particle_types.iter()) {
+        if old_def.emission_shape != def.emission_shape {
+            changes.push(format!("{} field shape", def.name));
+        } else if old_def != def {
+            changes.push(format!("{} properties", def.name));
+        }
+    }
+
+    if changes.is_empty() { "Edit".to_string() } else { changes.join(", ") }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One UI frame in which the editors set the light speed to `light_speed`.
+    fn edit_frame(history: &mut EditHistory, rules: &AlchemyRules, constants: &mut GlobalConstants, light_speed: f32, interacting: bool) {
+        history.begin_frame(rules, constants);
+        constants.light_speed = light_speed;
+        history.end_frame(rules, constants, interacting);
+    }
+
+    #[test]
+    fn a_drag_is_one_entry() {
+        let rules = AlchemyRules::default();
+        let mut constants = GlobalConstants::default();
+        let mut history = EditHistory::default();
+        for speed in [1100.0, 1200.0, 1300.0] {
+            edit_frame(&mut history, &rules, &mut constants, speed, true);
+        }
+        assert!(history.undo_entries.is_empty());
+        // Releasing the pointer commits the whole drag
+        edit_frame(&mut history, &rules, &mut constants, 1400.0, false);
+        assert_eq!(history.undo_entries.len(), 1);
+        assert_eq!(history.undo_entries[0].label, "Light speed");
+        assert_eq!(history.undo_entries[0].state.constants.light_speed, 1000.0);
+    }
+
+    #[test]
+    fn undo_and_redo_swap_between_the_stacks() {
+        let rules = AlchemyRules::default();
+        let mut constants = GlobalConstants::default();
+        let mut history = EditHistory::default();
+        edit_frame(&mut history, &rules, &mut constants, 2000.0, false);
+        edit_frame(&mut history, &rules, &mut constants, 3000.0, false);
+
+        constants = history.undo().unwrap().constants;
+        assert_eq!(constants.light_speed, 2000.0);
+        assert_eq!((history.undo_entries.len(), history.redo_entries.len()), (1, 1));
+        constants = history.undo().unwrap().constants;
+        assert_eq!(constants.light_speed, 1000.0);
+        assert!(history.undo().is_none());
+
+        // Restoring a state is not itself an edit
+        history.begin_frame(&rules, &constants);
+        history.end_frame(&rules, &constants, false);
+        assert_eq!((history.undo_entries.len(), history.redo_entries.len()), (0, 2));
+
+        constants = history.redo().unwrap().constants;
+        assert_eq!(constants.light_speed, 2000.0);
+        constants = history.redo().unwrap().constants;
+        assert_eq!(constants.light_speed, 3000.0);
+        assert!(history.redo().is_none());
+        assert_eq!(history.undo_entries.len(), 2);
+    }
+
+    #[test]
+    fn a_new_edit_clears_the_redo_stack() {
+        let rules = AlchemyRules::default();
+        let mut constants = GlobalConstants::default();
+        let mut history = EditHistory::default();
+        edit_frame(&mut history, &rules, &mut constants, 2000.0, false);
+        constants = history.undo().unwrap().constants;
+        assert_eq!(history.redo_entries.len(), 1);
+
+        edit_frame(&mut history, &rules, &mut constants, 500.0, false);
+        assert!(history.redo_entries.is_empty());
+        assert_eq!(history.undo_entries.len(), 1);
+        assert_eq!(history.undo_entries[0].state.constants.light_speed, 1000.0);
+    }
+
+    #[test]
+    fn the_oldest_entries_are_dropped() {
+        let rules = AlchemyRules::default();
+        let mut constants = GlobalConstants::default();
+        let mut history = EditHistory::default();
+        let extra = 10;
+        for i in 0..MAX_ENTRIES + extra {
+            edit_frame(&mut history, &rules, &mut constants, 2000.0 + i as f32, false);
+        }
+        assert_eq!(history.undo_entries.len(), MAX_ENTRIES);
+        // The first `extra` edits can no longer be undone
+        assert_eq!(history.undo_entries[0].state.constants.light_speed, 2000.0 + (extra - 1) as f32);
+    }
+}
@@ -4,14 +4,38 @@ mod ui;
 mod physics;
 mod render;
 mod camera; // New module
+mod analysis;
+mod overlay;
+mod share;
+mod generator;
+mod headless;
+mod evolution;
+mod cli;
+mod spatial;
+mod diagnostics;
+mod snapshot;
+mod sweep;
+mod scripting;
+mod history;
+mod field_grid;
+mod obstacles;
+mod collisions;
+mod thermostat;
+mod external;
+mod sources;
 
 use bevy::prelude::*;
-use components::*;
-use resources::*;
 use bevy_egui::EguiPlugin;
 use render::FieldVisPlugin;
+use physics::{PhysicsPlugin, PhysicsSet};
 
 fn main() {
+    // Any arguments select a headless CLI command
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(cli::run(&args));
+    }
+
     App::new()
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             primary_window: Some(Window {
@@ -24,19 +48,62 @@ fn main() {
         }))
         .add_plugins(EguiPlugin)
         .add_plugins(FieldVisPlugin)
-        .init_resource::<GlobalConstants>()
-        .init_resource::<AlchemyRules>()
+        .add_plugins(PhysicsPlugin)
+        .add_plugins(scripting::ScriptPlugin)
+        .init_resource::<analysis::ClusterSettings>()
+        .init_resource::<analysis::ClusterAnalysis>()
+        .init_resource::<analysis::StructureSettings>()
+        .init_resource::<analysis::StructureAnalysis>()
+        .init_resource::<overlay::FieldOverlaySettings>()
+        .init_resource::<overlay::FieldOverlay>()
+        .init_resource::<overlay::ForceOverlaySettings>()
+        .init_resource::<generator::RandomizerSettings>()
+        .init_resource::<share::RulesFile>()
+        .init_resource::<history::EditHistory>()
+        .init_resource::<obstacles::ObstacleEditor>()
         
         .add_systems(Startup, setup_camera)
         
-        // Physics & Logic
+        // Input & editing happen before the physics step...
         .add_systems(Update, (
+            ui::history_begin_system,
             camera::camera_control_system, // Add camera control
             ui::ui_system,
+            obstacles::obstacle_input_system,
             ui::sync_field_visualization, 
-            physics::particle_interaction_system,
-            physics::physics_integration_system,
-        ).chain())
+        ).chain().after(scripting::script_system).before(PhysicsSet))
+
+        // ...analysis and visualization after it
+        .add_systems(Update, (
+            render::attach_particle_visuals,
+            analysis::cluster_labeling_system,
+            analysis::structure_analysis_system,
+            // Windows and drawing are grouped, as a system tuple holds at most 20 entries
+            (
+                ui::render_settings_ui,
+                ui::field_overlay_ui,
+                ui::share_code_ui,
+                ui::rules_file_ui,
+                ui::randomizer_ui,
+                ui::clusters_ui,
+                ui::structure_ui,
+                ui::snapshot_ui,
+                ui::script_ui,
+                ui::history_ui,
+                ui::obstacles_ui,
+                ui::external_fields_ui,
+                ui::sources_ui,
+            ).chain(),
+            (
+                render::particle_appearance_system,
+                render::draw_trails_system,
+                overlay::field_overlay_system,
+                overlay::force_overlay_system,
+                obstacles::draw_obstacles_system,
+                external::draw_external_fields_system,
+                sources::draw_sources_system,
+            ).chain(),
+        ).chain().after(PhysicsSet))
         
         .run();
 }
@@ -0,0 +1,420 @@
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use serde::{Deserialize, Serialize};
+
+// Distance a bouncing particle is put back off the surface, so it does not hit it again straight away
+const CONTACT_OFFSET: f32 = 0.01;
+// Bounces resolved per particle and step; a particle still colliding after this stops at the last contact
+const MAX_BOUNCES: usize = 8;
+
+/// Geometry of a static obstacle, in world coordinates.
+/// Segments are open walls; rectangles, circles and polygons are solid.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ObstacleShape {
+    Segment { a: Vec2, b: Vec2 },
+    Rect { min: Vec2, max: Vec2 },
+    Circle { center: Vec2, radius: f32 },
+    // Closed; vertices in order, either winding
+    Polygon { points: Vec<Vec2> },
+}
+
+/// A static wall that particles bounce off. Saved in snapshots.
+#[derive(Component, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Obstacle {
+    pub shape: ObstacleShape,
+    // Fraction of the normal velocity kept on impact (1 = elastic, 0 = slides along)
+    pub restitution: f32,
+    // Field emitted from the obstacle's surface, with the curve of the type emitting that field.
+    // Only instantaneous fields; grid fields ignore obstacles.
+    #[serde(default)]
+    pub emits_field: Option<usize>,
+}
+
+impl ObstacleShape {
+    /// The closed outline of rectangles and polygons, the single edge of a segment.
+    fn edges(&self) -> Vec<(Vec2, Vec2)> {
+        match self {
+            ObstacleShape::Segment { a, b } => vec![(*a, *b)],
+            ObstacleShape::Rect { min, max } => {
+                let corners = [*min, Vec2::new(max.x, min.y), *max, Vec2::new(min.x, max.y)];
+                closed_edges(&corners)
+            }
+            ObstacleShape::Polygon { points } => closed_edges(points),
+            ObstacleShape::Circle { .. } => Vec::new(),
+        }
+    }
+
+    /// Earliest crossing of the path `from → to` with the boundary, as the fraction of the path
+    /// travelled and the boundary normal facing the side the path came from.
+    pub fn first_hit(&self, from: Vec2, to: Vec2) -> Option<(f32, Vec2)> {
+        if let ObstacleShape::Circle { center, radius } = self {
+            return circle_hit(from, to, *center, *radius);
+        }
+        self.edges().into_iter()
+            .filter_map(|(a, b)| segment_hit(from, to, a, b))
+            .min_by(|x, y| x.0.total_cmp(&y.0))
+    }
+
+    /// Whether `p` is inside a solid shape (never for segments).
+    pub fn contains(&self, p: Vec2) -> bool {
+        match self {
+            ObstacleShape::Segment { .. } => false,
+            ObstacleShape::Rect { min, max } => p.cmpge(*min).all() && p.cmple(*max).all(),
+            ObstacleShape::Circle { center, radius } => p.distance_squared(*center) <= radius * radius,
+            ObstacleShape::Polygon { points } => {
+                // Even-odd rule: count edge crossings of a ray towards +x
+                let mut inside = false;
+                for (a, b) in closed_edges(points) {
+                    if (a.y > p.y) != (b.y > p.y) {
+                        let x = a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x);
+                        if p.x < x { inside = !inside; }
+                    }
+                }
+                inside
+            }
+        }
+    }
+
+    /// The point of the obstacle nearest to `p` (`p` itself inside a solid shape).
+    pub fn closest_point(&self, p: Vec2) -> Vec2 {
+        if self.contains(p) { return p; }
+        match self {
+            ObstacleShape::Circle { center, radius } => *center + (p - *center).normalize_or_zero() * *radius,
+            ObstacleShape::Rect { min, max } => p.clamp(*min, *max),
+            _ => self.edges().into_iter()
+                .map(|(a, b)| closest_on_segment(p, a, b))
+                .min_by(|x, y| x.distance_squared(p).total_cmp(&y.distance_squared(p)))
+                .unwrap_or(p),
+        }
+    }
+
+    /// Axis-aligned bounding box as (min, max).
+    pub fn bounds(&self) -> (Vec2, Vec2) {
+        match self {
+            ObstacleShape::Segment { a, b } => (a.min(*b), a.max(*b)),
+            ObstacleShape::Rect { min, max } => (*min, *max),
+            ObstacleShape::Circle { center, radius } => (*center - Vec2::splat(*radius), *center + Vec2::splat(*radius)),
+            ObstacleShape::Polygon { points } => points.iter().fold(
+                (Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)),
+                |(lo, hi), p| (lo.min(*p), hi.max(*p)),
+            ),
+        }
+    }
+
+    /// Short description for lists.
+    pub fn describe(&self) -> String {
+        match self {
+            ObstacleShape::Segment { a, b } => format!("Segment, length {:.0}", a.distance(*b)),
+            ObstacleShape::Rect { min, max } => format!("Rectangle {:.0} × {:.0}", max.x - min.x, max.y - min.y),
+            ObstacleShape::Circle { radius, .. } => format!("Circle, radius {:.0}", radius),
+            ObstacleShape::Polygon { points } => format!("Polygon, {} vertices", points.len()),
+        }
+    }
+}
+
+fn closed_edges(points: &[Vec2]) -> Vec<(Vec2, Vec2)> {
+    if points.len() < 2 { return Vec::new(); }
+    (0..points.len()).map(|i| (points[i], points[(i + 1) % points.len()])).collect()
+}
+
+fn closest_on_segment(p: Vec2, a: Vec2, b: Vec2) -> Vec2 {
+    let edge = b - a;
+    let length_sq = edge.length_squared();
+    if length_sq == 0.0 { return a; }
+    a + edge * ((p - a).dot(edge) / length_sq).clamp(0.0, 1.0)
+}
+
+/// Crossing of the path `from → to` with the segment `a → b`.
+fn segment_hit(from: Vec2, to: Vec2, a: Vec2, b: Vec2) -> Option<(f32, Vec2)> {
+    let d = to - from;
+    let e = b - a;
+    let denom = d.perp_dot(e);
+    // Parallel paths slide along without crossing
+    if denom.abs() < f32::EPSILON { return None; }
+
+    // from + t·d = a + s·e
+    let w = a - from;
+    let t = w.perp_dot(e) / denom;
+    let s = w.perp_dot(d) / denom;
+    if !(0.0..=1.0).contains(&t) || !(0.0..=1.0).contains(&s) { return None; }
+
+    let normal = e.perp().normalize_or_zero();
+    Some((t, if normal.dot(d) > 0.0 { -normal } else { normal }))
+}
+
+/// Crossing of the path `from → to` with a circle, entering from outside or leaving from inside.
+fn circle_hit(from: Vec2, to: Vec2, center: Vec2, radius: f32) -> Option<(f32, Vec2)> {
+    let d = to - from;
+    let f = from - center;
+    let a = d.length_squared();
+    if a == 0.0 { return None; }
+    let b = 2.0 * f.dot(d);
+    let c = f.length_squared() - radius * radius;
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 { return None; }
+
+    let root = discriminant.sqrt();
+    let t = if c > 0.0 { (-b - root) / (2.0 * a) } else { (-b + root) / (2.0 * a) };
+    if !(0.0..=1.0).contains(&t) { return None; }
+
+    let normal = (from + d * t - center).normalize_or_zero();
+    Some((t, if normal.dot(d) > 0.0 { -normal } else { normal }))
+}
+
+/// Moves a particle by `velocity * dt`, bouncing off every obstacle crossed on the way.
+/// Swept against the whole path, so fast particles cannot tunnel through thin walls.
+/// Returns the new position and velocity.
+pub fn move_with_collisions(mut pos: Vec2, mut velocity: Vec2, dt: f32, obstacles: &[&Obstacle]) -> (Vec2, Vec2) {
+    let mut remaining = dt;
+
+    for _ in 0..MAX_BOUNCES {
+        let to = pos + velocity * remaining;
+        let hit = obstacles.iter()
+            .filter_map(|o| o.shape.first_hit(pos, to).map(|(t, normal)| (t, normal, o.restitution)))
+            .min_by(|x, y| x.0.total_cmp(&y.0));
+        let Some((t, normal, restitution)) = hit else { return (to, velocity); };
+
+        pos = pos.lerp(to, t) + normal * CONTACT_OFFSET;
+        // Reverse (and scale) the normal component, keep the tangential one
+        velocity -= (1.0 + restitution) * velocity.dot(normal) * normal;
+        remaining *= 1.0 - t;
+    }
+
+    (pos, velocity)
+}
+
+/// What a left-click in the viewport does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ObstacleTool {
+    #[default]
+    Off,
+    Segment,
+    Rect,
+    Circle,
+    Polygon,
+    Erase,
+}
+
+impl ObstacleTool {
+    pub const ALL: [ObstacleTool; 6] = [
+        ObstacleTool::Off, ObstacleTool::Segment, ObstacleTool::Rect,
+        ObstacleTool::Circle, ObstacleTool::Polygon, ObstacleTool::Erase,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ObstacleTool::Off => "Off",
+            ObstacleTool::Segment => "Segment",
+            ObstacleTool::Rect => "Rectangle",
+            ObstacleTool::Circle => "Circle",
+            ObstacleTool::Polygon => "Polygon",
+            ObstacleTool::Erase => "Erase",
+        }
+    }
+}
+
+/// State of drawing obstacles in the viewport.
+#[derive(Resource)]
+pub struct ObstacleEditor {
+    pub tool: ObstacleTool,
+    // Applied to newly drawn obstacles
+    pub restitution: f32,
+    pub emits_field: Option<usize>,
+    // Where the current drag started (segments, rectangles, circles)
+    drag_start: Option<Vec2>,
+    // Vertices placed so far (polygons)
+    polygon: Vec<Vec2>,
+    // Cursor in world coordinates, for the preview
+    cursor: Option<Vec2>,
+}
+
+impl Default for ObstacleEditor {
+    fn default() -> Self {
+        Self {
+            tool: ObstacleTool::Off,
+            restitution: 1.0,
+            emits_field: None,
+            drag_start: None,
+            polygon: Vec::new(),
+            cursor: None,
+        }
+    }
+}
+
+impl ObstacleEditor {
+    /// Drops a half-drawn shape (e.g. when switching tools).
+    pub fn cancel(&mut self) {
+        self.drag_start = None;
+        self.polygon.clear();
+    }
+
+    fn shape_from_drag(&self, start: Vec2, end: Vec2) -> Option<ObstacleShape> {
+        // Ignore clicks without a drag
+        if start.distance(end) < 1.0 { return None; }
+        Some(match self.tool {
+            ObstacleTool::Segment => ObstacleShape::Segment { a: start, b: end },
+            ObstacleTool::Rect => ObstacleShape::Rect { min: start.min(end), max: start.max(end) },
+            ObstacleTool::Circle => ObstacleShape::Circle { center: start, radius: start.distance(end) },
+            _ => return None,
+        })
+    }
+
+    fn spawn(&self, commands: &mut Commands, shape: ObstacleShape) {
+        commands.spawn(Obstacle { shape, restitution: self.restitution, emits_field: self.emits_field });
+    }
+}
+
+/// Draws obstacles with the mouse: drag for segments, rectangles and circles (centre then radius),
+/// click vertices then right-click or Enter for polygons, click near an obstacle to erase it.
+/// Escape cancels the shape being drawn.
+#[allow(clippy::too_many_arguments)]
+pub fn obstacle_input_system(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut editor: ResMut<ObstacleEditor>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    windows: Query<&Window>,
+    camera_query: Query<(&Camera, &GlobalTransform, &OrthographicProjection)>,
+    obstacles: Query<(Entity, &Obstacle)>,
+) {
+    let Ok(window) = windows.get_single() else { return; };
+    let Ok((camera, camera_transform, projection)) = camera_query.get_single() else { return; };
+    editor.cursor = window.cursor_position()
+        .and_then(|cursor| camera.viewport_to_world_2d(camera_transform, cursor));
+
+    if editor.tool == ObstacleTool::Off { return; }
+    if keys.just_pressed(KeyCode::Escape) {
+        editor.cancel();
+    }
+    let Some(cursor) = editor.cursor else { return; };
+    // Clicks on the UI are not for the viewport
+    let over_ui = contexts.ctx_mut().is_pointer_over_area();
+
+    match editor.tool {
+        ObstacleTool::Off => {}
+        ObstacleTool::Segment | ObstacleTool::Rect | ObstacleTool::Circle => {
+            if mouse_buttons.just_pressed(MouseButton::Left) && !over_ui {
+                editor.drag_start = Some(cursor);
+            }
+            if mouse_buttons.just_released(MouseButton::Left) {
+                if let Some(shape) = editor.drag_start.take().and_then(|start| editor.shape_from_drag(start, cursor)) {
+                    editor.spawn(&mut commands, shape);
+                }
+            }
+        }
+        ObstacleTool::Polygon => {
+            if mouse_buttons.just_pressed(MouseButton::Left) && !over_ui {
+                editor.polygon.push(cursor);
+            }
+            let close = (mouse_buttons.just_pressed(MouseButton::Right) && !over_ui) || keys.just_pressed(KeyCode::Enter);
+            if close {
+                let points = std::mem::take(&mut editor.polygon);
+                if points.len() >= 3 {
+                    editor.spawn(&mut commands, ObstacleShape::Polygon { points });
+                }
+            }
+        }
+        ObstacleTool::Erase => {
+            if mouse_buttons.just_pressed(MouseButton::Left) && !over_ui {
+                // Within a few pixels, whatever the zoom
+                let reach = 8.0 * projection.scale;
+                let nearest = obstacles.iter()
+                    .map(|(entity, o)| (entity, o.shape.closest_point(cursor).distance(cursor)))
+                    .filter(|(_, distance)| *distance <= reach)
+                    .min_by(|a, b| a.1.total_cmp(&b.1));
+                if let Some((entity, _)) = nearest {
+                    commands.entity(entity).despawn();
+                }
+            }
+        }
+    }
+}
+
+/// Outlines every obstacle, plus the shape being drawn.
+pub fn draw_obstacles_system(
+    mut gizmos: Gizmos,
+    editor: Res<ObstacleEditor>,
+    obstacles: Query<&Obstacle>,
+) {
+    for obstacle in obstacles.iter() {
+        // Emitting obstacles are tinted so they are told apart from plain walls
+        let color = if obstacle.emits_field.is_some() { Color::rgb(1.0, 0.8, 0.3) } else { Color::rgb(0.8, 0.8, 0.8) };
+        draw_shape(&mut gizmos, &obstacle.shape, color);
+    }
+
+    let preview = Color::rgba(0.5, 1.0, 0.5, 0.8);
+    if let (Some(start), Some(cursor)) = (editor.drag_start, editor.cursor) {
+        if let Some(shape) = editor.shape_from_drag(start, cursor) {
+            draw_shape(&mut gizmos, &shape, preview);
+        }
+    }
+    if !editor.polygon.is_empty() {
+        let open = editor.polygon.iter().copied().chain(editor.cursor);
+        gizmos.linestrip_2d(open, preview);
+    }
+}
+
+fn draw_shape(gizmos: &mut Gizmos, shape: &ObstacleShape, color: Color) {
+    match shape {
+        ObstacleShape::Circle { center, radius } => {
+            gizmos.circle_2d(*center, *radius, color).segments(64);
+        }
+        _ => {
+            for (a, b) in shape.edges() {
+                gizmos.line_2d(a, b, color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wall(shape: ObstacleShape) -> Obstacle {
+        Obstacle { shape, restitution: 1.0, emits_field: None }
+    }
+
+    fn segment(a: (f32, f32), b: (f32, f32)) -> Obstacle {
+        wall(ObstacleShape::Segment { a: Vec2::new(a.0, a.1), b: Vec2::new(b.0, b.1) })
+    }
+
+    #[test]
+    fn fast_particles_bounce_off_thin_segments() {
+        let obstacle = segment((0.0, -50.0), (0.0, 50.0));
+        // The step is a million times the (zero) thickness of the wall
+        let (pos, velocity) = move_with_collisions(Vec2::new(-1.0, 0.0), Vec2::new(1.0e6, 10.0), 1.0, &[&obstacle]);
+        assert!(pos.x < 0.0, "tunnelled to {:?}", pos);
+        assert!(velocity.x < 0.0 && velocity.y == 10.0);
+        assert!((velocity.length() - Vec2::new(1.0e6, 10.0).length()).abs() < 1.0);
+    }
+
+    #[test]
+    fn fast_particles_bounce_off_circles() {
+        let obstacle = wall(ObstacleShape::Circle { center: Vec2::ZERO, radius: 10.0 });
+        let (pos, velocity) = move_with_collisions(Vec2::new(-50.0, 1.0), Vec2::new(1.0e6, 0.0), 1.0, &[&obstacle]);
+        assert!(pos.x < -10.0, "ended at {:?}", pos);
+        assert!(!obstacle.shape.contains(pos));
+        assert!(velocity.x < 0.0);
+    }
+
+    #[test]
+    fn fast_particles_stay_inside_a_concave_corner() {
+        let floor = segment((0.0, 0.0), (100.0, 0.0));
+        let side = segment((0.0, 0.0), (0.0, 100.0));
+        let (pos, velocity) = move_with_collisions(Vec2::new(10.0, 5.0), Vec2::new(-1.0e5, -1.2e5), 1.0, &[&floor, &side]);
+        assert!(pos.x > 0.0 && pos.y > 0.0, "escaped the corner to {:?}", pos);
+        assert!(velocity.x > 0.0 && velocity.y > 0.0);
+    }
+
+    #[test]
+    fn particles_stop_cleanly_after_the_bounce_limit() {
+        // Two walls one unit apart: a fast particle would bounce between them far more than MAX_BOUNCES times
+        let left = segment((0.0, -10.0), (0.0, 10.0));
+        let right = segment((1.0, -10.0), (1.0, 10.0));
+        let (pos, velocity) = move_with_collisions(Vec2::new(0.5, 0.0), Vec2::new(1.0e6, 0.0), 1.0, &[&left, &right]);
+        assert!(pos.is_finite() && pos.x > 0.0 && pos.x < 1.0, "ended at {:?}", pos);
+        assert_eq!(velocity.length(), 1.0e6);
+    }
+}
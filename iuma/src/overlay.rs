@@ -0,0 +1,370 @@
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::render::render_asset::RenderAssetUsages;
+use crate::components::*;
+use crate::resources::*;
+use crate::render::ColorRamp;
+use crate::physics;
+use crate::field_grid::FieldGrids;
+use crate::obstacles::Obstacle;
+use crate::external::ExternalField;
+
+/// Settings for the aggregate field heatmap.
+#[derive(Resource)]
+pub struct FieldOverlaySettings {
+    pub enabled: bool,
+    // Which field type (as in `ParticleTypeDefinition::emits_field`) to show
+    pub field_id: usize,
+    // Grid cells along the longer side of the view
+    pub resolution: usize,
+    // If true, the colour range tracks the largest |value| in view
+    pub auto_range: bool,
+    // |value| mapped to the ends of the diverging ramp
+    pub range: f32,
+    pub isolines: bool,
+    pub isoline_count: usize,
+}
+
+impl Default for FieldOverlaySettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            field_id: 0,
+            resolution: 160,
+            auto_range: true,
+            range: 1000.0,
+            isolines: false,
+            isoline_count: 8,
+        }
+    }
+}
+
+/// CPU field grid and the texture/sprite showing it.
+#[derive(Resource, Default)]
+pub struct FieldOverlay {
+    pub image: Option<Handle<Image>>,
+    pub sprite: Option<Entity>,
+    pub width: usize,
+    pub height: usize,
+    // Row-major, row 0 at the bottom of the view (world +y up)
+    pub values: Vec<f32>,
+}
+
+/// Marks the sprite that displays the field heatmap
+#[derive(Component)]
+pub struct FieldOverlaySprite;
+
+/// Superposes the chosen field of every emitter onto a grid covering the view (or samples its
+/// concentration grid, for grid fields), then writes it into a texture with a diverging colormap (blue < 0 < red).
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn field_overlay_system(
+    mut commands: Commands,
+    settings: Res<FieldOverlaySettings>,
+    mut overlay: ResMut<FieldOverlay>,
+    mut images: ResMut<Assets<Image>>,
+    alchemy: Res<AlchemyRules>,
+    grids: Res<FieldGrids>,
+    camera_query: Query<(&Transform, &OrthographicProjection), With<Camera>>,
+    particle_query: Query<(&Transform, &ParticleTypeID), (With<Particle>, Without<Quarantined>)>,
+    obstacle_query: Query<&Obstacle>,
+    mut sprite_query: Query<(&mut Transform, &mut Sprite, &mut Visibility), (With<FieldOverlaySprite>, Without<Camera>, Without<Particle>)>,
+) {
+    if !settings.enabled {
+        if let Some(entity) = overlay.sprite {
+            if let Ok((_, _, mut visibility)) = sprite_query.get_mut(entity) {
+                *visibility = Visibility::Hidden;
+            }
+        }
+        return;
+    }
+
+    let Ok((camera_transform, projection)) = camera_query.get_single() else { return; };
+    let view_size = projection.area.size();
+    let view_min = camera_transform.translation.truncate() + projection.area.min;
+    if view_size.x <= 0.0 || view_size.y <= 0.0 { return; }
+
+    // 1. Grid dimensions follow the view aspect ratio
+    let resolution = settings.resolution.max(2);
+    let cell = view_size.x.max(view_size.y) / resolution as f32;
+    let width = ((view_size.x / cell).ceil() as usize).max(2);
+    let height = ((view_size.y / cell).ceil() as usize).max(2);
+
+    // 2. Splat every emitter of the chosen field into the cells within its radius
+    //    (a grid field is already stored as a grid, so it is only resampled)
+    let mut values = std::mem::take(&mut overlay.values);
+    values.clear();
+    values.resize(width * height, 0.0);
+
+    let grid_field = grids.grids.contains_key(&settings.field_id);
+    if grid_field {
+        for y in 0..height {
+            for x in 0..width {
+                let cell_center = view_min + Vec2::new(x as f32 + 0.5, y as f32 + 0.5) * cell;
+                values[y * width + x] = grids.sample(settings.field_id, cell_center);
+            }
+        }
+    }
+
+    for (transform, type_id) in particle_query.iter() {
+        let Some(def) = alchemy.particle_types.get(type_id.0) else { continue; };
+        if grid_field || def.emits_field != Some(settings.field_id) { continue; }
+
+        let shape = &def.emission_shape;
+        let pos = transform.translation.truncate();
+        let local = (pos - view_min) / cell;
+        let reach = shape.max_radius / cell;
+
+        let x0 = (local.x - reach).floor().max(0.0) as usize;
+        let y0 = (local.y - reach).floor().max(0.0) as usize;
+        let x1 = ((local.x + reach).ceil().max(0.0) as usize).min(width);
+        let y1 = ((local.y + reach).ceil().max(0.0) as usize).min(height);
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let cell_center = view_min + Vec2::new(x as f32 + 0.5, y as f32 + 0.5) * cell;
+                values[y * width + x] += shape.sample(cell_center.distance(pos));
+            }
+        }
+    }
+
+    // Obstacles emit from their nearest point
+    let obstacle_shape = alchemy.field_shape(settings.field_id).filter(|_| !grid_field);
+    if let Some(shape) = obstacle_shape {
+        for obstacle in obstacle_query.iter().filter(|o| o.emits_field == Some(settings.field_id)) {
+            let (min, max) = obstacle.shape.bounds();
+            let lo = ((min - view_min) / cell - shape.max_radius / cell).floor().max(Vec2::ZERO);
+            let hi = ((max - view_min) / cell + shape.max_radius / cell).ceil().max(Vec2::ZERO);
+
+            for y in (lo.y as usize)..(hi.y as usize).min(height) {
+                for x in (lo.x as usize)..(hi.x as usize).min(width) {
+                    let cell_center = view_min + Vec2::new(x as f32 + 0.5, y as f32 + 0.5) * cell;
+                    values[y * width + x] += shape.sample(obstacle.shape.closest_point(cell_center).distance(cell_center));
+                }
+            }
+        }
+    }
+
+    let range = if settings.auto_range {
+        values.iter().fold(0.0_f32, |m, v| m.max(v.abs())).max(f32::EPSILON)
+    } else {
+        settings.range.max(f32::EPSILON)
+    };
+
+    // 3. Colorize. Isolines are drawn where the contour band changes between neighbours.
+    let mut data = vec![0u8; width * height * 4];
+    let band_step = 2.0 * range / settings.isoline_count.max(1) as f32;
+    let band = |v: f32| (v / band_step).floor() as i32;
+
+    for y in 0..height {
+        for x in 0..width {
+            let v = values[y * width + x];
+            let [r, g, b] = ColorRamp::Coolwarm.sample(0.5 + 0.5 * v / range);
+            // Fade out where the field is (almost) zero so the void stays dark
+            let mut alpha = (v.abs() / range).sqrt().clamp(0.0, 1.0) * 0.85;
+            let mut rgb = [r, g, b];
+
+            if settings.isolines {
+                let here = band(v);
+                let right = if x + 1 < width { band(values[y * width + x + 1]) } else { here };
+                let up = if y + 1 < height { band(values[(y + 1) * width + x]) } else { here };
+                if here != right || here != up {
+                    rgb = [1.0, 1.0, 1.0];
+                    alpha = 0.9;
+                }
+            }
+
+            // Texture rows go top to bottom, grid rows bottom to top
+            let idx = ((height - 1 - y) * width + x) * 4;
+            data[idx] = (rgb[0] * 255.0) as u8;
+            data[idx + 1] = (rgb[1] * 255.0) as u8;
+            data[idx + 2] = (rgb[2] * 255.0) as u8;
+            data[idx + 3] = (alpha * 255.0) as u8;
+        }
+    }
+
+    overlay.values = values;
+    overlay.width = width;
+    overlay.height = height;
+
+    // 4. Upload (recreate the image when the grid size changes)
+    let size = Extent3d { width: width as u32, height: height as u32, depth_or_array_layers: 1 };
+    let existing = overlay.image.as_ref()
+        .and_then(|handle| images.get_mut(handle))
+        .filter(|image| image.texture_descriptor.size == size);
+    match existing {
+        Some(image) => image.data = data,
+        None => {
+            let image = Image::new(
+                size,
+                TextureDimension::D2,
+                data,
+                TextureFormat::Rgba8UnormSrgb,
+                RenderAssetUsages::RENDER_WORLD | RenderAssetUsages::MAIN_WORLD,
+            );
+            let handle = images.add(image);
+            if let Some(old) = overlay.image.replace(handle.clone()) {
+                images.remove(&old);
+            }
+            if let Some(entity) = overlay.sprite.take() {
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+
+    // 5. Keep the sprite stretched over the view, behind particles and field halos
+    let covered = Vec2::new(width as f32, height as f32) * cell;
+    let center = view_min + covered / 2.0;
+    match overlay.sprite.and_then(|e| sprite_query.get_mut(e).ok()) {
+        Some((mut transform, mut sprite, mut visibility)) => {
+            transform.translation = center.extend(-0.5);
+            sprite.custom_size = Some(covered);
+            *visibility = Visibility::Visible;
+        }
+        None if overlay.sprite.is_none() => {
+            let entity = commands.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        custom_size: Some(covered),
+                        ..default()
+                    },
+                    texture: overlay.image.clone().unwrap_or_default(),
+                    transform: Transform::from_translation(center.extend(-0.5)),
+                    ..default()
+                },
+                FieldOverlaySprite,
+            )).id();
+            overlay.sprite = Some(entity);
+        }
+        None => {}
+    }
+}
+
+/// Settings for the force debugging overlays.
+#[derive(Resource)]
+pub struct ForceOverlaySettings {
+    // Arrow of the last net force on each particle
+    pub particle_forces: bool,
+    // Force a test particle of `test_type` would feel, sampled on a grid
+    pub vector_field: bool,
+    // Streamlines through that vector field
+    pub streamlines: bool,
+    pub test_type: usize,
+    // World units between grid samples / streamline seeds
+    pub grid_spacing: f32,
+    // Arrow length in world units per unit force
+    pub arrow_scale: f32,
+    pub streamline_steps: usize,
+}
+
+impl Default for ForceOverlaySettings {
+    fn default() -> Self {
+        Self {
+            particle_forces: false,
+            vector_field: false,
+            streamlines: false,
+            test_type: 0,
+            grid_spacing: 40.0,
+            arrow_scale: 0.02,
+            streamline_steps: 60,
+        }
+    }
+}
+
+/// Draws net force arrows, the sampled test-particle force field and its streamlines.
+/// The test particle feels the forces a particle placed there would feel on the next step: it uses the
+/// physics' own pair evaluation (retarded fields and action = reaction included) and `field_force_at`.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn force_overlay_system(
+    mut gizmos: Gizmos,
+    settings: Res<ForceOverlaySettings>,
+    alchemy: Res<AlchemyRules>,
+    global_consts: Res<GlobalConstants>,
+    grids: Res<FieldGrids>,
+    time: Res<Time>,
+    camera_query: Query<(&Transform, &OrthographicProjection), With<Camera>>,
+    particle_query: Query<(Entity, &Transform, &ParticleTypeID, &NetForce), (With<Particle>, Without<Quarantined>)>,
+    history_query: Query<&PositionHistory>,
+    obstacle_query: Query<&Obstacle>,
+    external_query: Query<&ExternalField>,
+) {
+    if settings.particle_forces {
+        for (_, transform, _, force) in particle_query.iter() {
+            let start = transform.translation.truncate();
+            let end = start + force.0 * settings.arrow_scale;
+            if start.distance_squared(end) > 1.0 {
+                gizmos.arrow_2d(start, end, Color::YELLOW);
+            }
+        }
+    }
+
+    if !settings.vector_field && !settings.streamlines { return; }
+    if settings.test_type >= alchemy.particle_types.len() { return; }
+    let Ok((camera_transform, projection)) = camera_query.get_single() else { return; };
+
+    let bodies: Vec<(Vec2, usize)> = particle_query.iter()
+        .map(|(_, t, pid, _)| (t.translation.truncate(), pid.0))
+        .collect();
+    let emitters = physics::particle_emitters(
+        particle_query.iter().map(|(e, t, pid, _)| (e, t.translation.truncate(), pid.0)),
+        &history_query,
+        physics::retardation_step(&global_consts, &time),
+        &alchemy,
+    );
+    let obstacle_emitters: Vec<physics::Emitter> = physics::obstacle_emitters(obstacle_query.iter(), &alchemy).collect();
+    let externals: Vec<&ExternalField> = external_query.iter().collect();
+    let force_at = |pos: Vec2| {
+        physics::test_particle_force(pos, settings.test_type, &bodies, &emitters, global_consts.reciprocity, &alchemy)
+            + physics::field_force_at(pos, settings.test_type, &obstacle_emitters, &grids, &externals, &alchemy)
+    };
+
+    let view_min = camera_transform.translation.truncate() + projection.area.min;
+    let view_max = camera_transform.translation.truncate() + projection.area.max;
+    // Keep the sample count bounded when zoomed out
+    let spacing = settings.grid_spacing.max(projection.area.width().max(projection.area.height()) / 100.0);
+
+    let mut y = view_min.y + spacing / 2.0;
+    while y < view_max.y {
+        let mut x = view_min.x + spacing / 2.0;
+        while x < view_max.x {
+            let seed = Vec2::new(x, y);
+
+            if settings.vector_field {
+                let force = force_at(seed);
+                let length = force.length();
+                if length > f32::EPSILON {
+                    // Arrows are capped at one grid cell so the field stays readable
+                    let arrow = force / length * (length * settings.arrow_scale).min(spacing * 0.9);
+                    gizmos.arrow_2d(seed, seed + arrow, Color::rgba(0.6, 0.9, 1.0, 0.7));
+                }
+            }
+
+            if settings.streamlines {
+                let line = trace_streamline(seed, spacing * 0.25, settings.streamline_steps, &force_at);
+                if line.len() > 1 {
+                    gizmos.linestrip_2d(line, Color::rgba(1.0, 1.0, 1.0, 0.35));
+                }
+            }
+
+            x += spacing;
+        }
+        y += spacing;
+    }
+}
+
+/// Follows the force direction from `seed` with fixed-length midpoint (RK2) steps.
+fn trace_streamline(seed: Vec2, step: f32, max_steps: usize, force_at: &impl Fn(Vec2) -> Vec2) -> Vec<Vec2> {
+    let mut points = vec![seed];
+    let mut pos = seed;
+
+    for _ in 0..max_steps {
+        let k1 = force_at(pos).normalize_or_zero();
+        if k1 == Vec2::ZERO { break; }
+        let k2 = force_at(pos + k1 * step * 0.5).normalize_or_zero();
+        if k2 == Vec2::ZERO { break; }
+
+        pos += k2 * step;
+        points.push(pos);
+    }
+
+    points
+}
@@ -1,98 +1,394 @@
 use bevy::prelude::*;
 use crate::components::*;
 use crate::resources::*;
+use crate::diagnostics::{self, SimDiagnostics};
+use crate::field_grid::{self, FieldGrids};
+use crate::obstacles::{self, Obstacle, ObstacleShape};
+use crate::collisions;
+use crate::thermostat::{self, ThermostatRng};
+use crate::external::ExternalField;
+use crate::sources::{self, SourceRng};
+
+/// Ordering label for the core simulation systems.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PhysicsSet;
+
+/// Core simulation systems, shared by the windowed app and headless runs.
+pub struct PhysicsPlugin;
+
+impl Plugin for PhysicsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GlobalConstants>()
+            .init_resource::<AlchemyRules>()
+            .init_resource::<TrailSettings>()
+            .init_resource::<SimDiagnostics>()
+            .init_resource::<FieldGrids>()
+            .init_resource::<ThermostatRng>()
+            .init_resource::<SourceRng>()
+            .add_systems(Update, (
+                quarantine_system,
+                momentum_sync_system,
+                field_grid::field_grid_system,
+                particle_interaction_system,
+                physics_integration_system,
+                collisions::collision_system,
+                thermostat::thermostat_system,
+                sources::source_sink_system,
+                diagnostics::diagnostics_system,
+            ).chain().in_set(PhysicsSet));
+    }
+}
+
+/// A particle (or obstacle) that emits a field, as seen by the force evaluation.
+pub struct Emitter<'a> {
+    pub pos: Vec2,
+    pub field_id: usize,
+    pub shape: &'a FieldShape,
+    // Set when fields propagate at light speed
+    pub retarded: Option<Retardation<'a>>,
+    // Obstacles emit from their nearest point rather than from `pos`
+    pub obstacle: Option<&'a ObstacleShape>,
+}
+
+/// What a receiver needs to see an emitter at its retarded position.
+pub struct Retardation<'a> {
+    pub history: &'a PositionHistory,
+    // Distance light travels in one step
+    pub light_step: f32,
+}
+
+// Cap on recorded history, bounding memory when C is tiny compared to the field radii
+const MAX_HISTORY_STEPS: usize = 1024;
+
+impl Emitter<'_> {
+    /// Where a receiver at `receiver` currently sees this emitter: at its position
+    /// distance / C ago. Solved by fixed-point iteration on the history.
+    pub fn apparent_position(&self, receiver: Vec2) -> Vec2 {
+        // Obstacles are static, so retardation does not matter for them
+        if let Some(obstacle) = self.obstacle { return obstacle.closest_point(receiver); }
+        let Some(retarded) = &self.retarded else { return self.pos; };
+        let history = &retarded.history.positions;
+
+        let mut pos = self.pos;
+        for _ in 0..2 {
+            let steps_ago = ((receiver - pos).length() / retarded.light_step).round() as usize;
+            pos = match steps_ago {
+                0 => self.pos,
+                // Before the history starts (just spawned, or beyond the cap): the oldest known position
+                k => history.get(k - 1).or(history.back()).copied().unwrap_or(self.pos),
+            };
+        }
+        pos
+    }
+}
+
+/// The emitter for a particle, if its type emits an instantaneous field.
+/// Grid fields act through `FieldGrids` instead.
+fn particle_emitter(pos: Vec2, type_id: usize, alchemy: &AlchemyRules) -> Option<Emitter<'_>> {
+    let p_def = alchemy.particle_types.get(type_id)?;
+    let field_id = p_def.emits_field?;
+    let instant = alchemy.field_shape(field_id).is_some_and(|shape| shape.mode == FieldMode::Instant);
+    instant.then_some(Emitter {
+        pos,
+        field_id,
+        shape: &p_def.emission_shape,
+        retarded: None,
+        obstacle: None,
+    })
+}
+
+/// The emitter of each `(entity, position, type)` particle, `None` where it emits no instantaneous field.
+/// With `light_step` (distance light travels per step) set, emitters are seen through their history.
+pub fn particle_emitters<'a>(
+    particles: impl Iterator<Item = (Entity, Vec2, usize)>,
+    histories: &'a Query<&PositionHistory>,
+    light_step: Option<f32>,
+    alchemy: &'a AlchemyRules,
+) -> Vec<Option<Emitter<'a>>> {
+    particles
+        .map(|(entity, pos, type_id)| {
+            let mut emitter = particle_emitter(pos, type_id, alchemy)?;
+            if let Some(light_step) = light_step {
+                emitter.retarded = histories.get(entity).ok().map(|history| Retardation { history, light_step });
+            }
+            Some(emitter)
+        })
+        .collect()
+}
+
+/// Distance light travels in one step, if fields are retarded.
+pub fn retardation_step(global_consts: &GlobalConstants, time: &Time) -> Option<f32> {
+    // Positions advance by the frame time (see the integration step), so the history does too
+    let light_step = global_consts.light_speed * time.delta_seconds() * global_consts.time_scale;
+    (global_consts.retarded_fields && light_step > 0.0).then_some(light_step)
+}
+
+/// The forces a pair of particles exert on each other, after `GlobalConstants::reciprocity`.
+pub fn pair_forces(
+    (pos_a, type_a, emitter_a): (Vec2, usize, Option<&Emitter>),
+    (pos_b, type_b, emitter_b): (Vec2, usize, Option<&Emitter>),
+    reciprocity: Reciprocity,
+    alchemy: &AlchemyRules,
+) -> (Vec2, Vec2) {
+    let on_a = emitter_b.map_or(Vec2::ZERO, |e| emitter_force(pos_a, type_a, e, alchemy));
+    let on_b = emitter_a.map_or(Vec2::ZERO, |e| emitter_force(pos_b, type_b, e, alchemy));
+    if on_a == Vec2::ZERO && on_b == Vec2::ZERO { return (Vec2::ZERO, Vec2::ZERO); }
+    reciprocity.apply(on_a, type_a, on_b, type_b)
+}
+
+/// Pair force on a particle of type `my_type` placed at `my_pos`, from the particles `bodies`
+/// (position and type) with their `emitters`. Its own field starts at `my_pos`, as for a new particle.
+pub fn test_particle_force(
+    my_pos: Vec2,
+    my_type: usize,
+    bodies: &[(Vec2, usize)],
+    emitters: &[Option<Emitter>],
+    reciprocity: Reciprocity,
+    alchemy: &AlchemyRules,
+) -> Vec2 {
+    let my_emitter = particle_emitter(my_pos, my_type, alchemy);
+    bodies.iter().zip(emitters)
+        .map(|(&(pos, type_id), emitter)| {
+            pair_forces((my_pos, my_type, my_emitter.as_ref()), (pos, type_id, emitter.as_ref()), reciprocity, alchemy).0
+        })
+        .sum()
+}
+
+/// Emitters for the obstacles that emit an instantaneous field, using the shape of that field.
+pub fn obstacle_emitters<'a>(
+    obstacles: impl Iterator<Item = &'a Obstacle>,
+    alchemy: &'a AlchemyRules,
+) -> impl Iterator<Item = Emitter<'a>> {
+    obstacles.filter_map(|obstacle| {
+        let field_id = obstacle.emits_field?;
+        let shape = alchemy.field_shape(field_id).filter(|shape| shape.mode == FieldMode::Instant)?;
+        let (min, max) = obstacle.shape.bounds();
+        Some(Emitter {
+            pos: (min + max) / 2.0,
+            field_id,
+            shape,
+            retarded: None,
+            obstacle: Some(&obstacle.shape),
+        })
+    })
+}
+
+/// Force of a single emitter on a particle of type `my_type` at `my_pos`.
+/// This is the single force evaluation shared by the physics and the force overlays.
+pub fn emitter_force(my_pos: Vec2, my_type: usize, emitter: &Emitter, alchemy: &AlchemyRules) -> Vec2 {
+    let weight = alchemy.interactions.get(&(my_type, emitter.field_id)).copied().unwrap_or(0.0);
+    if weight == 0.0 { return Vec2::ZERO; }
+
+    let delta = emitter.apparent_position(my_pos) - my_pos;
+
+    // Force = Direction * Strength * Weight, with the shape's softening close to the emitter
+    emitter.shape.force(delta) * weight
+}
+
+/// Field force felt by a particle of type `my_type` at `my_pos` from everything but other particles:
+/// obstacle `emitters`, grid fields and external fields. Particles act through `pair_forces`.
+pub fn field_force_at(
+    my_pos: Vec2,
+    my_type: usize,
+    emitters: &[Emitter],
+    grids: &FieldGrids,
+    externals: &[&ExternalField],
+    alchemy: &AlchemyRules,
+) -> Vec2 {
+    let mut total_force = externals.iter().map(|field| field.force_at(my_pos, my_type)).sum::<Vec2>();
+
+    // Grid fields push up their concentration gradient. Scaling by the field radius makes the force
+    // the concentration change across one radius, comparable to an instantaneous field's strength.
+    for field_id in grids.grids.keys() {
+        let weight = alchemy.interactions.get(&(my_type, *field_id)).copied().unwrap_or(0.0);
+        if weight == 0.0 { continue; }
+        let Some(shape) = alchemy.field_shape(*field_id) else { continue; };
+        total_force += grids.gradient(*field_id, my_pos) * shape.max_radius * weight;
+    }
+
+    for emitter in emitters {
+        total_force += emitter_force(my_pos, my_type, emitter, alchemy);
+    }
+
+    total_force
+}
+
+/// Quarantines particles the physics cannot handle (a type the rules no longer define,
+/// a non-positive mass, or a position or velocity that blew up), and releases them once valid again.
+#[allow(clippy::type_complexity)]
+pub fn quarantine_system(
+    mut commands: Commands,
+    query: Query<(Entity, &Transform, &Velocity, &Mass, &ParticleTypeID, Has<Quarantined>), With<Particle>>,
+    alchemy: Res<AlchemyRules>,
+) {
+    let num_types = alchemy.particle_types.len();
+    for (entity, transform, velocity, mass, type_id, quarantined) in query.iter() {
+        let valid = type_id.0 < num_types
+            && mass.0.is_finite() && mass.0 > 0.0
+            && transform.translation.is_finite()
+            && velocity.0.is_finite();
+        if valid == quarantined {
+            if valid {
+                commands.entity(entity).remove::<Quarantined>();
+            } else {
+                warn!("Quarantining particle {:?}: type {}, mass {}", entity, type_id.0, mass.0);
+                commands.entity(entity).insert(Quarantined);
+            }
+        }
+    }
+}
+
+/// Derives the momentum of new particles from their velocity, unless they were spawned with one (restored
+/// from a snapshot), in which case the velocity follows it. When the speed limit model or C changes, every
+/// particle's momentum is derived again (the velocity is what was set or seen, so it is the one kept).
+pub fn momentum_sync_system(
+    mut query: Query<(&mut Velocity, &Mass, &mut Momentum), With<Particle>>,
+    global_consts: Res<GlobalConstants>,
+    mut last_model: Local<Option<(SpeedLimit, f32)>>,
+) {
+    let (model, c) = (global_consts.speed_limit, global_consts.light_speed);
+    // Nothing to convert on the first run; every particle counts as new then
+    let model_changed = last_model.is_some_and(|last| last != (model, c));
+    *last_model = Some((model, c));
+
+    for (mut velocity, mass, mut momentum) in query.iter_mut() {
+        if momentum.is_added() && !model_changed && momentum.0 != Vec2::ZERO {
+            velocity.0 = model.velocity(momentum.0, mass.0, c);
+        } else if model_changed || momentum.is_added() {
+            velocity.0 = model.limit(velocity.0, c);
+            momentum.0 = model.momentum(velocity.0, mass.0, c);
+        }
+    }
+}
 
 /// Calculates forces and updates velocities based on "Field" interactions.
-/// MVP Version: Brute-force O(N^2) CPU calculation to simulate fields.
+/// Brute-force O(N^2) CPU calculation, visiting each pair of particles once. The pair forces can be
+/// made to obey action = reaction (`GlobalConstants::reciprocity`); the net momentum they add
+/// is recorded in the diagnostics either way.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 pub fn particle_interaction_system(
-    mut query: Query<(Entity, &mut Velocity, &Transform, &ParticleTypeID, &Mass)>,
+    mut query: Query<(Entity, &mut Velocity, &mut Momentum, &mut NetForce, &Transform, &ParticleTypeID, &Mass), Without<Quarantined>>,
+    history_query: Query<&PositionHistory>,
+    obstacle_query: Query<&Obstacle>,
+    external_query: Query<&ExternalField>,
     global_consts: Res<GlobalConstants>,
     alchemy: Res<AlchemyRules>,
+    grids: Res<FieldGrids>,
+    time: Res<Time>,
+    mut diagnostics: ResMut<SimDiagnostics>,
 ) {
-    // 1. Collect all particle positions first (to avoid borrowing issues)
-    // Store reference to the whole FieldShape to access LUT
-    let particles: Vec<(Entity, Vec3, Option<usize>, &FieldShape)> = query.iter()
-        .map(|(e, _, t, pid, _)| {
-            let p_def = &alchemy.particle_types[pid.0];
-            (
-                e, 
-                t.translation, 
-                p_def.emits_field, 
-                &p_def.emission_shape
-            )
-        })
+    // 1. Collect all positions and emitters first (to avoid borrowing issues)
+    let bodies: Vec<(Vec2, usize)> = query.iter()
+        .map(|(_, _, _, _, t, pid, _)| (t.translation.truncate(), pid.0))
         .collect();
+    let emitters = particle_emitters(
+        query.iter().map(|(e, _, _, _, t, pid, _)| (e, t.translation.truncate(), pid.0)),
+        &history_query,
+        retardation_step(&global_consts, &time),
+        &alchemy,
+    );
+    let obstacle_emitters: Vec<Emitter> = obstacle_emitters(obstacle_query.iter(), &alchemy).collect();
+    let externals: Vec<&ExternalField> = external_query.iter().collect();
 
-    // 2. Iterate and apply forces
-    for (entity, mut velocity, transform, type_id, mass) in query.iter_mut() {
-        let mut total_force = Vec2::ZERO;
-        let my_pos = transform.translation.truncate();
-        let my_type = type_id.0;
-
-        for (other_e, other_pos_3d, other_emits_field, field_shape) in &particles {
-            if entity == *other_e { continue; } 
+    // 2. Pair forces, each pair once
+    let mut forces = vec![Vec2::ZERO; bodies.len()];
+    let mut injected_force = Vec2::ZERO;
+    for i in 0..bodies.len() {
+        let (pos_i, type_i) = bodies[i];
+        for j in (i + 1)..bodies.len() {
+            let (pos_j, type_j) = bodies[j];
+            let (on_i, on_j) = pair_forces(
+                (pos_i, type_i, emitters[i].as_ref()),
+                (pos_j, type_j, emitters[j].as_ref()),
+                global_consts.reciprocity,
+                &alchemy,
+            );
+            if on_i == Vec2::ZERO && on_j == Vec2::ZERO { continue; }
 
-            let field_id = match other_emits_field {
-                Some(id) => id,
-                None => continue,
-            };
-
-            let weight = alchemy.interactions.get(&(my_type, *field_id)).copied().unwrap_or(0.0);
-            if weight == 0.0 { continue; }
-
-            let other_pos = other_pos_3d.truncate();
-            let delta = other_pos - my_pos;
-            let distance = delta.length();
-            
-            // Check radius
-            if distance < 0.1 || distance > field_shape.max_radius { continue; } 
-
-            let direction = delta / distance;
-
-            // --- CURVE LOOKUP ---
-            // Normalize distance to 0..1
-            let t = distance / field_shape.max_radius;
-            
-            // Map to LUT index (0..99)
-            // Clamp index to be safe
-            let lut_idx = ((t * (field_shape.lut.len() - 1) as f32) as usize).clamp(0, field_shape.lut.len() - 1);
-            
-            // Sample
-            let normalized_strength = field_shape.lut[lut_idx];
-            
-            // Apply scale
-            // Note: In 1/r models, strength is huge near 0. 
-            // Our curve returns 0..1 (or -1..1). 
-            // So we multiply by strength_scale.
-            let field_strength = normalized_strength * field_shape.strength_scale;
-
-            // Force = Direction * Strength * Weight
-            total_force += direction * field_strength * weight;
+            forces[i] += on_i;
+            forces[j] += on_j;
+            injected_force += on_i + on_j;
         }
+    }
 
-        let acceleration = total_force / mass.0;
-        let dt = 0.016 * global_consts.time_scale; 
+    // 3. Add obstacle, grid and external fields and apply
+    let dt = 0.016 * global_consts.time_scale;
+    for ((_, mut velocity, mut momentum, mut net_force, transform, type_id, mass), pair_force) in query.iter_mut().zip(forces) {
+        let total_force = pair_force + field_force_at(
+            transform.translation.truncate(),
+            type_id.0,
+            &obstacle_emitters,
+            &grids,
+            &externals,
+            &alchemy,
+        );
+        net_force.0 = total_force;
 
-        velocity.0 += acceleration * dt;
+        // The impulse goes into the momentum; the speed limit model turns that into a velocity below C
+        velocity.0 = global_consts.speed_limit.apply_impulse(&mut momentum.0, total_force * dt, mass.0, global_consts.light_speed);
     }
+
+    diagnostics.momentum_injection = injected_force * dt;
 }
 
-/// Applies velocity to position and enforces Light Speed
+/// Applies velocity to position, bouncing off obstacles, and keeps speeds within Light Speed.
+/// Also records trail and retarded-field history, since this is where positions change.
+#[allow(clippy::type_complexity)]
 pub fn physics_integration_system(
-    mut query: Query<(&mut Transform, &mut Velocity)>,
+    mut query: Query<(&mut Transform, &mut Velocity, &mut Momentum, &Mass, &ParticleTypeID, Option<&mut Trail>, Option<&mut PositionHistory>), Without<Quarantined>>,
+    obstacle_query: Query<&Obstacle>,
     global_consts: Res<GlobalConstants>,
+    alchemy: Res<AlchemyRules>,
+    trail_settings: Res<TrailSettings>,
     time: Res<Time>,
 ) {
     let dt = time.delta_seconds() * global_consts.time_scale;
     let c = global_consts.light_speed;
 
-    for (mut transform, mut velocity) in query.iter_mut() {
-        let speed = velocity.0.length();
-        if speed > c {
-            velocity.0 = velocity.0.normalize() * c;
+    // Enough history for light to cross the largest field
+    let history_len = if global_consts.retarded_fields && c * dt > 0.0 {
+        let max_radius = alchemy.particle_types.iter().map(|def| def.emission_shape.max_radius).fold(0.0, f32::max);
+        ((max_radius / (c * dt)).ceil() as usize + 1).min(MAX_HISTORY_STEPS)
+    } else {
+        0
+    };
+
+    let obstacles: Vec<&Obstacle> = obstacle_query.iter().collect();
+
+    // Share the global point budget evenly between particles
+    let particle_count = query.iter().len().max(1);
+    let trail_len = trail_settings.length.min(trail_settings.max_total_points / particle_count);
+
+    for (mut transform, mut velocity, mut momentum, mass, type_id, trail, history) in query.iter_mut() {
+        if let Some(mut history) = history {
+            if history_len > 0 {
+                history.positions.push_front(transform.translation.truncate());
+                history.positions.truncate(history_len);
+            } else if !history.positions.is_empty() {
+                history.positions.clear();
+            }
         }
 
-        transform.translation.x += velocity.0.x * dt;
-        transform.translation.y += velocity.0.y * dt;
+        // Safety net for velocities set from outside; the force step already keeps them below C
+        if velocity.0.length_squared() > c * c {
+            velocity.0 = global_consts.speed_limit.limit(velocity.0, c);
+        }
+
+        let (pos, new_velocity) = obstacles::move_with_collisions(transform.translation.truncate(), velocity.0, dt, &obstacles);
+        transform.translation.x = pos.x;
+        transform.translation.y = pos.y;
+        momentum.0 = global_consts.speed_limit.momentum_after(momentum.0, velocity.0, new_velocity, mass.0, c);
+        velocity.0 = new_velocity;
+
+        if let Some(mut trail) = trail {
+            if trail_settings.enabled && trail_settings.type_enabled(type_id.0) && trail_len > 0 {
+                trail.points.push_back(transform.translation.truncate());
+                while trail.points.len() > trail_len {
+                    trail.points.pop_front();
+                }
+            } else if !trail.points.is_empty() {
+                trail.points.clear();
+            }
+        }
     }
 }
\ No newline at end of file
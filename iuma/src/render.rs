@@ -1,12 +1,15 @@
 use bevy::prelude::*;
 use bevy::render::render_resource::{AsBindGroup, ShaderRef};
-use bevy::sprite::{Material2d, Material2dPlugin};
+use bevy::sprite::{Material2d, Material2dPlugin, MaterialMesh2dBundle, Mesh2dHandle};
+use crate::components::*;
+use crate::resources::*;
 
 pub struct FieldVisPlugin;
 
 impl Plugin for FieldVisPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(Material2dPlugin::<FieldMaterial>::default());
+        app.add_plugins(Material2dPlugin::<FieldMaterial>::default())
+            .init_resource::<RenderSettings>();
     }
 }
 
@@ -28,3 +31,263 @@ impl Material2d for FieldMaterial {
         "shaders/field_material.wgsl".into()
     }
 }
+
+/// Gives newly spawned particles their sprite and, for emitters, the field halo child.
+/// Keeping visuals out of `ParticleBundle` lets headless runs spawn the same particles.
+pub fn attach_particle_visuals(
+    mut commands: Commands,
+    alchemy: Res<AlchemyRules>,
+    query: Query<(Entity, &ParticleTypeID), Added<Particle>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<FieldMaterial>>,
+) {
+    for (entity, type_id) in query.iter() {
+        let Some(def) = alchemy.particle_types.get(type_id.0) else { continue; };
+
+        commands.entity(entity).insert((
+            Sprite {
+                color: def.default_color,
+                custom_size: Some(Vec2::new(4.0, 4.0)),
+                ..default()
+            },
+            Handle::<Image>::default(),
+            VisibilityBundle::default(),
+        ))
+        .with_children(|parent| {
+            if def.emits_field.is_some() {
+                let mesh_handle = meshes.add(Mesh::from(Circle::new(1.0))); 
+                let texture_handle = def.field_texture.clone().unwrap_or_default(); 
+                let intensity = (def.emission_shape.strength_scale / 1000.0).clamp(0.2, 2.0);
+
+                parent.spawn(MaterialMesh2dBundle {
+                    mesh: Mesh2dHandle(mesh_handle),
+                    material: materials.add(FieldMaterial {
+                        color: def.default_color,
+                        intensity,
+                        lut_texture: texture_handle,
+                    }),
+                    transform: Transform {
+                        translation: Vec3::new(0.0, 0.0, -0.1),
+                        scale: Vec3::new(def.emission_shape.max_radius, def.emission_shape.max_radius, 1.0),
+                        ..default()
+                    },
+                    ..default()
+                });
+            }
+        });
+    }
+}
+
+/// What quantity the particle sprite colour represents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    Type,
+    Speed,
+    Force,
+    KineticEnergy,
+    Cluster,
+}
+
+impl ColorMode {
+    pub const ALL: [ColorMode; 5] = [
+        ColorMode::Type,
+        ColorMode::Speed,
+        ColorMode::Force,
+        ColorMode::KineticEnergy,
+        ColorMode::Cluster,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ColorMode::Type => "Particle Type",
+            ColorMode::Speed => "Speed (|v| / C)",
+            ColorMode::Force => "Net Force",
+            ColorMode::KineticEnergy => "Kinetic Energy",
+            ColorMode::Cluster => "Cluster ID",
+        }
+    }
+}
+
+/// Colour ramps used by the scalar colour modes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorRamp {
+    Viridis,
+    Inferno,
+    Coolwarm,
+    Grayscale,
+}
+
+impl ColorRamp {
+    pub const ALL: [ColorRamp; 4] = [
+        ColorRamp::Viridis,
+        ColorRamp::Inferno,
+        ColorRamp::Coolwarm,
+        ColorRamp::Grayscale,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ColorRamp::Viridis => "Viridis",
+            ColorRamp::Inferno => "Inferno",
+            ColorRamp::Coolwarm => "Coolwarm",
+            ColorRamp::Grayscale => "Grayscale",
+        }
+    }
+
+    fn stops(&self) -> &'static [[f32; 3]] {
+        match self {
+            ColorRamp::Viridis => &[
+                [0.267, 0.005, 0.329],
+                [0.229, 0.322, 0.546],
+                [0.128, 0.567, 0.551],
+                [0.369, 0.789, 0.383],
+                [0.993, 0.906, 0.144],
+            ],
+            ColorRamp::Inferno => &[
+                [0.001, 0.000, 0.014],
+                [0.341, 0.062, 0.429],
+                [0.735, 0.216, 0.330],
+                [0.978, 0.557, 0.035],
+                [0.988, 0.998, 0.645],
+            ],
+            ColorRamp::Coolwarm => &[
+                [0.230, 0.299, 0.754],
+                [0.552, 0.690, 0.996],
+                [0.866, 0.866, 0.866],
+                [0.957, 0.604, 0.482],
+                [0.706, 0.016, 0.150],
+            ],
+            ColorRamp::Grayscale => &[
+                [0.1, 0.1, 0.1],
+                [1.0, 1.0, 1.0],
+            ],
+        }
+    }
+
+    /// Samples the ramp at `t` (clamped to 0..1) as linear interpolation between stops.
+    pub fn sample(&self, t: f32) -> [f32; 3] {
+        let stops = self.stops();
+        let t = if t.is_finite() { t.clamp(0.0, 1.0) } else { 0.0 };
+        let scaled = t * (stops.len() - 1) as f32;
+        let i = (scaled as usize).min(stops.len() - 2);
+        let f = scaled - i as f32;
+        let (a, b) = (stops[i], stops[i + 1]);
+        [
+            a[0] + (b[0] - a[0]) * f,
+            a[1] + (b[1] - a[1]) * f,
+            a[2] + (b[2] - a[2]) * f,
+        ]
+    }
+
+    pub fn color(&self, t: f32) -> Color {
+        let [r, g, b] = self.sample(t);
+        Color::rgb(r, g, b)
+    }
+}
+
+/// Visual settings for the particle sprites.
+#[derive(Resource)]
+pub struct RenderSettings {
+    pub color_mode: ColorMode,
+    pub color_ramp: ColorRamp,
+    // If true, the upper end of the ramp tracks the largest value in the scene
+    pub auto_range: bool,
+    // Value mapped to the top of the ramp (Force / Kinetic Energy modes)
+    pub range_max: f32,
+    pub base_size: f32,
+    pub size_by_mass: bool,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            color_mode: ColorMode::Type,
+            color_ramp: ColorRamp::Viridis,
+            auto_range: true,
+            range_max: 1000.0,
+            base_size: 4.0,
+            size_by_mass: false,
+        }
+    }
+}
+
+/// Deterministic, well-spread colour for a cluster index (golden-angle hue walk).
+pub fn cluster_color(id: usize) -> Color {
+    let hue = (id as f32 * 137.508) % 360.0;
+    Color::hsl(hue, 0.75, 0.6)
+}
+
+/// Recolours and resizes particle sprites according to `RenderSettings`.
+#[allow(clippy::type_complexity)]
+pub fn particle_appearance_system(
+    mut settings: ResMut<RenderSettings>,
+    global_consts: Res<GlobalConstants>,
+    alchemy: Res<AlchemyRules>,
+    mut query: Query<(&mut Sprite, &Velocity, &Momentum, &Mass, &ParticleTypeID, &NetForce, Option<&ClusterId>), With<Particle>>,
+) {
+    let c = global_consts.light_speed;
+
+    // Auto range: find the largest value of the active quantity first
+    if settings.auto_range {
+        let max_value = query.iter()
+            .map(|(_, _, momentum, mass, _, force, _)| match settings.color_mode {
+                ColorMode::Force => force.0.length(),
+                ColorMode::KineticEnergy => global_consts.speed_limit.kinetic_energy(momentum.0, mass.0, c),
+                _ => 0.0,
+            })
+            .fold(0.0_f32, f32::max);
+        if max_value > 0.0 && settings.range_max != max_value {
+            settings.range_max = max_value;
+        }
+    }
+
+    let range_max = settings.range_max.max(f32::EPSILON);
+
+    for (mut sprite, velocity, momentum, mass, type_id, force, cluster) in query.iter_mut() {
+        sprite.color = match settings.color_mode {
+            ColorMode::Type => alchemy.particle_types
+                .get(type_id.0)
+                .map(|def| def.default_color)
+                .unwrap_or(Color::WHITE),
+            ColorMode::Speed => settings.color_ramp.color(velocity.0.length() / c),
+            ColorMode::Force => settings.color_ramp.color(force.0.length() / range_max),
+            ColorMode::KineticEnergy => {
+                let energy = global_consts.speed_limit.kinetic_energy(momentum.0, mass.0, c);
+                settings.color_ramp.color(energy / range_max)
+            }
+            ColorMode::Cluster => cluster.map(|id| cluster_color(id.0)).unwrap_or(Color::WHITE),
+        };
+
+        let size = if settings.size_by_mass {
+            // Area proportional to mass
+            settings.base_size * mass.0.max(0.0).sqrt()
+        } else {
+            settings.base_size
+        };
+        // Never draw a particle smaller than its hard core
+        let core = alchemy.particle_types.get(type_id.0).map_or(0.0, |def| 2.0 * def.hard_core_radius);
+        let size = size.max(core);
+        sprite.custom_size = Some(Vec2::splat(size));
+    }
+}
+
+/// Draws particle trails as gizmo line strips that fade towards the tail.
+pub fn draw_trails_system(
+    mut gizmos: Gizmos,
+    trail_settings: Res<TrailSettings>,
+    query: Query<(&Trail, &Sprite), With<Particle>>,
+) {
+    if !trail_settings.enabled { return; }
+
+    for (trail, sprite) in query.iter() {
+        let count = trail.points.len();
+        if count < 2 { continue; }
+
+        // Trails take the sprite colour so they follow the active colour mode
+        let color = sprite.color;
+        gizmos.linestrip_gradient_2d(trail.points.iter().enumerate().map(|(i, &p)| {
+            let alpha = (i + 1) as f32 / count as f32;
+            (p, color.with_a(alpha * 0.8))
+        }));
+    }
+}
@@ -1,41 +1,397 @@
 use bevy::prelude::*;
 use std::collections::HashMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+use serde::{Deserialize, Serialize};
 
-#[derive(Resource)]
+// Values the editors (and scripts) may set
+pub const LIGHT_SPEED_RANGE: RangeInclusive<f32> = 100.0..=5000.0;
+pub const TIME_SCALE_RANGE: RangeInclusive<f32> = 0.0..=5.0;
+
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct GlobalConstants {
     pub light_speed: f32,
     pub time_scale: f32,
+    // How velocities approach light speed; rules saved before this existed used clamping
+    #[serde(default)]
+    pub speed_limit: SpeedLimit,
+    // Fields propagate at light speed: receivers feel emitters where they were distance / C ago
+    #[serde(default)]
+    pub retarded_fields: bool,
+    // Fraction of the approach speed kept when hard cores collide (1 = elastic, 0 = perfectly inelastic)
+    #[serde(default = "default_restitution")]
+    pub collision_restitution: f32,
+    // Whether pair forces are made equal and opposite (asymmetric matrices otherwise create momentum)
+    #[serde(default)]
+    pub reciprocity: Reciprocity,
+    // Temperature control; off by default, as the void has no friction
+    #[serde(default)]
+    pub thermostat: Thermostat,
 }
 
+fn default_restitution() -> f32 { 1.0 }
+
 impl Default for GlobalConstants {
     fn default() -> Self {
         Self {
             light_speed: 1000.0,
             time_scale: 1.0,
+            speed_limit: SpeedLimit::default(),
+            retarded_fields: false,
+            collision_restitution: default_restitution(),
+            reciprocity: Reciprocity::default(),
+            thermostat: Thermostat::default(),
+        }
+    }
+}
+
+/// How the universal speed limit C is enforced.
+///
+/// Forces change the particle's `Momentum`, which is unbounded; the velocity is derived from it,
+/// so in the smooth models |v| approaches C but never reaches it while p keeps growing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SpeedLimit {
+    // Newtonian motion, speeds above C cut back to C after the fact
+    #[default]
+    Clamp,
+    // Special relativity: p = γmv, so v = p / sqrt(m² + p²/c²)
+    Relativistic,
+    // Smooth saturation: p = mc·atanh(|v|/c), so |v| = c·tanh(|p| / mc)
+    Tanh,
+}
+
+// Velocities set from outside the force step are kept below this fraction of c in the smooth models,
+// so the momentum derived from them stays finite
+const MAX_SPEED_FRACTION: f32 = 0.9999;
+
+impl SpeedLimit {
+    pub const ALL: [SpeedLimit; 3] = [SpeedLimit::Clamp, SpeedLimit::Relativistic, SpeedLimit::Tanh];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SpeedLimit::Clamp => "Hard Clamp",
+            SpeedLimit::Relativistic => "Relativistic",
+            SpeedLimit::Tanh => "Tanh Saturation",
+        }
+    }
+
+    /// Momentum of a particle of mass `m` moving at `v` under this model. Only for velocities set
+    /// from outside the force step (spawning, collisions, thermostats): speeds are capped first.
+    pub fn momentum(&self, v: Vec2, m: f32, c: f32) -> Vec2 {
+        let speed = v.length();
+        if speed == 0.0 { return Vec2::ZERO; }
+        let beta = (speed / c).min(MAX_SPEED_FRACTION);
+        let direction = v / speed;
+        match self {
+            SpeedLimit::Clamp => v * m,
+            SpeedLimit::Relativistic => direction * m * beta * c / (1.0 - beta * beta).sqrt(),
+            SpeedLimit::Tanh => direction * m * c * beta.atanh(),
+        }
+    }
+
+    /// Velocity of a particle of mass `m` carrying momentum `p`. Never faster than `c`.
+    pub fn velocity(&self, p: Vec2, m: f32, c: f32) -> Vec2 {
+        match self {
+            SpeedLimit::Clamp => self.limit(p / m, c),
+            SpeedLimit::Relativistic => p / (m * m + p.length_squared() / (c * c)).sqrt(),
+            SpeedLimit::Tanh => {
+                let magnitude = p.length();
+                if magnitude == 0.0 { return Vec2::ZERO; }
+                p / magnitude * c * (magnitude / (m * c)).tanh()
+            }
+        }
+    }
+
+    /// Adds the impulse `impulse` (force times dt) to the momentum `p` and returns the new velocity.
+    /// The hard clamp throws away the momentum beyond C; the smooth models keep all of it.
+    pub fn apply_impulse(&self, p: &mut Vec2, impulse: Vec2, m: f32, c: f32) -> Vec2 {
+        *p += impulse;
+        let v = self.velocity(*p, m, c);
+        if *self == SpeedLimit::Clamp {
+            *p = v * m;
+        }
+        v
+    }
+
+    /// Momentum after something other than a force changed the velocity from `old_v` to `new_v`.
+    /// A pure change of direction (an elastic bounce) turns `p` and keeps its size, so fast particles
+    /// lose nothing to the speed cap of `momentum`; anything else derives it from `new_v`.
+    pub fn momentum_after(&self, p: Vec2, old_v: Vec2, new_v: Vec2, m: f32, c: f32) -> Vec2 {
+        if new_v == old_v { return p; }
+        let (old_speed, new_speed) = (old_v.length(), new_v.length());
+        if old_speed > 0.0 && (new_speed - old_speed).abs() <= old_speed * 1e-5 {
+            new_v / new_speed * p.length()
+        } else {
+            self.momentum(new_v, m, c)
+        }
+    }
+
+    /// Cuts a velocity set from outside the physics (spawning, scripts) back to the allowed range.
+    pub fn limit(&self, v: Vec2, c: f32) -> Vec2 {
+        let max_speed = match self {
+            SpeedLimit::Clamp => c,
+            _ => c * MAX_SPEED_FRACTION,
+        };
+        v.clamp_length_max(max_speed)
+    }
+
+    /// Kinetic energy of a particle carrying momentum `p` (the work done to give it that momentum).
+    pub fn kinetic_energy(&self, p: Vec2, m: f32, c: f32) -> f32 {
+        let p_sq = p.length_squared();
+        match self {
+            SpeedLimit::Clamp => 0.5 * p_sq / m,
+            // (γ - 1)mc² = sqrt(p²c² + m²c⁴) - mc², written without the cancellation
+            SpeedLimit::Relativistic => p_sq / (m + (m * m + p_sq / (c * c)).sqrt()),
+            // ∫ v dp = mc²·ln cosh(|p| / mc), written so cosh cannot overflow
+            SpeedLimit::Tanh => {
+                let x = p_sq.sqrt() / (m * c);
+                m * c * c * (x + (-2.0 * x).exp().ln_1p() - std::f32::consts::LN_2)
+            }
         }
     }
 }
 
-#[derive(Debug, Clone)]
+/// Whether pair forces obey Newton's third law.
+///
+/// The force on A from B uses weight(A's type, B's field) and B's curve, the force on B from A
+/// the reverse, so in general they differ and the pair gains net momentum ("chasing").
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Reciprocity {
+    // Each particle feels its own force; momentum is not conserved for asymmetric rules
+    #[default]
+    Off,
+    // Both feel the average of the two, as an equal and opposite pair
+    Average,
+    // The particle of the lower type index (the upper triangle of the matrix) decides, the other feels the reaction
+    Mirror,
+}
+
+impl Reciprocity {
+    pub const ALL: [Reciprocity; 3] = [Reciprocity::Off, Reciprocity::Average, Reciprocity::Mirror];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Reciprocity::Off => "Off (as defined)",
+            Reciprocity::Average => "Average",
+            Reciprocity::Mirror => "Mirror Upper Triangle",
+        }
+    }
+
+    /// The forces applied to a pair, given the forces `on_a` (on a particle of `type_a`)
+    /// and `on_b` each would feel from the other.
+    pub fn apply(&self, on_a: Vec2, type_a: usize, on_b: Vec2, type_b: usize) -> (Vec2, Vec2) {
+        let shared = match self {
+            Reciprocity::Off => return (on_a, on_b),
+            Reciprocity::Average => (on_a - on_b) / 2.0,
+            Reciprocity::Mirror if type_a <= type_b => on_a,
+            Reciprocity::Mirror => -on_b,
+        };
+        (shared, -shared)
+    }
+}
+
+/// How a thermostat acts on velocities (see `thermostat::thermostat_system`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ThermostatKind {
+    #[default]
+    Off,
+    // Scales velocities to the target temperature exactly, every step
+    Rescale,
+    // Scales velocities towards the target temperature at rate `coupling` (1 / relaxation time)
+    Berendsen,
+    // Friction `coupling` plus random kicks balancing it at the target temperature
+    Langevin,
+    // Friction `coupling` only; cools towards rest
+    Drag,
+}
+
+impl ThermostatKind {
+    pub const ALL: [ThermostatKind; 5] = [
+        ThermostatKind::Off, ThermostatKind::Rescale, ThermostatKind::Berendsen,
+        ThermostatKind::Langevin, ThermostatKind::Drag,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ThermostatKind::Off => "Off (frictionless)",
+            ThermostatKind::Rescale => "Velocity Rescaling",
+            ThermostatKind::Berendsen => "Berendsen",
+            ThermostatKind::Langevin => "Langevin",
+            ThermostatKind::Drag => "Linear Drag",
+        }
+    }
+
+    pub fn uses_target(&self) -> bool {
+        matches!(self, ThermostatKind::Rescale | ThermostatKind::Berendsen | ThermostatKind::Langevin)
+    }
+
+    pub fn uses_coupling(&self) -> bool {
+        matches!(self, ThermostatKind::Berendsen | ThermostatKind::Langevin | ThermostatKind::Drag)
+    }
+}
+
+/// Temperature control. Temperature is the mean kinetic energy ½mv² per particle
+/// (kT with two degrees of freedom).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Thermostat {
+    pub kind: ThermostatKind,
+    pub target_temperature: f32,
+    // Rate in 1/s: how fast Berendsen relaxes, the friction of Langevin and drag
+    pub coupling: f32,
+    // Per particle type on/off, indexed by ParticleTypeID (missing entries count as on)
+    pub per_type: Vec<bool>,
+    // Seed for the Langevin noise
+    pub seed: u64,
+}
+
+impl Default for Thermostat {
+    fn default() -> Self {
+        Self {
+            kind: ThermostatKind::Off,
+            target_temperature: 1000.0,
+            coupling: 1.0,
+            per_type: Vec::new(),
+            seed: 0,
+        }
+    }
+}
+
+impl Thermostat {
+    pub fn type_enabled(&self, type_id: usize) -> bool {
+        self.per_type.get(type_id).copied().unwrap_or(true)
+    }
+}
+
+/// Settings for the fading motion trails behind particles.
+#[derive(Resource)]
+pub struct TrailSettings {
+    pub enabled: bool,
+    // Trail length in integration steps
+    pub length: usize,
+    // Global budget of trail points shared by all particles
+    pub max_total_points: usize,
+    // Per particle type on/off, indexed by ParticleTypeID (missing entries count as on)
+    pub per_type: Vec<bool>,
+}
+
+impl Default for TrailSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            length: 60,
+            max_total_points: 50_000,
+            per_type: Vec::new(),
+        }
+    }
+}
+
+impl TrailSettings {
+    pub fn type_enabled(&self, type_id: usize) -> bool {
+        self.per_type.get(type_id).copied().unwrap_or(true)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CurvePoint {
     pub x: f32, // Normalized Distance (0.0 to 1.0)
     pub y: f32, // Normalized Strength (-1.0 to 1.0)
 }
 
+/// How a field reaches its receivers.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum FieldMode {
+    // Evaluated from the emitter positions every step, through the curve
+    #[default]
+    Instant,
+    // A persistent concentration grid (see `field_grid`): emitters deposit into it, it diffuses
+    // (world units² per second) and decays (fraction per second), and receivers follow its gradient
+    Grid { diffusion: f32, decay: f32 },
+}
+
+impl FieldMode {
+    pub fn label(&self) -> &'static str {
+        match self {
+            FieldMode::Instant => "Instant (curve)",
+            FieldMode::Grid { .. } => "Grid (reaction-diffusion)",
+        }
+    }
+}
+
+/// What a field does to receivers very close to its emitter, where the direction is ill-defined.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Softening {
+    // No force closer than `distance`
+    Skip { distance: f32 },
+    // Closer than `distance`, the strength stays at the curve's value at `distance`
+    Clamp { distance: f32 },
+    // Plummer softening: the curve is read at sqrt(d² + length²) and the force fades to zero at the centre
+    Plummer { length: f32 },
+}
+
+// Rules saved before softening was configurable skipped everything closer than 0.1
+impl Default for Softening {
+    fn default() -> Self {
+        Softening::Skip { distance: 0.1 }
+    }
+}
+
+impl Softening {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Softening::Skip { .. } => "Skip",
+            Softening::Clamp { .. } => "Clamp",
+            Softening::Plummer { .. } => "Plummer",
+        }
+    }
+
+    /// The distance (Skip, Clamp) or length (Plummer) of the mode.
+    pub fn parameter(&self) -> f32 {
+        match *self {
+            Softening::Skip { distance } | Softening::Clamp { distance } => distance,
+            Softening::Plummer { length } => length,
+        }
+    }
+
+    pub fn parameter_mut(&mut self) -> &mut f32 {
+        match self {
+            Softening::Skip { distance } | Softening::Clamp { distance } => distance,
+            Softening::Plummer { length } => length,
+        }
+    }
+}
+
 /// Defines the shape of a field emitted by a particle.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FieldShape {
     pub max_radius: f32,
     pub strength_scale: f32, 
     
     // User defined points for the curve editor
     pub points: Vec<CurvePoint>,
+
+    // Rules saved before grid fields existed are instantaneous
+    #[serde(default)]
+    pub mode: FieldMode,
+
+    #[serde(default)]
+    pub softening: Softening,
     
     // Baked Lookup Table for fast physics
     // Size should be e.g., 100
+    // Not serialized: rebake with `AlchemyRules::bake_all` after loading.
+    #[serde(skip)]
     pub lut: Vec<f32>, 
 }
 
+// Equality ignores the LUT, which is derived from the other fields
+impl PartialEq for FieldShape {
+    fn eq(&self, other: &Self) -> bool {
+        let Self { max_radius, strength_scale, points, mode, softening, lut: _ } = self;
+        *max_radius == other.max_radius && *strength_scale == other.strength_scale && *points == other.points
+            && *mode == other.mode && *softening == other.softening
+    }
+}
+
 impl FieldShape {
     pub fn new_linear_falloff(radius: f32, strength: f32) -> Self {
         let mut shape = Self {
@@ -45,6 +401,8 @@ impl FieldShape {
                 CurvePoint { x: 0.0, y: 1.0 },
                 CurvePoint { x: 1.0, y: 0.0 },
             ],
+            mode: FieldMode::default(),
+            softening: Softening::default(),
             lut: Vec::new(),
         };
         shape.bake_lut();
@@ -67,6 +425,42 @@ impl FieldShape {
         }
     }
 
+    /// Field strength at `distance` from the emitter (LUT lookup times `strength_scale`).
+    /// Returns 0 outside `max_radius`.
+    pub fn sample(&self, distance: f32) -> f32 {
+        if distance > self.max_radius || self.lut.is_empty() { return 0.0; }
+
+        // Normalize distance to 0..1 and map to LUT index (0..99)
+        let t = distance / self.max_radius;
+        let lut_idx = ((t * (self.lut.len() - 1) as f32) as usize).clamp(0, self.lut.len() - 1);
+
+        // Note: In 1/r models, strength is huge near 0. 
+        // Our curve returns 0..1 (or -1..1). 
+        // So we multiply by strength_scale.
+        self.lut[lut_idx] * self.strength_scale
+    }
+
+    /// Force (before the interaction weight) on a receiver, where `delta` points from the receiver
+    /// to the emitter. Zero outside `max_radius`; the softening decides what happens close in.
+    pub fn force(&self, delta: Vec2) -> Vec2 {
+        let distance = delta.length();
+        if distance > self.max_radius || distance == 0.0 { return Vec2::ZERO; }
+        let direction = delta / distance;
+
+        match self.softening {
+            Softening::Skip { distance: min } => {
+                if distance < min { return Vec2::ZERO; }
+                direction * self.sample(distance)
+            }
+            Softening::Clamp { distance: min } => direction * self.sample(distance.max(min)),
+            Softening::Plummer { length } => {
+                let softened = (distance * distance + length * length).sqrt();
+                // |delta| / softened: 1 far away, falling linearly to 0 at the centre
+                delta / softened * self.sample(softened)
+            }
+        }
+    }
+
     fn sample_points(&self, t: f32) -> f32 {
         if self.points.is_empty() { return 0.0; }
         if t <= self.points[0].x { return self.points[0].y; }
@@ -86,28 +480,56 @@ impl FieldShape {
 }
 
 /// The definition of a particle type (The "Blueprint")
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ParticleTypeDefinition {
     pub name: String,
     pub default_mass: f32,
     pub default_color: Color,
     pub emits_field: Option<usize>, 
     pub emission_shape: FieldShape,
+    // Particles of this type cannot overlap closer than the sum of their radii (0 = point particle)
+    #[serde(default)]
+    pub hard_core_radius: f32,
+    // Particles of this type despawn after this many simulated seconds (None = they live forever)
+    #[serde(default)]
+    pub lifetime: Option<f32>,
     // The visual representation of the curve (1D Gradient Texture)
     // We store it here so we can update it when the curve changes.
     // It's optional because it's created lazily or during setup.
+    #[serde(skip)]
     pub field_texture: Option<Handle<Image>>, 
 }
 
+// Equality ignores the lazily created texture
+impl PartialEq for ParticleTypeDefinition {
+    fn eq(&self, other: &Self) -> bool {
+        let Self { name, default_mass, default_color, emits_field, emission_shape, hard_core_radius, lifetime, field_texture: _ } = self;
+        *name == other.name
+            && *default_mass == other.default_mass
+            && *default_color == other.default_color
+            && *emits_field == other.emits_field
+            && *emission_shape == other.emission_shape
+            && *hard_core_radius == other.hard_core_radius
+            && *lifetime == other.lifetime
+    }
+}
+
 /// The central Alchemy definition
-#[derive(Resource)]
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
 pub struct AlchemyRules {
     pub particle_types: Vec<ParticleTypeDefinition>,
     // Interaction Matrix: How particle type P responds to field F
     // Key: (ParticleTypeID, FieldID), Value: Weight
+    // Serialized in sorted order so saved rules and share codes are deterministic
+    #[serde(serialize_with = "serialize_sorted")]
     pub interactions: HashMap<(usize, usize), f32>,
 }
 
+fn serialize_sorted<S: serde::Serializer>(map: &HashMap<(usize, usize), f32>, serializer: S) -> Result<S::Ok, S::Error> {
+    let sorted: std::collections::BTreeMap<_, _> = map.iter().collect();
+    sorted.serialize(serializer)
+}
+
 impl Default for AlchemyRules {
     fn default() -> Self {
         let mut rules = Self {
@@ -122,6 +544,8 @@ impl Default for AlchemyRules {
             default_color: Color::RED,
             emits_field: Some(0),
             emission_shape: FieldShape::new_linear_falloff(300.0, 1000.0),
+            hard_core_radius: 0.0,
+            lifetime: None,
             field_texture: None,
         });
 
@@ -132,6 +556,8 @@ impl Default for AlchemyRules {
             default_color: Color::BLUE,
             emits_field: Some(1),
             emission_shape: FieldShape::new_linear_falloff(300.0, 1000.0),
+            hard_core_radius: 0.0,
+            lifetime: None,
             field_texture: None,
         });
 
@@ -143,4 +569,316 @@ impl Default for AlchemyRules {
 
         rules
     }
-}
\ No newline at end of file
+}
+
+// A missing interaction means weight 0, as the matrix editor fills them in lazily
+impl PartialEq for AlchemyRules {
+    fn eq(&self, other: &Self) -> bool {
+        let same_weights = |a: &HashMap<(usize, usize), f32>, b: &HashMap<(usize, usize), f32>| {
+            a.iter().all(|(key, weight)| b.get(key).copied().unwrap_or(0.0) == *weight)
+        };
+        self.particle_types == other.particle_types
+            && same_weights(&self.interactions, &other.interactions)
+            && same_weights(&other.interactions, &self.interactions)
+    }
+}
+
+impl AlchemyRules {
+    /// Rebakes every field LUT (needed after deserializing, since LUTs are not stored).
+    pub fn bake_all(&mut self) {
+        for p_def in self.particle_types.iter_mut() {
+            p_def.emission_shape.bake_lut();
+        }
+    }
+
+    /// The shape that configures field `field_id`: that of the first type emitting it.
+    /// Decides the field's mode, and its grid coefficients in grid mode.
+    pub fn field_shape(&self, field_id: usize) -> Option<&FieldShape> {
+        self.particle_types.iter()
+            .find(|def| def.emits_field == Some(field_id))
+            .map(|def| &def.emission_shape)
+    }
+
+    /// Checks the rules for data the physics cannot handle. Returns every problem found (empty = valid).
+    /// Field ids share the index space of particle types: one field layer per type slot.
+    pub fn validate(&self) -> Vec<RuleError> {
+        let mut errors = Vec::new();
+        let num_types = self.particle_types.len();
+        let valid_positive = |v: f32| v.is_finite() && v > 0.0;
+
+        for (type_id, def) in self.particle_types.iter().enumerate() {
+            if self.particle_types[..type_id].iter().any(|other| other.name == def.name) {
+                errors.push(RuleError::DuplicateName { name: def.name.clone() });
+            }
+            if !valid_positive(def.default_mass) {
+                errors.push(RuleError::InvalidMass { type_id, mass: def.default_mass });
+            }
+            if !(def.hard_core_radius.is_finite() && def.hard_core_radius >= 0.0) {
+                errors.push(RuleError::InvalidHardCore { type_id, radius: def.hard_core_radius });
+            }
+            if let Some(lifetime) = def.lifetime {
+                if !(lifetime.is_finite() && lifetime > 0.0) {
+                    errors.push(RuleError::InvalidLifetime { type_id, lifetime });
+                }
+            }
+            if let Some(field_id) = def.emits_field {
+                if field_id >= num_types {
+                    errors.push(RuleError::UnknownField { type_id, field_id });
+                }
+            }
+
+            let shape = &def.emission_shape;
+            if shape.points.len() < 2 {
+                errors.push(RuleError::TooFewCurvePoints { type_id, count: shape.points.len() });
+            }
+            if let Some(index) = shape.points.iter().position(|p| !(0.0..=1.0).contains(&p.x) || !p.y.is_finite()) {
+                errors.push(RuleError::InvalidCurvePoint { type_id, index });
+            }
+            if !valid_positive(shape.max_radius) {
+                errors.push(RuleError::InvalidRadius { type_id, radius: shape.max_radius });
+            }
+            if !shape.strength_scale.is_finite() {
+                errors.push(RuleError::InvalidStrength { type_id, strength: shape.strength_scale });
+            }
+            let softening = shape.softening.parameter();
+            if !(softening.is_finite() && softening >= 0.0) {
+                errors.push(RuleError::InvalidSoftening { type_id, value: softening });
+            }
+            if let FieldMode::Grid { diffusion, decay } = shape.mode {
+                let valid_rate = |v: f32| v.is_finite() && v >= 0.0;
+                if !valid_rate(diffusion) || !valid_rate(decay) {
+                    errors.push(RuleError::InvalidGridCoefficients { type_id, diffusion, decay });
+                }
+            }
+        }
+
+        let mut keys: Vec<_> = self.interactions.keys().copied().collect();
+        keys.sort();
+        for (subject, field_id) in keys {
+            let weight = self.interactions[&(subject, field_id)];
+            if subject >= num_types || field_id >= num_types {
+                errors.push(RuleError::UnknownInteraction { subject, field_id });
+            } else if !weight.is_finite() {
+                errors.push(RuleError::InvalidWeight { subject, field_id, weight });
+            }
+        }
+
+        errors
+    }
+}
+
+/// A problem found by `AlchemyRules::validate`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuleError {
+    DuplicateName { name: String },
+    InvalidMass { type_id: usize, mass: f32 },
+    InvalidHardCore { type_id: usize, radius: f32 },
+    InvalidLifetime { type_id: usize, lifetime: f32 },
+    UnknownField { type_id: usize, field_id: usize },
+    TooFewCurvePoints { type_id: usize, count: usize },
+    InvalidCurvePoint { type_id: usize, index: usize },
+    InvalidRadius { type_id: usize, radius: f32 },
+    InvalidStrength { type_id: usize, strength: f32 },
+    InvalidGridCoefficients { type_id: usize, diffusion: f32, decay: f32 },
+    InvalidSoftening { type_id: usize, value: f32 },
+    UnknownInteraction { subject: usize, field_id: usize },
+    InvalidWeight { subject: usize, field_id: usize, weight: f32 },
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleError::DuplicateName { name } => write!(f, "particle type name \"{}\" is used more than once", name),
+            RuleError::InvalidMass { type_id, mass } => write!(f, "type {}: mass must be positive, got {}", type_id, mass),
+            RuleError::InvalidHardCore { type_id, radius } => write!(f, "type {}: hard-core radius must be non-negative, got {}", type_id, radius),
+            RuleError::InvalidLifetime { type_id, lifetime } => write!(f, "type {}: lifetime must be positive, got {}", type_id, lifetime),
+            RuleError::UnknownField { type_id, field_id } => write!(f, "type {}: emits field {}, which does not exist", type_id, field_id),
+            RuleError::TooFewCurvePoints { type_id, count } => write!(f, "type {}: field curve needs at least 2 points, has {}", type_id, count),
+            RuleError::InvalidCurvePoint { type_id, index } => write!(f, "type {}: curve point {} is outside distance 0..1 or not finite", type_id, index),
+            RuleError::InvalidRadius { type_id, radius } => write!(f, "type {}: field radius must be positive, got {}", type_id, radius),
+            RuleError::InvalidStrength { type_id, strength } => write!(f, "type {}: field strength is not finite ({})", type_id, strength),
+            RuleError::InvalidSoftening { type_id, value } => write!(f, "type {}: softening distance must be non-negative, got {}", type_id, value),
+            RuleError::InvalidGridCoefficients { type_id, diffusion, decay } => write!(f, "type {}: grid diffusion and decay must be non-negative, got {} and {}", type_id, diffusion, decay),
+            RuleError::UnknownInteraction { subject, field_id } => write!(f, "interaction ({}, {}) refers to a type or field that does not exist", subject, field_id),
+            RuleError::InvalidWeight { subject, field_id, weight } => write!(f, "interaction ({}, {}): weight is not finite ({})", subject, field_id, weight),
+        }
+    }
+}
+
+/// Joins validation errors into one line for messages.
+pub fn describe_rule_errors(errors: &[RuleError]) -> String {
+    errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smooth_speed_limits_conserve_pair_momentum_under_constant_force() {
+        let (m, c, dt) = (1.0, 1000.0, 0.016);
+        let force = Vec2::new(5000.0, 0.0);
+        let steps = 10_000;
+        for model in [SpeedLimit::Relativistic, SpeedLimit::Tanh] {
+            let (mut p_a, mut p_b) = (Vec2::ZERO, Vec2::ZERO);
+            let mut last_energy = 0.0;
+            for _ in 0..steps {
+                let v_a = model.apply_impulse(&mut p_a, force * dt, m, c);
+                let v_b = model.apply_impulse(&mut p_b, -force * dt, m, c);
+                assert!(v_a.length() <= c && v_b.length() <= c, "{:?} exceeded C", model);
+
+                // Every impulse still does work, however close to C
+                let energy = model.kinetic_energy(p_a, m, c);
+                assert!(energy > last_energy, "{:?} stopped gaining energy", model);
+                last_energy = energy;
+            }
+
+            assert_eq!(p_a + p_b, Vec2::ZERO, "{:?} lost pair momentum", model);
+            let expected = steps as f32 * force.x * dt;
+            assert!((p_a.x - expected).abs() <= expected * 1e-3, "{:?}: p = {}, expected {}", model, p_a.x, expected);
+            // Far beyond the momentum of the fastest velocity that can be set from outside
+            assert!(p_a.x > 2.0 * model.momentum(Vec2::new(c, 0.0), m, c).x);
+        }
+    }
+
+    #[test]
+    fn default_rules_are_valid() {
+        assert_eq!(AlchemyRules::default().validate(), Vec::new());
+    }
+
+    #[test]
+    fn validate_reports_each_problem_with_its_type() {
+        let mut rules = AlchemyRules::default();
+        rules.particle_types[1].name = rules.particle_types[0].name.clone();
+        rules.particle_types[0].default_mass = 0.0;
+        rules.particle_types[0].hard_core_radius = -1.0;
+        rules.particle_types[0].lifetime = Some(0.0);
+        rules.particle_types[1].emits_field = Some(5);
+        rules.particle_types[1].emission_shape.max_radius = -10.0;
+        rules.particle_types[1].emission_shape.softening = Softening::Plummer { length: -2.0 };
+        rules.particle_types[1].emission_shape.mode = FieldMode::Grid { diffusion: -1.0, decay: 0.5 };
+
+        assert_eq!(rules.validate(), vec![
+            RuleError::InvalidMass { type_id: 0, mass: 0.0 },
+            RuleError::InvalidHardCore { type_id: 0, radius: -1.0 },
+            RuleError::InvalidLifetime { type_id: 0, lifetime: 0.0 },
+            RuleError::DuplicateName { name: "Proton".to_string() },
+            RuleError::UnknownField { type_id: 1, field_id: 5 },
+            RuleError::InvalidRadius { type_id: 1, radius: -10.0 },
+            RuleError::InvalidSoftening { type_id: 1, value: -2.0 },
+            RuleError::InvalidGridCoefficients { type_id: 1, diffusion: -1.0, decay: 0.5 },
+        ]);
+    }
+
+    #[test]
+    fn validate_checks_curves() {
+        let mut rules = AlchemyRules::default();
+        rules.particle_types[0].emission_shape.points.truncate(1);
+        rules.particle_types[1].emission_shape.points[1].x = 1.5;
+        assert_eq!(rules.validate(), vec![
+            RuleError::TooFewCurvePoints { type_id: 0, count: 1 },
+            RuleError::InvalidCurvePoint { type_id: 1, index: 1 },
+        ]);
+    }
+
+    #[test]
+    fn validate_rejects_non_finite_numbers() {
+        let mut rules = AlchemyRules::default();
+        rules.particle_types[0].default_mass = f32::NAN;
+        rules.particle_types[1].emission_shape.strength_scale = f32::INFINITY;
+        rules.interactions.insert((0, 1), f32::NAN);
+
+        let errors = rules.validate();
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+        // NaN never compares equal, so match on the variants
+        assert!(matches!(errors[0], RuleError::InvalidMass { type_id: 0, mass } if mass.is_nan()));
+        assert!(matches!(errors[1], RuleError::InvalidStrength { type_id: 1, strength } if strength.is_infinite()));
+        assert!(matches!(errors[2], RuleError::InvalidWeight { subject: 0, field_id: 1, weight } if weight.is_nan()));
+    }
+
+    #[test]
+    fn validate_reports_interactions_with_unknown_types_in_order() {
+        let mut rules = AlchemyRules::default();
+        rules.interactions.insert((7, 0), 1.0);
+        rules.interactions.insert((0, 2), 1.0);
+        assert_eq!(rules.validate(), vec![
+            RuleError::UnknownInteraction { subject: 0, field_id: 2 },
+            RuleError::UnknownInteraction { subject: 7, field_id: 0 },
+        ]);
+    }
+
+    // Linear falloff from 1000 at the centre to 0 at 300
+    fn softened(softening: Softening) -> FieldShape {
+        FieldShape { softening, ..FieldShape::new_linear_falloff(300.0, 1000.0) }
+    }
+
+    // The curve read without any softening
+    fn unsoftened(shape: &FieldShape, delta: Vec2) -> Vec2 {
+        delta.normalize() * shape.sample(delta.length())
+    }
+
+    // One LUT cell of the test curve, the resolution of any comparison between distances
+    const LUT_STEP: f32 = 1000.0 / 99.0;
+
+    #[test]
+    fn softened_forces_stay_finite_and_bounded_near_the_centre() {
+        for softening in [
+            Softening::Skip { distance: 0.1 },
+            Softening::Clamp { distance: 5.0 },
+            Softening::Plummer { length: 5.0 },
+        ] {
+            let shape = softened(softening);
+            for d in [0.0, 1e-7, 1e-4, 1e-2, 0.5, 4.9] {
+                let force = shape.force(Vec2::new(d, 0.0));
+                assert!(force.is_finite(), "{:?} at {}: {:?}", softening, d, force);
+                assert!(force.length() <= 1000.0, "{:?} at {}: {:?}", softening, d, force);
+            }
+        }
+
+        // Skip has nothing inside its distance; Plummer fades linearly towards the centre
+        assert_eq!(softened(Softening::Skip { distance: 0.1 }).force(Vec2::new(0.05, 0.0)), Vec2::ZERO);
+        let plummer = softened(Softening::Plummer { length: 5.0 });
+        assert!(plummer.force(Vec2::new(1e-4, 0.0)).length() < 0.1);
+        assert!(plummer.force(Vec2::new(1.0, 0.0)).length() < plummer.force(Vec2::new(2.0, 0.0)).length());
+    }
+
+    #[test]
+    fn clamp_is_continuous_at_its_distance() {
+        let shape = softened(Softening::Clamp { distance: 50.0 });
+        let outside = shape.force(Vec2::new(50.0, 0.0)).length();
+        for d in [49.999, 40.0, 1.0, 1e-5] {
+            let inside = shape.force(Vec2::new(d, 0.0));
+            assert_eq!(inside.length(), outside, "strength at {} differs from the clamp distance", d);
+            assert!(inside.x > 0.0, "points towards the emitter");
+        }
+        // The curve starts at 1000, well above its value at the clamp distance
+        assert!(outside < 1000.0 - LUT_STEP);
+    }
+
+    #[test]
+    fn softening_agrees_with_the_curve_far_from_the_source() {
+        for softening in [
+            Softening::Skip { distance: 5.0 },
+            Softening::Clamp { distance: 5.0 },
+            Softening::Plummer { length: 5.0 },
+        ] {
+            let shape = softened(softening);
+            for d in [60.0, 150.0, 299.0] {
+                let delta = Vec2::new(d * 0.6, d * 0.8);
+                let difference = (shape.force(delta) - unsoftened(&shape, delta)).length();
+                assert!(difference <= LUT_STEP, "{:?} at {}: off by {}", softening, d, difference);
+            }
+            // Nothing beyond the radius
+            assert_eq!(shape.force(Vec2::new(301.0, 0.0)), Vec2::ZERO);
+        }
+    }
+
+    #[test]
+    fn clamp_discards_momentum_beyond_light_speed() {
+        let mut p = Vec2::ZERO;
+        for _ in 0..100 {
+            SpeedLimit::Clamp.apply_impulse(&mut p, Vec2::new(100.0, 0.0), 1.0, 1000.0);
+        }
+        assert_eq!(p, Vec2::new(1000.0, 0.0));
+    }
+}
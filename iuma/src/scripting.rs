@@ -0,0 +1,442 @@
+use bevy::prelude::*;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime};
+use crate::components::*;
+use crate::resources::*;
+use crate::physics::PhysicsSet;
+use crate::diagnostics::SimDiagnostics;
+use crate::snapshot::ParticleState;
+
+// Upper bound on script work per call, so a runaway loop cannot freeze the simulation
+const MAX_OPERATIONS: u64 = 50_000_000;
+// How often the script file's modification time is checked for hot reload
+const RELOAD_CHECK_SECS: f32 = 0.5;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// World state a script call reads and the edits it queues, shared with the registered functions.
+#[derive(Default)]
+struct ScriptContext {
+    // Particles at the start of the call; script ids are indices into this list
+    entities: Vec<Entity>,
+    particles: Vec<ParticleState>,
+    rules: AlchemyRules,
+    constants: GlobalConstants,
+    rules_changed: bool,
+    constants_changed: bool,
+    spawns: Vec<ParticleState>,
+    despawns: Vec<Entity>,
+}
+
+impl ScriptContext {
+    fn check_type(&self, type_id: i64) -> ScriptResult<usize> {
+        let num_types = self.rules.particle_types.len();
+        usize::try_from(type_id).ok()
+            .filter(|&t| t < num_types)
+            .ok_or_else(|| format!("particle type {} does not exist ({} types)", type_id, num_types).into())
+    }
+
+    fn particle_map(&self, id: usize) -> Map {
+        let p = &self.particles[id];
+        let mut map = Map::new();
+        map.insert("id".into(), (id as i64).into());
+        map.insert("type".into(), (p.type_id as i64).into());
+        map.insert("x".into(), (p.position.x as f64).into());
+        map.insert("y".into(), (p.position.y as f64).into());
+        map.insert("vx".into(), (p.velocity.x as f64).into());
+        map.insert("vy".into(), (p.velocity.y as f64).into());
+        map.insert("mass".into(), (p.mass as f64).into());
+        map.insert("age".into(), (p.age as f64).into());
+        map
+    }
+}
+
+struct LoadedScript {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    ast: AST,
+    scope: Scope<'static>,
+}
+
+/// A Rhai script driving the simulation, loaded from a file and hot-reloaded when it changes.
+///
+/// Top-level code runs on every (re)load, `fn setup()` only on the first load of a file,
+/// and `fn on_step(step)` before every physics step.
+#[derive(Resource)]
+pub struct ScriptHost {
+    engine: Engine,
+    context: Arc<Mutex<ScriptContext>>,
+    script: Option<LoadedScript>,
+    // Set by the UI; picked up by `script_system`
+    requested: Option<PathBuf>,
+    last_reload_check: Instant,
+    pub hot_reload: bool,
+    pub error: Option<String>,
+    // Message after the last successful (re)load
+    pub status: Option<String>,
+}
+
+impl Default for ScriptHost {
+    fn default() -> Self {
+        let context = Arc::new(Mutex::new(ScriptContext::default()));
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.on_print(|text| info!("script: {}", text));
+        register_api(&mut engine, &context);
+
+        Self {
+            engine,
+            context,
+            script: None,
+            requested: None,
+            last_reload_check: Instant::now(),
+            hot_reload: true,
+            error: None,
+            status: None,
+        }
+    }
+}
+
+impl ScriptHost {
+    /// Queues `path` to be loaded before the next step.
+    pub fn request_load(&mut self, path: PathBuf) {
+        self.requested = Some(path);
+    }
+
+    pub fn unload(&mut self) {
+        self.script = None;
+        self.requested = None;
+        self.error = None;
+        self.status = None;
+    }
+
+    pub fn loaded_path(&self) -> Option<&Path> {
+        self.script.as_ref().map(|script| script.path.as_path())
+    }
+
+    /// Compiles `path` and runs its top-level code (and `setup` if `first_load`).
+    /// On failure the previously loaded script stays active.
+    fn load(&mut self, world: &mut World, path: PathBuf, first_load: bool) -> Result<(), String> {
+        let source = std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+        let ast = self.engine.compile(&source).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        let mut scope = Scope::new();
+        fill_context(&self.context, world);
+        let mut result = self.engine.run_ast_with_scope(&mut scope, &ast);
+        if result.is_ok() && first_load && has_fn(&ast, "setup", 0) {
+            result = self.engine.call_fn::<Dynamic>(&mut scope, &ast, "setup", ()).map(|_| ());
+        }
+        let applied = apply_context(&self.context, world);
+        result.map_err(|e| format!("{}: {}", path.display(), e))?;
+        applied.map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        self.status = Some(format!("{} {}", if first_load { "Loaded" } else { "Reloaded" }, path.display()));
+        self.script = Some(LoadedScript { path, modified, ast, scope });
+        Ok(())
+    }
+
+    /// Calls `on_step(step)` if the script defines it.
+    fn call_on_step(&mut self, world: &mut World) -> Result<(), String> {
+        let step = world.resource::<SimDiagnostics>().step as i64;
+        let Some(script) = self.script.as_mut() else { return Ok(()); };
+        if !has_fn(&script.ast, "on_step", 1) { return Ok(()); }
+
+        // Split borrows: the engine and script are both fields of self
+        let (engine, context) = (&self.engine, &self.context);
+        fill_context(context, world);
+        let result = engine.call_fn::<Dynamic>(&mut script.scope, &script.ast, "on_step", (step,));
+        let applied = apply_context(context, world);
+        result.map(|_| ()).map_err(|e| e.to_string())
+            .and(applied)
+            .map_err(|e| format!("{}: on_step: {}", script.path.display(), e))
+    }
+
+    /// True if the loaded file changed on disk since it was (re)loaded. Rate-limited.
+    fn file_changed(&mut self) -> bool {
+        if !self.hot_reload || self.last_reload_check.elapsed().as_secs_f32() < RELOAD_CHECK_SECS {
+            return false;
+        }
+        self.last_reload_check = Instant::now();
+        let Some(script) = self.script.as_mut() else { return false; };
+        let modified = std::fs::metadata(&script.path).and_then(|m| m.modified()).ok();
+        if modified.is_some() && modified != script.modified {
+            // Remember it even if the reload fails, so a broken file is only reported once
+            script.modified = modified;
+            return true;
+        }
+        false
+    }
+}
+
+/// `value` as an f32 if it lies in `range` (which also rules out NaN).
+fn check_range(name: &str, value: f64, range: RangeInclusive<f32>) -> ScriptResult<f32> {
+    let value = value as f32;
+    if range.contains(&value) {
+        Ok(value)
+    } else {
+        Err(format!("{} {} is outside {}..={}", name, value, range.start(), range.end()).into())
+    }
+}
+
+fn has_fn(ast: &AST, name: &str, params: usize) -> bool {
+    ast.iter_functions().any(|f| f.name == name && f.params.len() == params)
+}
+
+/// Copies the particles, rules and constants into the shared context.
+fn fill_context(context: &Mutex<ScriptContext>, world: &mut World) {
+    let mut query = world.query_filtered::<(Entity, &Transform, &Velocity, &Mass, &ParticleTypeID, &Age), With<Particle>>();
+    let (entities, particles) = query.iter(world)
+        .map(|(entity, transform, velocity, mass, type_id, age)| (entity, ParticleState {
+            type_id: type_id.0,
+            position: transform.translation.truncate(),
+            velocity: velocity.0,
+            mass: mass.0,
+            momentum: None,
+            age: age.0,
+        }))
+        .unzip();
+
+    *context.lock().unwrap() = ScriptContext {
+        entities,
+        particles,
+        rules: world.resource::<AlchemyRules>().clone(),
+        constants: world.resource::<GlobalConstants>().clone(),
+        ..default()
+    };
+}
+
+/// Writes back whatever the script changed and carries out queued spawns and despawns.
+/// Rule edits that fail validation are dropped and reported; everything else still applies.
+/// The edits bypass `EditHistory`, which adopts them without an undo entry.
+fn apply_context(context: &Mutex<ScriptContext>, world: &mut World) -> Result<(), String> {
+    let context = std::mem::take(&mut *context.lock().unwrap());
+    let mut result = Ok(());
+    if context.rules_changed {
+        let errors = context.rules.validate();
+        if errors.is_empty() {
+            *world.resource_mut::<AlchemyRules>() = context.rules;
+        } else {
+            result = Err(format!("rule changes rejected: {}", describe_rule_errors(&errors)));
+        }
+    }
+    if context.constants_changed {
+        *world.resource_mut::<GlobalConstants>() = context.constants;
+    }
+    for entity in context.despawns {
+        if let Some(entity) = world.get_entity_mut(entity) {
+            entity.despawn_recursive();
+        }
+    }
+    for p in context.spawns {
+        world.spawn(p.bundle());
+    }
+    result
+}
+
+/// Registers the functions scripts use to inspect and change the simulation.
+fn register_api(engine: &mut Engine, context: &Arc<Mutex<ScriptContext>>) {
+    // Each binding gets its own handle on the shared context
+    // --- Particles ---
+    let c = context.clone();
+    engine.register_fn("particle_count", move || c.lock().unwrap().particles.len() as i64);
+
+    let c = context.clone();
+    engine.register_fn("particles", move || -> Array {
+        let ctx = c.lock().unwrap();
+        (0..ctx.particles.len()).map(|id| Dynamic::from_map(ctx.particle_map(id))).collect()
+    });
+
+    let c = context.clone();
+    engine.register_fn("particles_of_type", move |type_id: i64| -> ScriptResult<Array> {
+        let ctx = c.lock().unwrap();
+        let type_id = ctx.check_type(type_id)?;
+        Ok((0..ctx.particles.len())
+            .filter(|&id| ctx.particles[id].type_id == type_id)
+            .map(|id| Dynamic::from_map(ctx.particle_map(id)))
+            .collect())
+    });
+
+    let c = context.clone();
+    engine.register_fn("spawn_particle", move |type_id: i64, x: f64, y: f64| -> ScriptResult<()> {
+        let mut ctx = c.lock().unwrap();
+        let type_id = ctx.check_type(type_id)?;
+        let mass = ctx.rules.particle_types[type_id].default_mass;
+        ctx.spawns.push(ParticleState { type_id, position: Vec2::new(x as f32, y as f32), velocity: Vec2::ZERO, mass, momentum: None, age: 0.0 });
+        Ok(())
+    });
+
+    let c = context.clone();
+    engine.register_fn("spawn_particle", move |type_id: i64, x: f64, y: f64, vx: f64, vy: f64| -> ScriptResult<()> {
+        let mut ctx = c.lock().unwrap();
+        let type_id = ctx.check_type(type_id)?;
+        let mass = ctx.rules.particle_types[type_id].default_mass;
+        ctx.spawns.push(ParticleState {
+            type_id,
+            position: Vec2::new(x as f32, y as f32),
+            velocity: Vec2::new(vx as f32, vy as f32),
+            mass,
+            momentum: None,
+            age: 0.0,
+        });
+        Ok(())
+    });
+
+    let c = context.clone();
+    engine.register_fn("despawn_particle", move |id: i64| -> ScriptResult<()> {
+        let mut ctx = c.lock().unwrap();
+        let entity = usize::try_from(id).ok()
+            .and_then(|id| ctx.entities.get(id).copied())
+            .ok_or_else(|| format!("no particle with id {}", id))?;
+        ctx.despawns.push(entity);
+        Ok(())
+    });
+
+    // --- Rules ---
+    let c = context.clone();
+    engine.register_fn("type_count", move || c.lock().unwrap().rules.particle_types.len() as i64);
+
+    let c = context.clone();
+    engine.register_fn("get_interaction", move |a: i64, b: i64| -> ScriptResult<f64> {
+        let ctx = c.lock().unwrap();
+        let (a, b) = (ctx.check_type(a)?, ctx.check_type(b)?);
+        Ok(ctx.rules.interactions.get(&(a, b)).copied().unwrap_or(0.0) as f64)
+    });
+
+    let c = context.clone();
+    engine.register_fn("set_interaction", move |a: i64, b: i64, weight: f64| -> ScriptResult<()> {
+        let mut ctx = c.lock().unwrap();
+        let (a, b) = (ctx.check_type(a)?, ctx.check_type(b)?);
+        ctx.rules.interactions.insert((a, b), weight as f32);
+        ctx.rules_changed = true;
+        Ok(())
+    });
+
+    let c = context.clone();
+    engine.register_fn("get_field_radius", move |type_id: i64| -> ScriptResult<f64> {
+        let ctx = c.lock().unwrap();
+        let type_id = ctx.check_type(type_id)?;
+        Ok(ctx.rules.particle_types[type_id].emission_shape.max_radius as f64)
+    });
+
+    let c = context.clone();
+    engine.register_fn("set_field_radius", move |type_id: i64, radius: f64| -> ScriptResult<()> {
+        let mut ctx = c.lock().unwrap();
+        let type_id = ctx.check_type(type_id)?;
+        ctx.rules.particle_types[type_id].emission_shape.max_radius = radius as f32;
+        ctx.rules_changed = true;
+        Ok(())
+    });
+
+    let c = context.clone();
+    engine.register_fn("get_field_strength", move |type_id: i64| -> ScriptResult<f64> {
+        let ctx = c.lock().unwrap();
+        let type_id = ctx.check_type(type_id)?;
+        Ok(ctx.rules.particle_types[type_id].emission_shape.strength_scale as f64)
+    });
+
+    let c = context.clone();
+    engine.register_fn("set_field_strength", move |type_id: i64, strength: f64| -> ScriptResult<()> {
+        let mut ctx = c.lock().unwrap();
+        let type_id = ctx.check_type(type_id)?;
+        ctx.rules.particle_types[type_id].emission_shape.strength_scale = strength as f32;
+        ctx.rules_changed = true;
+        Ok(())
+    });
+
+    // --- Constants ---
+    let c = context.clone();
+    engine.register_fn("get_light_speed", move || c.lock().unwrap().constants.light_speed as f64);
+
+    let c = context.clone();
+    engine.register_fn("set_light_speed", move |value: f64| -> ScriptResult<()> {
+        let mut ctx = c.lock().unwrap();
+        ctx.constants.light_speed = check_range("light speed", value, LIGHT_SPEED_RANGE)?;
+        ctx.constants_changed = true;
+        Ok(())
+    });
+
+    let c = context.clone();
+    engine.register_fn("get_time_scale", move || c.lock().unwrap().constants.time_scale as f64);
+
+    let c = context.clone();
+    engine.register_fn("set_time_scale", move |value: f64| -> ScriptResult<()> {
+        let mut ctx = c.lock().unwrap();
+        ctx.constants.time_scale = check_range("time scale", value, TIME_SCALE_RANGE)?;
+        ctx.constants_changed = true;
+        Ok(())
+    });
+}
+
+/// Loads a script immediately, for callers with direct world access (the headless runner).
+pub fn load_script(world: &mut World, path: PathBuf) -> Result<(), String> {
+    world.resource_scope(|world, mut host: Mut<ScriptHost>| {
+        let result = host.load(world, path, true);
+        host.error = result.as_ref().err().cloned();
+        result
+    })
+}
+
+/// Handles load requests and hot reloads, then runs the script's per-step hook.
+pub fn script_system(world: &mut World) {
+    world.resource_scope(|world, mut host: Mut<ScriptHost>| {
+        if let Some(path) = host.requested.take() {
+            host.error = None;
+            if let Err(e) = host.load(world, path, true) {
+                host.error = Some(e);
+            }
+        } else if host.file_changed() {
+            let path = host.script.as_ref().map(|script| script.path.clone()).unwrap();
+            host.error = host.load(world, path, false).err();
+        }
+
+        // A failing hook keeps running; its latest error stays visible
+        if let Err(e) = host.call_on_step(world) {
+            host.error = Some(e);
+        }
+    });
+}
+
+/// Adds the script host, run just before each physics step.
+pub struct ScriptPlugin;
+
+impl Plugin for ScriptPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ScriptHost>()
+            .add_systems(Update, script_system.before(PhysicsSet));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(host: &ScriptHost, source: &str) -> Result<(), String> {
+        host.engine.run(source).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn constants_outside_the_editor_ranges_are_rejected() {
+        let host = ScriptHost::default();
+        for source in ["set_light_speed(0.0)", "set_light_speed(-100.0)", "set_light_speed(1.0e9)",
+                       "set_light_speed(0.0 / 0.0)", "set_time_scale(-1.0)", "set_time_scale(6.0)",
+                       "set_time_scale(0.0 / 0.0)"] {
+            let err = run(&host, source).expect_err(source);
+            assert!(err.contains("is outside"), "{}: {}", source, err);
+        }
+        assert!(!host.context.lock().unwrap().constants_changed);
+    }
+
+    #[test]
+    fn constants_inside_the_editor_ranges_are_applied() {
+        let host = ScriptHost::default();
+        run(&host, "set_light_speed(2500.0); set_time_scale(0.0);").unwrap();
+        let ctx = host.context.lock().unwrap();
+        assert!(ctx.constants_changed);
+        assert_eq!(ctx.constants.light_speed, 2500.0);
+        assert_eq!(ctx.constants.time_scale, 0.0);
+    }
+}
@@ -0,0 +1,309 @@
+use std::fmt;
+use std::io::{Read, Write};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use flate2::Compression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use std::time::{Instant, SystemTime};
+use bevy::prelude::Resource;
+use serde::{Deserialize, Serialize};
+use crate::resources::*;
+
+/// Prefix of every share code, followed by the format version and a colon.
+pub const CODE_PREFIX: &str = "IUMA";
+/// Current share code format version.
+pub const CODE_VERSION: u32 = 1;
+
+/// Everything a share code or rules file carries: the full ruleset plus universe constants.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SharedRuleset {
+    pub rules: AlchemyRules,
+    pub constants: GlobalConstants,
+}
+
+#[derive(Debug)]
+pub enum ShareCodeError {
+    MissingPrefix,
+    BadVersion(String),
+    FutureVersion(u32),
+    Base64(base64::DecodeError),
+    Decompress(std::io::Error),
+    Parse(ron::error::SpannedError),
+    Invalid(Vec<RuleError>),
+}
+
+impl fmt::Display for ShareCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShareCodeError::MissingPrefix => write!(f, "not an IUMA code (expected it to start with \"{}{}:\")", CODE_PREFIX, CODE_VERSION),
+            ShareCodeError::BadVersion(v) => write!(f, "invalid version \"{}\"", v),
+            ShareCodeError::FutureVersion(v) => write!(f, "code version {} is newer than this build supports (up to {})", v, CODE_VERSION),
+            ShareCodeError::Base64(e) => write!(f, "corrupted code (base64): {}", e),
+            ShareCodeError::Decompress(e) => write!(f, "corrupted code (compression): {}", e),
+            ShareCodeError::Parse(e) => write!(f, "invalid ruleset data: {}", e),
+            ShareCodeError::Invalid(errors) => write!(f, "invalid rules: {}", describe_rule_errors(errors)),
+        }
+    }
+}
+
+impl std::error::Error for ShareCodeError {}
+
+/// Encodes rules and constants as `IUMA<version>:<base64(deflate(ron))>`.
+pub fn encode(rules: &AlchemyRules, constants: &GlobalConstants) -> String {
+    let shared = SharedRuleset { rules: rules.clone(), constants: constants.clone() };
+    let text = ron::to_string(&shared).expect("ruleset is always serializable");
+
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(text.as_bytes()).expect("writing to a Vec cannot fail");
+    let compressed = encoder.finish().expect("writing to a Vec cannot fail");
+
+    format!("{}{}:{}", CODE_PREFIX, CODE_VERSION, URL_SAFE_NO_PAD.encode(compressed))
+}
+
+/// Decodes a share code. LUTs are rebaked; field textures are left for the UI to create.
+pub fn decode(code: &str) -> Result<SharedRuleset, ShareCodeError> {
+    // Whitespace sneaks in when codes are pasted from chat
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+
+    let rest = code.strip_prefix(CODE_PREFIX).ok_or(ShareCodeError::MissingPrefix)?;
+    let (version, payload) = rest.split_once(':').ok_or(ShareCodeError::MissingPrefix)?;
+    let version: u32 = version.parse().map_err(|_| ShareCodeError::BadVersion(version.to_string()))?;
+    if version > CODE_VERSION {
+        return Err(ShareCodeError::FutureVersion(version));
+    }
+    if version == 0 {
+        return Err(ShareCodeError::BadVersion(version.to_string()));
+    }
+
+    let compressed = URL_SAFE_NO_PAD.decode(payload).map_err(ShareCodeError::Base64)?;
+    let mut text = String::new();
+    DeflateDecoder::new(compressed.as_slice())
+        .read_to_string(&mut text)
+        .map_err(ShareCodeError::Decompress)?;
+
+    let mut shared: SharedRuleset = ron::from_str(&text).map_err(ShareCodeError::Parse)?;
+    let errors = shared.rules.validate();
+    if !errors.is_empty() {
+        return Err(ShareCodeError::Invalid(errors));
+    }
+    shared.rules.bake_all();
+    Ok(shared)
+}
+
+/// Error reading a RON file (rules or snapshot).
+#[derive(Debug)]
+pub enum RonFileError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    // Parsed, but the rules failed validation
+    Invalid(Vec<RuleError>),
+}
+
+impl fmt::Display for RonFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RonFileError::Io(e) => write!(f, "{}", e),
+            RonFileError::Parse(e) => write!(f, "invalid RON: {}", e),
+            RonFileError::Invalid(errors) => write!(f, "invalid rules: {}", describe_rule_errors(errors)),
+        }
+    }
+}
+
+impl std::error::Error for RonFileError {}
+
+/// Writes rules and constants to a human-editable RON file.
+pub fn save_rules(path: &std::path::Path, rules: &AlchemyRules, constants: &GlobalConstants) -> std::io::Result<()> {
+    let shared = SharedRuleset { rules: rules.clone(), constants: constants.clone() };
+    let text = ron::ser::to_string_pretty(&shared, ron::ser::PrettyConfig::default())
+        .expect("ruleset is always serializable");
+    std::fs::write(path, text)
+}
+
+/// Parses a RON rules file. A bare `AlchemyRules` is accepted too, with default constants.
+pub fn parse_rules(text: &str) -> Result<SharedRuleset, RonFileError> {
+    let mut shared = match ron::from_str::<SharedRuleset>(text) {
+        Ok(shared) => shared,
+        Err(full_error) => match ron::from_str::<AlchemyRules>(text) {
+            Ok(rules) => SharedRuleset { rules, constants: GlobalConstants::default() },
+            // Report the error for the full format, it is the one we write
+            Err(_) => return Err(RonFileError::Parse(full_error)),
+        },
+    };
+    let errors = shared.rules.validate();
+    if !errors.is_empty() {
+        return Err(RonFileError::Invalid(errors));
+    }
+    shared.rules.bake_all();
+    Ok(shared)
+}
+
+/// Reads and parses a RON rules file.
+pub fn load_rules(path: &std::path::Path) -> Result<SharedRuleset, RonFileError> {
+    let text = std::fs::read_to_string(path).map_err(RonFileError::Io)?;
+    parse_rules(&text)
+}
+
+// How often a watched rules file's modification time is checked
+const WATCH_INTERVAL_SECS: f32 = 0.5;
+
+/// The rules file the windowed app loads from and saves to, optionally watched for edits.
+#[derive(Resource)]
+pub struct RulesFile {
+    pub path: String,
+    pub watch: bool,
+    // Modification time when last loaded or saved; None until then
+    modified: Option<SystemTime>,
+    last_check: Instant,
+    // Ok(message) or Err(message) from the last load, reload or save
+    pub status: Option<Result<String, String>>,
+}
+
+impl Default for RulesFile {
+    fn default() -> Self {
+        Self {
+            path: "rules.ron".to_string(),
+            watch: true,
+            modified: None,
+            last_check: Instant::now(),
+            status: None,
+        }
+    }
+}
+
+impl RulesFile {
+    fn disk_modified(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.path).and_then(|m| m.modified()).ok()
+    }
+
+    /// Remembers the file's current version as the one in use.
+    pub fn mark_current(&mut self) {
+        self.modified = self.disk_modified();
+    }
+
+    /// True if watching and the file changed since it was loaded or saved. Rate-limited.
+    /// The new version is marked current either way, so a broken edit is reported only once.
+    pub fn poll_changed(&mut self) -> bool {
+        if !self.watch || self.modified.is_none() || self.last_check.elapsed().as_secs_f32() < WATCH_INTERVAL_SECS {
+            return false;
+        }
+        self.last_check = Instant::now();
+        let modified = self.disk_modified();
+        if modified.is_some() && modified != self.modified {
+            self.modified = modified;
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> (AlchemyRules, GlobalConstants) {
+        let constants = GlobalConstants {
+            light_speed: 1234.0,
+            speed_limit: SpeedLimit::Tanh,
+            ..GlobalConstants::default()
+        };
+        (AlchemyRules::default(), constants)
+    }
+
+    /// A code with the current header around an arbitrary payload.
+    fn code_with_payload(bytes: &[u8]) -> String {
+        format!("{}{}:{}", CODE_PREFIX, CODE_VERSION, URL_SAFE_NO_PAD.encode(bytes))
+    }
+
+    #[test]
+    fn share_code_round_trips() {
+        let (rules, constants) = sample();
+        let code = encode(&rules, &constants);
+        assert!(code.starts_with("IUMA1:"));
+
+        let shared = decode(&code).unwrap();
+        assert_eq!(shared.rules, rules);
+        assert_eq!(shared.constants, constants);
+        // The LUTs are ready to sample
+        assert!(shared.rules.particle_types.iter().all(|def| !def.emission_shape.lut.is_empty()));
+
+        // Line breaks and spaces picked up when pasting are ignored
+        let (head, tail) = code.split_at(code.len() / 2);
+        assert_eq!(decode(&format!(" {}\n {} ", head, tail)).unwrap().rules, rules);
+    }
+
+    #[test]
+    fn share_code_rejects_wrong_prefix() {
+        let (rules, constants) = sample();
+        let code = encode(&rules, &constants);
+        assert!(matches!(decode(&code.replacen("IUMA", "ABCD", 1)), Err(ShareCodeError::MissingPrefix)));
+        assert!(matches!(decode("IUMA1"), Err(ShareCodeError::MissingPrefix)));
+        assert!(matches!(decode(""), Err(ShareCodeError::MissingPrefix)));
+        assert!(matches!(decode("IUMAx:abc"), Err(ShareCodeError::BadVersion(_))));
+    }
+
+    #[test]
+    fn share_code_rejects_version_zero_and_future_versions() {
+        let (rules, constants) = sample();
+        let payload = encode(&rules, &constants).split_once(':').unwrap().1.to_string();
+        assert!(matches!(decode(&format!("IUMA0:{}", payload)), Err(ShareCodeError::BadVersion(_))));
+        let future = CODE_VERSION + 1;
+        assert!(matches!(
+            decode(&format!("IUMA{}:{}", future, payload)),
+            Err(ShareCodeError::FutureVersion(v)) if v == future
+        ));
+    }
+
+    #[test]
+    fn share_code_rejects_corrupt_payloads() {
+        assert!(matches!(decode("IUMA1:not*base64!"), Err(ShareCodeError::Base64(_))));
+        // Valid base64, but not a deflate stream
+        assert!(matches!(decode(&code_with_payload(&[0xff; 16])), Err(ShareCodeError::Decompress(_))));
+
+        // Valid deflate, but not a ruleset
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(b"(nonsense: true)").unwrap();
+        assert!(matches!(decode(&code_with_payload(&encoder.finish().unwrap())), Err(ShareCodeError::Parse(_))));
+    }
+
+    #[test]
+    fn share_code_rejects_invalid_rules() {
+        let (mut rules, constants) = sample();
+        rules.particle_types[0].default_mass = -1.0;
+        match decode(&encode(&rules, &constants)) {
+            Err(ShareCodeError::Invalid(errors)) => {
+                assert_eq!(errors, vec![RuleError::InvalidMass { type_id: 0, mass: -1.0 }]);
+            }
+            other => panic!("expected invalid rules, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn parse_rules_reads_full_files_and_bare_rules() {
+        let (rules, constants) = sample();
+        let full = ron::to_string(&SharedRuleset { rules: rules.clone(), constants: constants.clone() }).unwrap();
+        let shared = parse_rules(&full).unwrap();
+        assert_eq!(shared.rules, rules);
+        assert_eq!(shared.constants, constants);
+
+        // Bare rules get the default constants
+        let shared = parse_rules(&ron::to_string(&rules).unwrap()).unwrap();
+        assert_eq!(shared.rules, rules);
+        assert_eq!(shared.constants, GlobalConstants::default());
+        assert!(shared.rules.particle_types.iter().all(|def| !def.emission_shape.lut.is_empty()));
+    }
+
+    #[test]
+    fn parse_rules_rejects_bad_text_and_invalid_rules() {
+        assert!(matches!(parse_rules("(rules: oops"), Err(RonFileError::Parse(_))));
+
+        let (mut rules, _) = sample();
+        rules.interactions.insert((0, 9), 1.0);
+        match parse_rules(&ron::to_string(&rules).unwrap()) {
+            Err(RonFileError::Invalid(errors)) => {
+                assert_eq!(errors, vec![RuleError::UnknownInteraction { subject: 0, field_id: 9 }]);
+            }
+            other => panic!("expected invalid rules, got {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::components::{Age, Momentum, ParticleBundle};
+use crate::share::RonFileError;
+use crate::obstacles::Obstacle;
+use crate::external::ExternalField;
+use crate::sources::{ParticleSource, Sink};
+
+/// Plain-data view of one particle, used by snapshots and headless runs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParticleState {
+    pub type_id: usize,
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub mass: f32,
+    // Under the speed limit model in use when saved. The velocity is capped just below C, so only the
+    // momentum keeps a highly relativistic particle's state; `None` derives it from the velocity
+    #[serde(default)]
+    pub momentum: Option<Vec2>,
+    // Simulated seconds since the particle appeared (counts towards its type's lifetime)
+    #[serde(default)]
+    pub age: f32,
+}
+
+/// The state of a universe: particles, the obstacles they move between, the external fields
+/// acting on them and the sources and sinks they flow through (rules are stored separately).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Snapshot {
+    // Physics step the snapshot was taken at
+    pub step: u64,
+    pub particles: Vec<ParticleState>,
+    // Snapshots saved before obstacles existed have none
+    #[serde(default)]
+    pub obstacles: Vec<Obstacle>,
+    #[serde(default)]
+    pub external_fields: Vec<ExternalField>,
+    #[serde(default)]
+    pub sources: Vec<ParticleSource>,
+    #[serde(default)]
+    pub sinks: Vec<Sink>,
+}
+
+impl ParticleState {
+    /// The components of a particle restored from this state.
+    pub fn bundle(&self) -> ParticleBundle {
+        ParticleBundle {
+            momentum: Momentum(self.momentum.unwrap_or_default()),
+            age: Age(self.age),
+            ..ParticleBundle::new(self.type_id, self.position, self.velocity, self.mass)
+        }
+    }
+}
+
+impl Snapshot {
+    pub fn save(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .expect("snapshot is always serializable");
+        std::fs::write(path, text)
+    }
+
+    pub fn load(path: &std::path::Path) -> Result<Self, RonFileError> {
+        let text = std::fs::read_to_string(path).map_err(RonFileError::Io)?;
+        ron::from_str(&text).map_err(RonFileError::Parse)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn momentum_round_trips_and_older_files_load() {
+        let state = ParticleState {
+            type_id: 1, position: Vec2::new(1.0, 2.0), velocity: Vec2::new(999.9, 0.0), mass: 2.0,
+            momentum: Some(Vec2::new(4.0e5, 0.0)), age: 3.0,
+        };
+        let text = ron::to_string(&state).unwrap();
+        let loaded: ParticleState = ron::from_str(&text).unwrap();
+        assert_eq!(loaded.momentum, state.momentum);
+
+        let old = "(type_id: 0, position: (0.0, 0.0), velocity: (5.0, 0.0), mass: 1.0, age: 0.0)";
+        let loaded: ParticleState = ron::from_str(old).unwrap();
+        assert_eq!(loaded.momentum, None);
+        assert_eq!(loaded.bundle().momentum.0, Vec2::ZERO);
+    }
+}
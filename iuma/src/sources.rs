@@ -0,0 +1,293 @@
+use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use crate::components::*;
+use crate::resources::*;
+use crate::diagnostics::SimDiagnostics;
+use crate::external::Region;
+
+/// How a source picks the initial velocity of each particle it creates.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum VelocityDistribution {
+    // Every particle starts with the same velocity
+    Fixed { velocity: Vec2 },
+    // Speed uniform in min..max, heading within ±spread/2 of `direction` (radians)
+    Cone { direction: f32, spread: f32, min_speed: f32, max_speed: f32 },
+    // Maxwell-Boltzmann at ⟨½mv²⟩ = temperature, the same convention as the thermostats
+    Thermal { temperature: f32 },
+}
+
+/// Creates particles at a steady rate. Saved in snapshots.
+#[derive(Component, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParticleSource {
+    pub position: Vec2,
+    // New particles appear uniformly within this distance of `position` (0 = exactly on it)
+    pub radius: f32,
+    // Types to create, one picked at random per particle; types the rules lack are ignored
+    pub types: Vec<usize>,
+    // Particles per simulated second
+    pub rate: f32,
+    pub velocity: VelocityDistribution,
+    // Fraction of a particle carried over to the next step
+    #[serde(default)]
+    pub pending: f32,
+}
+
+/// Removes the particles that enter its region. Saved in snapshots.
+#[derive(Component, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sink {
+    pub region: Region,
+    // Types it removes; empty removes every type
+    pub types: Vec<usize>,
+}
+
+/// Random source for spawn positions, types and velocities. Fixed seed, so headless runs repeat.
+#[derive(Resource)]
+pub struct SourceRng(StdRng);
+
+impl Default for SourceRng {
+    fn default() -> Self {
+        Self(StdRng::seed_from_u64(0))
+    }
+}
+
+impl SourceRng {
+    /// A standard normal sample (Box-Muller).
+    fn normal(&mut self) -> f32 {
+        let u1: f32 = self.0.gen_range(f32::EPSILON..1.0);
+        let u2: f32 = self.0.gen();
+        (-2.0 * u1.ln()).sqrt() * (std::f32::consts::TAU * u2).cos()
+    }
+}
+
+impl VelocityDistribution {
+    pub const NAMES: [&'static str; 3] = ["Fixed", "Cone", "Thermal"];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            VelocityDistribution::Fixed { .. } => Self::NAMES[0],
+            VelocityDistribution::Cone { .. } => Self::NAMES[1],
+            VelocityDistribution::Thermal { .. } => Self::NAMES[2],
+        }
+    }
+
+    /// A default distribution of the named kind, for the editor.
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "Cone" => VelocityDistribution::Cone { direction: 0.0, spread: 0.5, min_speed: 50.0, max_speed: 150.0 },
+            "Thermal" => VelocityDistribution::Thermal { temperature: 1000.0 },
+            _ => VelocityDistribution::Fixed { velocity: Vec2::new(100.0, 0.0) },
+        }
+    }
+
+    fn sample(&self, mass: f32, rng: &mut SourceRng) -> Vec2 {
+        match *self {
+            VelocityDistribution::Fixed { velocity } => velocity,
+            VelocityDistribution::Cone { direction, spread, min_speed, max_speed } => {
+                let angle = direction + spread.abs() * (rng.0.gen::<f32>() - 0.5);
+                let speed = min_speed + (max_speed - min_speed) * rng.0.gen::<f32>();
+                Vec2::from_angle(angle) * speed
+            }
+            VelocityDistribution::Thermal { temperature } => {
+                let sigma = (temperature.max(0.0) / mass).sqrt();
+                Vec2::new(rng.normal(), rng.normal()) * sigma
+            }
+        }
+    }
+}
+
+impl Sink {
+    pub fn absorbs(&self, pos: Vec2, type_id: usize) -> bool {
+        (self.types.is_empty() || self.types.contains(&type_id)) && self.region.contains(pos)
+    }
+}
+
+/// Ages particles and removes the expired ones, lets sinks absorb particles, then runs the sources.
+/// Runs after the other physics so new particles start moving on the next step.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn source_sink_system(
+    mut commands: Commands,
+    mut particles: Query<(Entity, &Transform, &ParticleTypeID, &mut Age), (With<Particle>, Without<Quarantined>)>,
+    mut sources: Query<&mut ParticleSource>,
+    sinks: Query<&Sink>,
+    alchemy: Res<AlchemyRules>,
+    global_consts: Res<GlobalConstants>,
+    mut rng: ResMut<SourceRng>,
+    time: Res<Time>,
+    mut diagnostics: ResMut<SimDiagnostics>,
+) {
+    let dt = time.delta_seconds() * global_consts.time_scale;
+
+    // 1. Lifetimes, then sinks
+    for (entity, transform, type_id, mut age) in particles.iter_mut() {
+        age.0 += dt;
+        let lifetime = alchemy.particle_types.get(type_id.0).and_then(|def| def.lifetime);
+        if lifetime.is_some_and(|lifetime| age.0 >= lifetime) {
+            commands.entity(entity).despawn_recursive();
+            diagnostics.expired += 1;
+            continue;
+        }
+        let pos = transform.translation.truncate();
+        if sinks.iter().any(|sink| sink.absorbs(pos, type_id.0)) {
+            commands.entity(entity).despawn_recursive();
+            diagnostics.absorbed += 1;
+        }
+    }
+
+    // 2. Sources
+    let num_types = alchemy.particle_types.len();
+    for mut source in sources.iter_mut() {
+        let types: Vec<usize> = source.types.iter().copied().filter(|&t| t < num_types).collect();
+        if types.is_empty() || !(source.rate.is_finite() && source.rate > 0.0) {
+            source.pending = 0.0;
+            continue;
+        }
+        source.pending += source.rate * dt;
+        while source.pending >= 1.0 {
+            source.pending -= 1.0;
+            let type_id = types[rng.0.gen_range(0..types.len())];
+            let mass = alchemy.particle_types[type_id].default_mass;
+            // sqrt for a uniform density over the disc area
+            let offset = Vec2::from_angle(rng.0.gen_range(0.0..std::f32::consts::TAU))
+                * source.radius.max(0.0) * rng.0.gen::<f32>().sqrt();
+            let velocity = global_consts.speed_limit.limit(source.velocity.sample(mass, &mut rng), global_consts.light_speed);
+            commands.spawn(ParticleBundle::new(type_id, source.position + offset, velocity, mass));
+            diagnostics.spawned += 1;
+        }
+    }
+}
+
+/// Marks sources with their spawn disc and mean heading, and outlines sink regions.
+pub fn draw_sources_system(
+    mut gizmos: Gizmos,
+    sources: Query<&ParticleSource>,
+    sinks: Query<&Sink>,
+) {
+    let source_color = Color::rgba(0.3, 1.0, 0.5, 0.8);
+    let sink_color = Color::rgba(1.0, 0.35, 0.3, 0.8);
+
+    for source in sources.iter() {
+        gizmos.circle_2d(source.position, source.radius.max(6.0), source_color).segments(32);
+        let heading = match source.velocity {
+            VelocityDistribution::Fixed { velocity } => velocity,
+            VelocityDistribution::Cone { direction, min_speed, max_speed, .. } => {
+                Vec2::from_angle(direction) * (min_speed + max_speed) / 2.0
+            }
+            VelocityDistribution::Thermal { .. } => Vec2::ZERO,
+        };
+        if heading.length_squared() > 1.0 {
+            let start = source.position;
+            gizmos.arrow_2d(start, start + heading.clamp_length(20.0, 80.0), source_color);
+        }
+    }
+
+    for sink in sinks.iter() {
+        match sink.region {
+            // Nothing to outline; the whole universe drains
+            Region::Everywhere => {}
+            Region::Rect { min, max } => {
+                gizmos.rect_2d((min + max) / 2.0, 0.0, max - min, sink_color);
+                gizmos.line_2d(min, max, sink_color);
+                gizmos.line_2d(Vec2::new(min.x, max.y), Vec2::new(max.x, min.y), sink_color);
+            }
+            Region::Circle { center, radius } => {
+                gizmos.circle_2d(center, radius, sink_color).segments(64);
+                let d = Vec2::splat(radius * std::f32::consts::FRAC_1_SQRT_2);
+                gizmos.line_2d(center - d, center + d, sink_color);
+                gizmos.line_2d(center + Vec2::new(-d.x, d.y), center + Vec2::new(d.x, -d.y), sink_color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+    use std::time::Duration;
+
+    // Exact in binary, so rates times dt add up without rounding
+    const DT: f32 = 0.25;
+
+    fn world(alchemy: AlchemyRules) -> World {
+        let mut world = World::new();
+        world.insert_resource(alchemy);
+        world.init_resource::<GlobalConstants>();
+        world.init_resource::<SourceRng>();
+        world.init_resource::<SimDiagnostics>();
+        let mut time = Time::<()>::default();
+        time.advance_by(Duration::from_secs_f32(DT));
+        world.insert_resource(time);
+        world
+    }
+
+    fn particle_count(world: &mut World) -> usize {
+        world.query_filtered::<(), With<Particle>>().iter(world).count()
+    }
+
+    #[test]
+    fn sources_carry_fractions_over_between_steps() {
+        let mut world = world(AlchemyRules::default());
+        let source = world.spawn(ParticleSource {
+            position: Vec2::new(50.0, 0.0),
+            radius: 5.0,
+            types: vec![0],
+            rate: 3.0,
+            velocity: VelocityDistribution::Fixed { velocity: Vec2::new(10.0, 0.0) },
+            pending: 0.0,
+        }).id();
+
+        // 0.75 particles per step: 0.75, 1.5, 1.25, 1.0 pending before spawning
+        let mut spawned = Vec::new();
+        for _ in 0..4 {
+            let before = particle_count(&mut world);
+            world.run_system_once(source_sink_system);
+            spawned.push(particle_count(&mut world) - before);
+        }
+        assert_eq!(spawned, vec![0, 1, 1, 1]);
+        assert_eq!(world.get::<ParticleSource>(source).unwrap().pending, 0.0);
+        assert_eq!(world.resource::<SimDiagnostics>().spawned, 3);
+
+        let mut query = world.query::<(&Transform, &Velocity)>();
+        for (transform, velocity) in query.iter(&world) {
+            assert!(transform.translation.truncate().distance(Vec2::new(50.0, 0.0)) <= 5.0);
+            assert_eq!(velocity.0, Vec2::new(10.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn sinks_absorb_the_selected_types_inside_their_region() {
+        let mut world = world(AlchemyRules::default());
+        world.spawn(Sink { region: Region::Circle { center: Vec2::ZERO, radius: 10.0 }, types: vec![0] });
+        let absorbed = world.spawn(ParticleBundle::new(0, Vec2::new(5.0, 0.0), Vec2::ZERO, 1.0)).id();
+        let other_type = world.spawn(ParticleBundle::new(1, Vec2::new(5.0, 0.0), Vec2::ZERO, 1.0)).id();
+        let outside = world.spawn(ParticleBundle::new(0, Vec2::new(50.0, 0.0), Vec2::ZERO, 1.0)).id();
+
+        world.run_system_once(source_sink_system);
+
+        assert!(world.get_entity(absorbed).is_none());
+        assert!(world.get_entity(other_type).is_some() && world.get_entity(outside).is_some());
+        let diagnostics = world.resource::<SimDiagnostics>();
+        assert_eq!((diagnostics.absorbed, diagnostics.expired), (1, 0));
+    }
+
+    #[test]
+    fn expired_particles_count_as_expired_not_absorbed() {
+        let mut alchemy = AlchemyRules::default();
+        alchemy.particle_types[0].lifetime = Some(0.5);
+        let mut world = world(alchemy);
+        world.spawn(Sink { region: Region::Everywhere, types: Vec::new() });
+        // Ages past its lifetime this step, while inside the sink
+        let mut old = ParticleBundle::new(0, Vec2::ZERO, Vec2::ZERO, 1.0);
+        old.age = Age(0.4);
+        let old = world.spawn(old).id();
+        let young = world.spawn(ParticleBundle::new(0, Vec2::ZERO, Vec2::ZERO, 1.0)).id();
+
+        world.run_system_once(source_sink_system);
+
+        assert!(world.get_entity(old).is_none() && world.get_entity(young).is_none());
+        let diagnostics = world.resource::<SimDiagnostics>();
+        assert_eq!((diagnostics.expired, diagnostics.absorbed), (1, 1));
+    }
+}
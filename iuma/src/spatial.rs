@@ -0,0 +1,119 @@
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+/// Edge handling for neighbor searches.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Boundary {
+    Open,
+    // Box of this size centred on the origin; distances use the minimum image
+    Periodic(Vec2),
+}
+
+impl Boundary {
+    /// Displacement from `from` to `to`, wrapped to the nearest periodic image.
+    pub fn delta(&self, from: Vec2, to: Vec2) -> Vec2 {
+        let delta = to - from;
+        match self {
+            Boundary::Open => delta,
+            Boundary::Periodic(size) => delta - *size * (delta / *size).round(),
+        }
+    }
+
+    /// Area covered by `positions`: the box when periodic, their bounding box when open.
+    pub fn area(&self, positions: &[Vec2]) -> f32 {
+        match self {
+            Boundary::Periodic(size) => size.x * size.y,
+            Boundary::Open => {
+                let min = positions.iter().fold(Vec2::splat(f32::MAX), |m, p| m.min(*p));
+                let max = positions.iter().fold(Vec2::splat(f32::MIN), |m, p| m.max(*p));
+                let extent = (max - min).max(Vec2::ZERO);
+                extent.x * extent.y
+            }
+        }
+    }
+}
+
+/// Uniform grid over point indices for fixed-radius neighbor queries (broad phase).
+/// With `cell_size` >= the query radius, all neighbors lie in the 3x3 surrounding cells.
+pub struct SpatialGrid {
+    cell_size: f32,
+    boundary: Boundary,
+    // Cells per axis when periodic (indices wrap around)
+    wrap: Option<(i32, i32)>,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl SpatialGrid {
+    pub fn new(positions: &[Vec2], cell_size: f32, boundary: Boundary) -> Self {
+        let mut cell_size = cell_size.max(f32::EPSILON);
+        let wrap = match boundary {
+            Boundary::Open => None,
+            Boundary::Periodic(size) => {
+                // Cells must tile the box exactly, so grow the cell size up to a divisor
+                let nx = ((size.x / cell_size).floor() as i32).max(1);
+                let ny = ((size.y / cell_size).floor() as i32).max(1);
+                cell_size = (size.x / nx as f32).min(size.y / ny as f32);
+                Some((nx, ny))
+            }
+        };
+
+        let mut grid = Self { cell_size, boundary, wrap, cells: HashMap::new() };
+        for (i, pos) in positions.iter().enumerate() {
+            let cell = grid.cell_of(*pos);
+            grid.cells.entry(cell).or_default().push(i);
+        }
+        grid
+    }
+
+    fn cell_of(&self, pos: Vec2) -> (i32, i32) {
+        match (self.boundary, self.wrap) {
+            (Boundary::Periodic(size), Some((nx, ny))) => {
+                // Shift the origin-centred box to [0, size) before binning
+                let local = (pos + size / 2.0).rem_euclid(size);
+                let cx = ((local.x / size.x * nx as f32) as i32).min(nx - 1);
+                let cy = ((local.y / size.y * ny as f32) as i32).min(ny - 1);
+                (cx, cy)
+            }
+            _ => ((pos.x / self.cell_size).floor() as i32, (pos.y / self.cell_size).floor() as i32),
+        }
+    }
+
+    /// The distinct cells around `cell` (fewer than 9 when a periodic box is only 1-2 cells wide).
+    fn surrounding(&self, (cx, cy): (i32, i32)) -> Vec<(i32, i32)> {
+        let mut cells = Vec::with_capacity(9);
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                let cell = match self.wrap {
+                    Some((nx, ny)) => ((cx + dx).rem_euclid(nx), (cy + dy).rem_euclid(ny)),
+                    None => (cx + dx, cy + dy),
+                };
+                if !cells.contains(&cell) {
+                    cells.push(cell);
+                }
+            }
+        }
+        cells
+    }
+
+    /// Calls `f(i, j, delta)` once for every pair i < j closer than `radius`,
+    /// where `delta` points from i to j (minimum image when periodic).
+    /// `radius` must not exceed the cell size.
+    pub fn for_each_pair_within(&self, positions: &[Vec2], radius: f32, mut f: impl FnMut(usize, usize, Vec2)) {
+        debug_assert!(radius <= self.cell_size, "query radius larger than grid cells");
+        let radius_sq = radius * radius;
+        for (&cell, members) in self.cells.iter() {
+            for other_cell in self.surrounding(cell) {
+                let Some(others) = self.cells.get(&other_cell) else { continue; };
+                for &i in members {
+                    for &j in others {
+                        if i >= j { continue; }
+                        let delta = self.boundary.delta(positions[i], positions[j]);
+                        if delta.length_squared() <= radius_sq {
+                            f(i, j, delta);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
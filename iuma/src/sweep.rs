@@ -0,0 +1,351 @@
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use crate::resources::*;
+use crate::analysis;
+use crate::diagnostics::SimDiagnostics;
+use crate::headless::{self, HeadlessSim};
+use crate::snapshot::Snapshot;
+
+/// A scalar in the rules or constants that a sweep can vary.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SweepParam {
+    LightSpeed,
+    TimeScale,
+    // interactions[(subject type, field id)]
+    Interaction(usize, usize),
+    // particle_types[i].emission_shape.max_radius
+    MaxRadius(usize),
+    // particle_types[i].emission_shape.strength_scale
+    StrengthScale(usize),
+    // particle_types[i].default_mass
+    Mass(usize),
+}
+
+const PARAM_HELP: &str = "light_speed, time_scale, interactions[a,b] or \
+    particle_types[i].emission_shape.max_radius / .emission_shape.strength_scale / .default_mass";
+
+/// Parses the `usize` list inside `prefix[...]` at the start of `s`, returning it and the rest.
+fn parse_indices<'a>(s: &'a str, prefix: &str) -> Option<Result<(Vec<usize>, &'a str), String>> {
+    let rest = s.strip_prefix(prefix)?.strip_prefix('[')?;
+    let Some((inner, rest)) = rest.split_once(']') else {
+        return Some(Err(format!("missing ']' in '{}'", s)));
+    };
+    let indices = inner.split(',')
+        .map(|v| v.trim().parse::<usize>().map_err(|_| format!("invalid index '{}' in '{}'", v, s)))
+        .collect::<Result<Vec<_>, _>>();
+    Some(indices.map(|indices| (indices, rest)))
+}
+
+impl FromStr for SweepParam {
+    type Err = String;
+
+    /// Parses a parameter path such as `interactions[0,1]` or `particle_types[0].emission_shape.max_radius`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s {
+            "light_speed" => return Ok(SweepParam::LightSpeed),
+            "time_scale" => return Ok(SweepParam::TimeScale),
+            _ => {}
+        }
+
+        if let Some(parsed) = parse_indices(s, "interactions") {
+            return match parsed? {
+                (indices, "") if indices.len() == 2 => Ok(SweepParam::Interaction(indices[0], indices[1])),
+                _ => Err(format!("expected interactions[a,b], got '{}'", s)),
+            };
+        }
+
+        if let Some(parsed) = parse_indices(s, "particle_types") {
+            let (indices, field) = parsed?;
+            if indices.len() != 1 {
+                return Err(format!("expected particle_types[i], got '{}'", s));
+            }
+            let idx = indices[0];
+            return match field {
+                ".emission_shape.max_radius" => Ok(SweepParam::MaxRadius(idx)),
+                ".emission_shape.strength_scale" => Ok(SweepParam::StrengthScale(idx)),
+                ".default_mass" => Ok(SweepParam::Mass(idx)),
+                _ => Err(format!("unknown particle type field '{}' in '{}'", field, s)),
+            };
+        }
+
+        Err(format!("unknown parameter '{}' (expected {})", s, PARAM_HELP))
+    }
+}
+
+impl fmt::Display for SweepParam {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SweepParam::LightSpeed => write!(f, "light_speed"),
+            SweepParam::TimeScale => write!(f, "time_scale"),
+            SweepParam::Interaction(a, b) => write!(f, "interactions[{},{}]", a, b),
+            SweepParam::MaxRadius(i) => write!(f, "particle_types[{}].emission_shape.max_radius", i),
+            SweepParam::StrengthScale(i) => write!(f, "particle_types[{}].emission_shape.strength_scale", i),
+            SweepParam::Mass(i) => write!(f, "particle_types[{}].default_mass", i),
+        }
+    }
+}
+
+impl SweepParam {
+    /// Checks that the indices in the path exist in `rules`.
+    pub fn check(&self, rules: &AlchemyRules) -> Result<(), String> {
+        let num_types = rules.particle_types.len();
+        let index = match self {
+            SweepParam::LightSpeed | SweepParam::TimeScale => return Ok(()),
+            SweepParam::Interaction(a, b) => (*a).max(*b),
+            SweepParam::MaxRadius(i) | SweepParam::StrengthScale(i) | SweepParam::Mass(i) => *i,
+        };
+        if index >= num_types {
+            return Err(format!("{}: index {} out of range, the rules define {} types", self, index, num_types));
+        }
+        Ok(())
+    }
+
+    /// Sets the parameter. Must have passed `check` against these rules.
+    /// Masses live on the particles, so `Mass` also applies to the matching particles in `initial`.
+    pub fn apply(&self, rules: &mut AlchemyRules, constants: &mut GlobalConstants, initial: &mut Snapshot, value: f32) {
+        match self {
+            SweepParam::LightSpeed => constants.light_speed = value,
+            SweepParam::TimeScale => constants.time_scale = value,
+            SweepParam::Interaction(a, b) => { rules.interactions.insert((*a, *b), value); }
+            SweepParam::MaxRadius(i) => rules.particle_types[*i].emission_shape.max_radius = value,
+            SweepParam::StrengthScale(i) => rules.particle_types[*i].emission_shape.strength_scale = value,
+            SweepParam::Mass(i) => {
+                rules.particle_types[*i].default_mass = value;
+                for p in initial.particles.iter_mut().filter(|p| p.type_id == *i) {
+                    p.mass = value;
+                }
+            }
+        }
+    }
+}
+
+/// `count` evenly spaced values from `from` to `to` inclusive (descending if `to < from`).
+/// A single value is `from`. Errors on non-finite ends or a count of zero.
+pub fn linspace(from: f32, to: f32, count: usize) -> Result<Vec<f32>, String> {
+    if !from.is_finite() || !to.is_finite() {
+        return Err(format!("range {} to {} is not finite", from, to));
+    }
+    Ok(match count {
+        0 => return Err("a sweep needs at least one value".to_string()),
+        1 => vec![from],
+        // The last value is set exactly, rounding would otherwise miss `to`
+        _ => (0..count)
+            .map(|i| if i == count - 1 { to } else { from + (to - from) * i as f32 / (count - 1) as f32 })
+            .collect(),
+    })
+}
+
+/// One swept parameter and the values it takes.
+#[derive(Debug, Clone)]
+pub struct SweepAxis {
+    pub param: SweepParam,
+    pub values: Vec<f32>,
+}
+
+/// Summary metrics of one sweep run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SweepPoint {
+    // One value per axis, in axis order
+    pub values: Vec<f32>,
+    pub diagnostics: SimDiagnostics,
+    // Clusters of at least two particles at the end of the run
+    pub clusters: usize,
+}
+
+/// Settings shared by every run of a sweep.
+#[derive(Debug, Clone)]
+pub struct SweepConfig {
+    pub steps: usize,
+    // Distance linking particles into a cluster for the cluster count
+    pub link_distance: f32,
+    // Worker threads; 0 = one per available core
+    pub threads: usize,
+}
+
+/// Every combination of axis values, the last axis varying fastest.
+pub fn combinations(axes: &[SweepAxis]) -> Vec<Vec<f32>> {
+    axes.iter().fold(vec![Vec::new()], |combos, axis| {
+        combos.iter()
+            .flat_map(|prefix| axis.values.iter().map(move |&v| {
+                let mut combo = prefix.clone();
+                combo.push(v);
+                combo
+            }))
+            .collect()
+    })
+}
+
+fn run_point(
+    rules: &AlchemyRules,
+    constants: &GlobalConstants,
+    initial: &Snapshot,
+    axes: &[SweepAxis],
+    values: Vec<f32>,
+    config: &SweepConfig,
+) -> SweepPoint {
+    let mut rules = rules.clone();
+    let mut constants = constants.clone();
+    let mut initial = initial.clone();
+    for (axis, &value) in axes.iter().zip(values.iter()) {
+        axis.param.apply(&mut rules, &mut constants, &mut initial, value);
+    }
+
+    let mut sim = HeadlessSim::new(rules, constants, &initial);
+    for _ in 0..config.steps {
+        sim.step();
+    }
+    let positions: Vec<_> = sim.particles().iter().map(|p| p.position).collect();
+    SweepPoint {
+        values,
+        diagnostics: sim.diagnostics().clone(),
+        clusters: analysis::count_clusters(&positions, config.link_distance, 2),
+    }
+}
+
+/// Runs the same initial state once per combination of axis values, spread over worker threads.
+/// `on_point` is called on the calling thread as runs finish (in completion order);
+/// the returned table is in `combinations` order.
+pub fn run_sweep(
+    rules: &AlchemyRules,
+    constants: &GlobalConstants,
+    initial: &Snapshot,
+    axes: &[SweepAxis],
+    config: &SweepConfig,
+    on_point: impl FnMut(&SweepPoint),
+) -> Vec<SweepPoint> {
+    let combos = combinations(axes);
+    headless::run_parallel(
+        &combos,
+        config.threads,
+        |values| run_point(rules, constants, initial, axes, values.clone(), config),
+        on_point,
+    )
+}
+
+/// Writes the table as CSV: one column per axis, then the metrics.
+pub fn write_csv(path: &Path, axes: &[SweepAxis], points: &[SweepPoint]) -> std::io::Result<()> {
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    for axis in axes {
+        // Paths contain commas (interactions[a,b]), so quote them
+        write!(file, "\"{}\",", axis.param)?;
+    }
+    writeln!(file, "step,particle_count,kinetic_energy,momentum_x,momentum_y,mean_speed,max_speed,clusters")?;
+
+    for point in points {
+        for value in point.values.iter() {
+            write!(file, "{},", value)?;
+        }
+        let d = &point.diagnostics;
+        writeln!(
+            file, "{},{},{},{},{},{},{},{}",
+            d.step, d.particle_count, d.kinetic_energy, d.momentum.x, d.momentum.y, d.mean_speed, d.max_speed, point.clusters,
+        )?;
+    }
+    file.flush()
+}
+
+/// Sweep output for the JSON and RON formats: the parameter names plus the table.
+#[derive(Serialize)]
+pub struct SweepTable<'a> {
+    pub params: Vec<String>,
+    pub points: &'a [SweepPoint],
+}
+
+impl<'a> SweepTable<'a> {
+    pub fn new(axes: &[SweepAxis], points: &'a [SweepPoint]) -> Self {
+        Self {
+            params: axes.iter().map(|axis| axis.param.to_string()).collect(),
+            points,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn params_parse_and_print_back() {
+        for path in [
+            "light_speed",
+            "time_scale",
+            "interactions[0,1]",
+            "particle_types[2].emission_shape.max_radius",
+            "particle_types[0].emission_shape.strength_scale",
+            "particle_types[1].default_mass",
+        ] {
+            let param: SweepParam = path.parse().unwrap();
+            assert_eq!(param.to_string(), path);
+        }
+        assert_eq!(" interactions[ 3 , 4 ] ".parse::<SweepParam>(), Ok(SweepParam::Interaction(3, 4)));
+    }
+
+    #[test]
+    fn params_reject_malformed_paths() {
+        for path in [
+            "",
+            "speed_of_light",
+            "interactions[0]",
+            "interactions[0,1,2]",
+            "interactions[0,1",
+            "interactions[0,x]",
+            "interactions[0,1].weight",
+            "particle_types[0,1].default_mass",
+            "particle_types[-1].default_mass",
+            "particle_types[0].colour",
+            "particle_types[0]",
+        ] {
+            assert!(path.parse::<SweepParam>().is_err(), "'{}' should not parse", path);
+        }
+    }
+
+    #[test]
+    fn params_check_indices_against_rules() {
+        let rules = AlchemyRules::default();
+        assert!(SweepParam::Interaction(1, 1).check(&rules).is_ok());
+        assert!(SweepParam::Interaction(0, 2).check(&rules).is_err());
+        assert!(SweepParam::Mass(2).check(&rules).is_err());
+        assert!(SweepParam::LightSpeed.check(&rules).is_ok());
+    }
+
+    #[test]
+    fn linspace_covers_the_range_inclusively() {
+        assert_eq!(linspace(0.0, 1.0, 5), Ok(vec![0.0, 0.25, 0.5, 0.75, 1.0]));
+        assert_eq!(linspace(3.0, -3.0, 3), Ok(vec![3.0, 0.0, -3.0]));
+        // The last value is exact even when the step does not divide evenly
+        assert_eq!(*linspace(0.0, 0.7, 7).unwrap().last().unwrap(), 0.7);
+    }
+
+    #[test]
+    fn linspace_with_one_value_is_the_start() {
+        assert_eq!(linspace(2.5, 100.0, 1), Ok(vec![2.5]));
+    }
+
+    #[test]
+    fn linspace_rejects_bad_ranges() {
+        assert!(linspace(0.0, 1.0, 0).is_err());
+        assert!(linspace(f32::NAN, 1.0, 3).is_err());
+        assert!(linspace(0.0, f32::INFINITY, 3).is_err());
+    }
+
+    #[test]
+    fn combinations_are_the_cartesian_product() {
+        let axes = [
+            SweepAxis { param: SweepParam::LightSpeed, values: vec![1.0, 2.0, 3.0] },
+            SweepAxis { param: SweepParam::TimeScale, values: vec![10.0, 20.0] },
+        ];
+        let combos = combinations(&axes);
+        assert_eq!(combos.len(), 3 * 2);
+        // First axis outermost, values in axis order
+        assert_eq!(combos[0], vec![1.0, 10.0]);
+        assert_eq!(combos[1], vec![1.0, 20.0]);
+        assert_eq!(combos[5], vec![3.0, 20.0]);
+
+        assert_eq!(combinations(&axes[..1]).len(), 3);
+        assert_eq!(combinations(&[]), vec![Vec::<f32>::new()]);
+    }
+}
@@ -0,0 +1,201 @@
+use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use crate::components::*;
+use crate::resources::*;
+
+/// Random source of the Langevin thermostat, reseeded whenever the configured seed changes.
+#[derive(Resource)]
+pub struct ThermostatRng {
+    seed: u64,
+    rng: StdRng,
+}
+
+impl Default for ThermostatRng {
+    fn default() -> Self {
+        Self { seed: 0, rng: StdRng::seed_from_u64(0) }
+    }
+}
+
+impl ThermostatRng {
+    /// A standard normal sample (Box-Muller).
+    fn normal(&mut self) -> f32 {
+        let u1: f32 = self.rng.gen_range(f32::EPSILON..1.0);
+        let u2: f32 = self.rng.gen();
+        (-2.0 * u1.ln()).sqrt() * (std::f32::consts::TAU * u2).cos()
+    }
+}
+
+/// Applies the configured thermostat to the particles of the selected types, after the integration.
+#[allow(clippy::type_complexity)]
+pub fn thermostat_system(
+    mut query: Query<(&mut Velocity, &mut Momentum, &Mass, &ParticleTypeID), (With<Particle>, Without<Quarantined>)>,
+    global_consts: Res<GlobalConstants>,
+    mut noise: ResMut<ThermostatRng>,
+    time: Res<Time>,
+) {
+    let thermostat = &global_consts.thermostat;
+    if thermostat.kind == ThermostatKind::Off { return; }
+    if noise.seed != thermostat.seed {
+        *noise = ThermostatRng { seed: thermostat.seed, rng: StdRng::seed_from_u64(thermostat.seed) };
+    }
+
+    let dt = time.delta_seconds() * global_consts.time_scale;
+    let target = thermostat.target_temperature.max(0.0);
+    let coupling = thermostat.coupling.max(0.0);
+    let c = global_consts.light_speed;
+
+    let mut selected: Vec<_> = query.iter_mut()
+        .filter(|(.., type_id)| thermostat.type_enabled(type_id.0))
+        .collect();
+    if selected.is_empty() { return; }
+
+    match thermostat.kind {
+        ThermostatKind::Off => {}
+        ThermostatKind::Rescale | ThermostatKind::Berendsen => {
+            // Current temperature of the thermostatted particles
+            let kinetic: f32 = selected.iter().map(|(v, _, m, _)| 0.5 * m.0 * v.0.length_squared()).sum();
+            let temperature = kinetic / selected.len() as f32;
+            if temperature <= f32::EPSILON { return; }
+
+            let ratio = target / temperature;
+            let scale = if thermostat.kind == ThermostatKind::Rescale {
+                ratio.sqrt()
+            } else {
+                (1.0 + (coupling * dt).min(1.0) * (ratio - 1.0)).max(0.0).sqrt()
+            };
+            for (velocity, momentum, mass, _) in selected.iter_mut() {
+                let new_velocity = global_consts.speed_limit.limit(velocity.0 * scale, c);
+                momentum.0 = global_consts.speed_limit.momentum_after(momentum.0, velocity.0, new_velocity, mass.0, c);
+                velocity.0 = new_velocity;
+            }
+        }
+        ThermostatKind::Langevin => {
+            // Exact Ornstein-Uhlenbeck step: friction decays v, noise restores ⟨½mv²⟩ = target per particle
+            let decay = (-coupling * dt).exp();
+            let spread = (1.0 - decay * decay).sqrt();
+            for (velocity, momentum, mass, _) in selected.iter_mut() {
+                let sigma = (target / mass.0).sqrt() * spread;
+                let kick = Vec2::new(noise.normal(), noise.normal()) * sigma;
+                let new_velocity = global_consts.speed_limit.limit(velocity.0 * decay + kick, c);
+                momentum.0 = global_consts.speed_limit.momentum_after(momentum.0, velocity.0, new_velocity, mass.0, c);
+                velocity.0 = new_velocity;
+            }
+        }
+        ThermostatKind::Drag => {
+            let decay = (-coupling * dt).exp();
+            for (velocity, momentum, mass, _) in selected.iter_mut() {
+                let new_velocity = velocity.0 * decay;
+                momentum.0 = global_consts.speed_limit.momentum_after(momentum.0, velocity.0, new_velocity, mass.0, c);
+                velocity.0 = new_velocity;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+    use std::time::Duration;
+
+    const DT: f32 = 0.1;
+
+    /// Runs `steps` thermostat steps of `DT` over particles given as (type, velocity, mass);
+    /// returns their velocities and momenta.
+    fn run(thermostat: Thermostat, particles: &[(usize, Vec2, f32)], steps: usize) -> Vec<(Vec2, Vec2)> {
+        let mut world = World::new();
+        let entities: Vec<Entity> = particles.iter()
+            .map(|&(type_id, velocity, mass)| {
+                let mut bundle = ParticleBundle::new(type_id, Vec2::ZERO, velocity, mass);
+                bundle.momentum = Momentum(velocity * mass);
+                world.spawn(bundle).id()
+            })
+            .collect();
+        world.insert_resource(GlobalConstants { thermostat, ..default() });
+        world.init_resource::<ThermostatRng>();
+        let mut time = Time::<()>::default();
+        time.advance_by(Duration::from_secs_f32(DT));
+        world.insert_resource(time);
+
+        for _ in 0..steps {
+            world.run_system_once(thermostat_system);
+        }
+        entities.iter()
+            .map(|&e| (world.get::<Velocity>(e).unwrap().0, world.get::<Momentum>(e).unwrap().0))
+            .collect()
+    }
+
+    fn temperature(particles: &[(usize, Vec2, f32)], velocities: &[(Vec2, Vec2)]) -> f32 {
+        let kinetic: f32 = particles.iter().zip(velocities).map(|(p, (v, _))| 0.5 * p.2 * v.length_squared()).sum();
+        kinetic / particles.len() as f32
+    }
+
+    fn sample() -> Vec<(usize, Vec2, f32)> {
+        vec![
+            (0, Vec2::new(30.0, 0.0), 1.0),
+            (0, Vec2::new(-10.0, 20.0), 2.0),
+            (0, Vec2::new(0.0, -50.0), 0.5),
+        ]
+    }
+
+    #[test]
+    fn rescaling_reaches_the_target_in_one_step() {
+        let particles = sample();
+        let thermostat = Thermostat { kind: ThermostatKind::Rescale, target_temperature: 100.0, ..default() };
+        let after = run(thermostat, &particles, 1);
+        assert!((temperature(&particles, &after) - 100.0).abs() < 1e-2);
+        // Directions are kept, and the momentum follows the velocity
+        for (p, (v, momentum)) in particles.iter().zip(after.iter()) {
+            assert!(p.1.normalize().dot(v.normalize()) > 0.9999);
+            assert!((*momentum - *v * p.2).length() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn berendsen_relaxes_at_the_coupling_rate() {
+        let particles = sample();
+        let start = temperature(&particles, &particles.iter().map(|p| (p.1, p.1)).collect::<Vec<_>>());
+        let thermostat = Thermostat { kind: ThermostatKind::Berendsen, target_temperature: 100.0, coupling: 2.0, ..default() };
+        let after = run(thermostat, &particles, 1);
+        // T' = T + coupling · dt · (target - T)
+        let expected = start + 2.0 * DT * (100.0 - start);
+        assert!((temperature(&particles, &after) - expected).abs() < 1e-2 * expected, "{} vs {}", temperature(&particles, &after), expected);
+    }
+
+    #[test]
+    fn drag_decays_velocities_at_the_coupling_rate() {
+        let particles = sample();
+        let thermostat = Thermostat { kind: ThermostatKind::Drag, coupling: 3.0, ..default() };
+        let after = run(thermostat, &particles, 2);
+        let decay = (-3.0 * DT * 2.0).exp();
+        for (p, (v, _)) in particles.iter().zip(after.iter()) {
+            assert!((*v - p.1 * decay).length() < 1e-4, "{:?} vs {:?}", v, p.1 * decay);
+        }
+    }
+
+    #[test]
+    fn langevin_settles_at_the_target() {
+        let particles: Vec<_> = (0..2000).map(|i| (0, Vec2::ZERO, 1.0 + (i % 3) as f32)).collect();
+        let thermostat = Thermostat { kind: ThermostatKind::Langevin, target_temperature: 50.0, coupling: 5.0, seed: 3, ..default() };
+        let after = run(thermostat, &particles, 30);
+        let t = temperature(&particles, &after);
+        assert!((t - 50.0).abs() < 5.0, "temperature {}", t);
+    }
+
+    #[test]
+    fn disabled_types_are_left_alone() {
+        let mut particles = sample();
+        particles.push((1, Vec2::new(200.0, 0.0), 1.0));
+        let thermostat = Thermostat {
+            kind: ThermostatKind::Rescale,
+            target_temperature: 100.0,
+            per_type: vec![true, false],
+            ..default()
+        };
+        let after = run(thermostat, &particles, 1);
+        assert_eq!(after[3], (Vec2::new(200.0, 0.0), Vec2::new(200.0, 0.0)));
+        // The target applies to the enabled types alone
+        assert!((temperature(&particles[..3], &after[..3]) - 100.0).abs() < 1e-2);
+    }
+}
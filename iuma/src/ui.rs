@@ -1,21 +1,33 @@
 use bevy::prelude::*;
 use bevy_egui::{egui, EguiContexts};
-use bevy::sprite::{MaterialMesh2dBundle, Mesh2dHandle};
 use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
 use bevy::render::render_asset::RenderAssetUsages;
 use crate::resources::*;
 use crate::components::*;
-use crate::render::FieldMaterial;
+use crate::render::{FieldMaterial, RenderSettings, ColorMode, ColorRamp, cluster_color};
+use crate::analysis::{self, ClusterAnalysis, ClusterSettings, StructureAnalysis, StructureSettings};
+use crate::overlay::{FieldOverlaySettings, ForceOverlaySettings};
+use crate::share::{self, RulesFile};
+use crate::diagnostics::SimDiagnostics;
+use crate::snapshot::{ParticleState, Snapshot};
+use crate::generator::{self, CurveFamily, RandomizerSettings};
+use crate::scripting::ScriptHost;
+use crate::history::{EditHistory, EditState};
+use crate::field_grid::FieldGrids;
+use crate::obstacles::{Obstacle, ObstacleEditor, ObstacleTool};
+use crate::external::{ExternalField, ExternalShape, Region};
+use crate::sources::{ParticleSource, Sink, VelocityDistribution};
 
+#[allow(clippy::too_many_arguments)]
 pub fn ui_system(
     mut contexts: EguiContexts,
     mut global_consts: ResMut<GlobalConstants>,
     mut alchemy: ResMut<AlchemyRules>,
     mut commands: Commands,
     particle_query: Query<Entity, With<Particle>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<FieldMaterial>>,
     mut images: ResMut<Assets<Image>>,
+    diagnostics: Res<SimDiagnostics>,
+    mut grids: ResMut<FieldGrids>,
 ) {
     // Ensure textures are initialized
     for p_def in alchemy.particle_types.iter_mut() {
@@ -39,12 +51,90 @@ pub fn ui_system(
         
         ui.horizontal(|ui| {
             ui.label("Light Speed (C):");
-            ui.add(egui::Slider::new(&mut global_consts.light_speed, 100.0..=5000.0));
+            ui.add(egui::Slider::new(&mut global_consts.light_speed, LIGHT_SPEED_RANGE));
         });
 
         ui.horizontal(|ui| {
             ui.label("Time Scale:");
-            ui.add(egui::Slider::new(&mut global_consts.time_scale, 0.0..=5.0));
+            ui.add(egui::Slider::new(&mut global_consts.time_scale, TIME_SCALE_RANGE));
+        });
+
+        ui.checkbox(&mut global_consts.retarded_fields, "Retarded Fields (propagate at C)");
+
+        egui::ComboBox::from_label("Action = Reaction")
+            .selected_text(global_consts.reciprocity.label())
+            .show_ui(ui, |ui| {
+                for mode in Reciprocity::ALL {
+                    ui.selectable_value(&mut global_consts.reciprocity, mode, mode.label());
+                }
+            });
+
+        egui::ComboBox::from_label("Speed Limit")
+            .selected_text(global_consts.speed_limit.label())
+            .show_ui(ui, |ui| {
+                for model in SpeedLimit::ALL {
+                    ui.selectable_value(&mut global_consts.speed_limit, model, model.label());
+                }
+            });
+
+        ui.separator();
+
+        ui.heading("Thermostat");
+        let thermostat = &mut global_consts.thermostat;
+        egui::ComboBox::from_label("Model")
+            .selected_text(thermostat.kind.label())
+            .show_ui(ui, |ui| {
+                for kind in ThermostatKind::ALL {
+                    ui.selectable_value(&mut thermostat.kind, kind, kind.label());
+                }
+            });
+        if thermostat.kind != ThermostatKind::Off {
+            ui.add_enabled_ui(thermostat.kind.uses_target(), |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Target Temperature:");
+                    ui.add(egui::DragValue::new(&mut thermostat.target_temperature).speed(10.0).clamp_range(0.0..=f32::MAX));
+                });
+            });
+            ui.add_enabled_ui(thermostat.kind.uses_coupling(), |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Coupling (1/s):");
+                    ui.add(egui::Slider::new(&mut thermostat.coupling, 0.001..=100.0).logarithmic(true));
+                });
+            });
+            if thermostat.kind == ThermostatKind::Langevin {
+                ui.horizontal(|ui| {
+                    ui.label("Noise Seed:");
+                    ui.add(egui::DragValue::new(&mut thermostat.seed));
+                });
+            }
+            ui.horizontal_wrapped(|ui| {
+                ui.label("Applies to:");
+                for (idx, def) in alchemy.particle_types.iter().enumerate() {
+                    let mut enabled = thermostat.type_enabled(idx);
+                    if ui.checkbox(&mut enabled, &def.name).changed() {
+                        if thermostat.per_type.len() <= idx {
+                            thermostat.per_type.resize(idx + 1, true);
+                        }
+                        thermostat.per_type[idx] = enabled;
+                    }
+                }
+            });
+        }
+
+        ui.separator();
+
+        ui.heading("Hard Cores");
+        ui.horizontal(|ui| {
+            ui.label("Restitution:");
+            ui.add(egui::Slider::new(&mut global_consts.collision_restitution, 0.0..=1.0));
+        });
+        // Radius 0 is a point particle, which still collides with particles that have a core
+        egui::Grid::new("hard_core_radii").show(ui, |ui| {
+            for def in alchemy.particle_types.iter_mut() {
+                ui.label(&def.name);
+                ui.add(egui::DragValue::new(&mut def.hard_core_radius).speed(0.1).clamp_range(0.0..=100.0));
+                ui.end_row();
+            }
         });
 
         ui.separator();
@@ -59,8 +149,6 @@ pub fn ui_system(
                      idx, 
                      Vec2::new(0.0, 0.0), 
                      p_def, 
-                     &mut meshes, 
-                     &mut materials
                 );
             }
         }
@@ -71,6 +159,30 @@ pub fn ui_system(
                  commands.entity(entity).despawn_recursive();
              }
         }
+
+        ui.separator();
+        ui.heading("Diagnostics");
+        ui.label(format!("Step: {}", diagnostics.step));
+        ui.label(format!("Kinetic Energy: {:.1}", diagnostics.kinetic_energy));
+        ui.label(format!("Momentum: ({:.1}, {:.1})", diagnostics.momentum.x, diagnostics.momentum.y));
+        ui.label(format!("Momentum Injection (last step): {:.3}", diagnostics.momentum_injection.length()));
+        ui.label(format!("Speed: mean {:.1}, max {:.1}", diagnostics.mean_speed, diagnostics.max_speed));
+        ui.label(format!("Collisions (last step): {}", diagnostics.collisions));
+        ui.label(format!("Temperature (mean ½mv²): {:.1}", diagnostics.temperature));
+        ui.label(format!("Spawned / Absorbed / Expired: {} / {} / {}", diagnostics.spawned, diagnostics.absorbed, diagnostics.expired));
+        if diagnostics.quarantined > 0 {
+            ui.colored_label(egui::Color32::YELLOW, format!("Quarantined particles: {}", diagnostics.quarantined));
+        }
+
+        // Edits can leave the rules invalid (e.g. a curve without points); list what is wrong
+        let problems = alchemy.validate();
+        if !problems.is_empty() {
+            ui.separator();
+            ui.colored_label(egui::Color32::RED, "Rule problems:");
+            for problem in problems.iter() {
+                ui.colored_label(egui::Color32::RED, problem.to_string());
+            }
+        }
     });
 
     // 2. Alchemy Editor (Matrix)
@@ -103,7 +215,30 @@ pub fn ui_system(
 
     // 3. Field Shape Editor
     egui::Window::new("Field Shape Editor").show(contexts.ctx_mut(), |ui| {
-        for p_def in alchemy.particle_types.iter_mut() {
+        // Shared by all grid fields; changing the cell size or count clears them
+        ui.horizontal(|ui| {
+            ui.label("Grid Cell Size:");
+            if ui.add(egui::Slider::new(&mut grids.cell_size, 2.0..=50.0)).changed() {
+                grids.grids.values_mut().for_each(|grid| grid.fill(0.0));
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Grid Cells per Side:");
+            ui.add(egui::Slider::new(&mut grids.cells, 16..=512));
+        });
+        if ui.button("Clear Grid Fields").clicked() {
+            grids.grids.values_mut().for_each(|grid| grid.fill(0.0));
+        }
+        ui.separator();
+
+        // Grid settings come from the first type emitting each field
+        let first_emitters: Vec<Option<String>> = alchemy.particle_types.iter()
+            .map(|def| def.emits_field
+                .and_then(|id| alchemy.particle_types.iter().find(|other| other.emits_field == Some(id)))
+                .map(|first| first.name.clone()))
+            .collect();
+
+        for (p_def, first_emitter) in alchemy.particle_types.iter_mut().zip(first_emitters) {
             ui.collapsing(format!("{} Field Shape", p_def.name), |ui| {
                 let shape = &mut p_def.emission_shape;
                 let mut changed = false;
@@ -121,16 +256,69 @@ pub fn ui_system(
                     }
                 });
 
+                egui::ComboBox::from_id_source(format!("field_mode_{}", p_def.name))
+                    .selected_text(shape.mode.label())
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut shape.mode, FieldMode::Instant, FieldMode::Instant.label());
+                        let grid = match shape.mode {
+                            FieldMode::Grid { .. } => shape.mode,
+                            FieldMode::Instant => FieldMode::Grid { diffusion: 200.0, decay: 0.5 },
+                        };
+                        ui.selectable_value(&mut shape.mode, grid, grid.label());
+                    });
+                if let FieldMode::Grid { diffusion, decay } = &mut shape.mode {
+                    ui.horizontal(|ui| {
+                        ui.label("Diffusion:");
+                        ui.add(egui::Slider::new(diffusion, 0.0..=5000.0).logarithmic(true));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Decay (per s):");
+                        ui.add(egui::Slider::new(decay, 0.0..=10.0).logarithmic(true));
+                    });
+                    ui.label("Emitters deposit Global Strength per second; the curve is unused.");
+                    if p_def.emits_field.is_some_and(|field_id| grids.capped.contains(&field_id)) {
+                        ui.colored_label(egui::Color32::YELLOW,
+                            "Diffusion is too fast for the cell size and frame time; it is capped and spreads slower than set. Lower it or enlarge the cells.");
+                    }
+                }
+                let softening = shape.softening;
+                egui::ComboBox::from_id_source(format!("softening_{}", p_def.name))
+                    .selected_text(format!("Softening: {}", softening.label()))
+                    .show_ui(ui, |ui| {
+                        // Switching modes keeps the distance
+                        let value = softening.parameter();
+                        for option in [
+                            Softening::Skip { distance: value },
+                            Softening::Clamp { distance: value },
+                            Softening::Plummer { length: value },
+                        ] {
+                            ui.selectable_value(&mut shape.softening, option, option.label());
+                        }
+                    });
+                ui.horizontal(|ui| {
+                    ui.label(match shape.softening {
+                        Softening::Plummer { .. } => "Softening Length:",
+                        _ => "Softening Distance:",
+                    });
+                    ui.add(egui::DragValue::new(shape.softening.parameter_mut()).speed(0.1).clamp_range(0.0..=f32::MAX));
+                });
+
+                if let Some(first) = first_emitter.filter(|first| *first != p_def.name) {
+                    ui.label(format!("The field's mode and grid settings are those of {}.", first));
+                }
+
                 ui.separator();
                 ui.label("Curve Points (Distance 0.0 -> 1.0)");
                 
                 let mut points_to_remove = Vec::new();
+                let point_count = shape.points.len();
                 for (i, point) in shape.points.iter_mut().enumerate() {
                     ui.horizontal(|ui| {
                         ui.label(format!("P{}:", i));
                         if ui.add(egui::Slider::new(&mut point.x, 0.0..=1.0).text("Dist")).changed() { changed = true; }
                         if ui.add(egui::Slider::new(&mut point.y, -1.0..=1.0).text("Val")).changed() { changed = true; }
-                        if ui.button("X").clicked() {
+                        // A curve needs at least two points
+                        if ui.add_enabled(point_count > 2, egui::Button::new("X")).clicked() {
                             points_to_remove.push(i);
                             changed = true;
                         }
@@ -204,16 +392,17 @@ fn update_texture_from_shape(image: &mut Image, shape: &FieldShape) {
     }
 }
 
-/// Syncs the visuals (Mesh Scale & Intensity) with the AlchemyRules
+/// Syncs the visuals (Mesh Scale, Intensity, Colour & LUT Texture) with the AlchemyRules
+#[allow(clippy::type_complexity)]
 pub fn sync_field_visualization(
     alchemy: Res<AlchemyRules>,
-    particle_query: Query<(Entity, &ParticleTypeID, &Children), With<Particle>>,
+    particle_query: Query<(Entity, &ParticleTypeID, &Children), (With<Particle>, Without<Quarantined>)>,
     mut transform_query: Query<&mut Transform>,
     mut material_handles: Query<&mut Handle<FieldMaterial>>,
     mut materials: ResMut<Assets<FieldMaterial>>,
 ) {
     for (_entity, type_id, children) in particle_query.iter() {
-        let def = &alchemy.particle_types[type_id.0];
+        let Some(def) = alchemy.particle_types.get(type_id.0) else { continue; };
         
         for child in children.iter() {
             // 1. Update Intensity 
@@ -224,6 +413,15 @@ pub fn sync_field_visualization(
                      if (material.intensity - target_intensity).abs() > 0.01 {
                          material.intensity = target_intensity;
                      }
+                     // Colour and texture change when a whole ruleset is swapped in
+                     if material.color != def.default_color {
+                         material.color = def.default_color;
+                     }
+                     if let Some(texture) = &def.field_texture {
+                         if material.lut_texture != *texture {
+                             material.lut_texture = texture.clone();
+                         }
+                     }
                 }
             }
 
@@ -245,51 +443,1317 @@ fn spawn_particle(
     type_id: usize, 
     pos: Vec2, 
     def: &ParticleTypeDefinition,
-    meshes: &mut ResMut<Assets<Mesh>>,
-    materials: &mut ResMut<Assets<FieldMaterial>>,
 ) {
     let mut rng = rand::thread_rng();
     use rand::Rng;
     
     let jitter_x = rng.gen_range(-50.0..50.0);
     let jitter_y = rng.gen_range(-50.0..50.0);
-    let start_pos = Vec3::new(pos.x + jitter_x, pos.y + jitter_y, 0.0);
-
-    commands.spawn((
-        SpriteBundle {
-            sprite: Sprite {
-                color: Color::WHITE, 
-                custom_size: Some(Vec2::new(4.0, 4.0)),
-                ..default()
-            },
-            transform: Transform::from_translation(start_pos),
-            ..default()
-        },
-        Particle,
-        ParticleTypeID(type_id),
-        Mass(def.default_mass),
-        Velocity(Vec2::ZERO),
-    ))
-    .with_children(|parent| {
-        if def.emits_field.is_some() {
-            let mesh_handle = meshes.add(Mesh::from(Circle::new(1.0))); 
-            let texture_handle = def.field_texture.clone().unwrap_or_default(); 
-            let intensity = (def.emission_shape.strength_scale / 1000.0).clamp(0.2, 2.0);
-
-            parent.spawn(MaterialMesh2dBundle {
-                mesh: Mesh2dHandle(mesh_handle),
-                material: materials.add(FieldMaterial {
-                    color: def.default_color,
-                    intensity,
-                    lut_texture: texture_handle,
-                }),
-                transform: Transform {
-                    translation: Vec3::new(0.0, 0.0, -0.1),
-                    scale: Vec3::new(def.emission_shape.max_radius, def.emission_shape.max_radius, 1.0),
-                    ..default()
-                },
-                ..default()
+    let start_pos = Vec2::new(pos.x + jitter_x, pos.y + jitter_y);
+
+    // Sprite and field halo are attached by render::attach_particle_visuals
+    commands.spawn(ParticleBundle::new(type_id, start_pos, Vec2::ZERO, def.default_mass));
+}
+
+/// Render mode selector with a legend for the active colour mode
+pub fn render_settings_ui(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<RenderSettings>,
+    mut trail_settings: ResMut<TrailSettings>,
+    alchemy: Res<AlchemyRules>,
+) {
+    egui::Window::new("Render Settings").show(contexts.ctx_mut(), |ui| {
+        egui::ComboBox::from_label("Colour By")
+            .selected_text(settings.color_mode.label())
+            .show_ui(ui, |ui| {
+                for mode in ColorMode::ALL {
+                    ui.selectable_value(&mut settings.color_mode, mode, mode.label());
+                }
+            });
+
+        match settings.color_mode {
+            ColorMode::Type => {
+                for def in alchemy.particle_types.iter() {
+                    ui.horizontal(|ui| {
+                        color_swatch(ui, def.default_color);
+                        ui.label(&def.name);
+                    });
+                }
+            }
+            ColorMode::Cluster => {
+                ui.horizontal(|ui| {
+                    for id in 0..8 {
+                        color_swatch(ui, cluster_color(id));
+                    }
+                    ui.label("...");
+                });
+                ui.label("Link distance is set in the Clusters window.");
+            }
+            scalar_mode => {
+                egui::ComboBox::from_label("Colour Ramp")
+                    .selected_text(settings.color_ramp.label())
+                    .show_ui(ui, |ui| {
+                        for ramp in ColorRamp::ALL {
+                            ui.selectable_value(&mut settings.color_ramp, ramp, ramp.label());
+                        }
+                    });
+
+                // Legend: gradient bar with the value range underneath
+                let (response, painter) = ui.allocate_painter(egui::Vec2::new(200.0, 16.0), egui::Sense::hover());
+                let rect = response.rect;
+                let steps = 64;
+                for i in 0..steps {
+                    let t0 = i as f32 / steps as f32;
+                    let t1 = (i + 1) as f32 / steps as f32;
+                    let [r, g, b] = settings.color_ramp.sample(t0);
+                    painter.rect_filled(
+                        egui::Rect::from_min_max(
+                            egui::Pos2::new(rect.left() + t0 * rect.width(), rect.top()),
+                            egui::Pos2::new(rect.left() + t1 * rect.width(), rect.bottom()),
+                        ),
+                        0.0,
+                        egui::Color32::from_rgb((r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8),
+                    );
+                }
+
+                let max_label = if scalar_mode == ColorMode::Speed {
+                    "C".to_string()
+                } else {
+                    format!("{:.1}", settings.range_max)
+                };
+                ui.horizontal(|ui| {
+                    ui.label("0");
+                    ui.add_space(160.0);
+                    ui.label(max_label);
+                });
+
+                if scalar_mode != ColorMode::Speed {
+                    ui.checkbox(&mut settings.auto_range, "Auto Range");
+                    ui.add_enabled(
+                        !settings.auto_range,
+                        egui::DragValue::new(&mut settings.range_max).speed(1.0).clamp_range(0.001..=f32::MAX),
+                    );
+                }
+            }
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Sprite Size:");
+            ui.add(egui::Slider::new(&mut settings.base_size, 1.0..=20.0));
+        });
+        ui.checkbox(&mut settings.size_by_mass, "Scale Size With Mass");
+
+        ui.separator();
+        ui.checkbox(&mut trail_settings.enabled, "Motion Trails");
+        if trail_settings.enabled {
+            ui.horizontal(|ui| {
+                ui.label("Length (steps):");
+                ui.add(egui::Slider::new(&mut trail_settings.length, 2..=500));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Max Total Points:");
+                ui.add(egui::DragValue::new(&mut trail_settings.max_total_points).speed(100.0).clamp_range(0..=1_000_000));
+            });
+
+            let num_types = alchemy.particle_types.len();
+            if trail_settings.per_type.len() < num_types {
+                trail_settings.per_type.resize(num_types, true);
+            }
+            ui.horizontal_wrapped(|ui| {
+                for (idx, def) in alchemy.particle_types.iter().enumerate() {
+                    ui.checkbox(&mut trail_settings.per_type[idx], &def.name);
+                }
+            });
+        }
+    });
+}
+
+fn color_swatch(ui: &mut egui::Ui, color: Color) {
+    let [r, g, b, _] = color.as_rgba_u8();
+    let (rect, _) = ui.allocate_exact_size(egui::Vec2::new(12.0, 12.0), egui::Sense::hover());
+    ui.painter().rect_filled(rect, 2.0, egui::Color32::from_rgb(r, g, b));
+}
+
+/// Controls for the aggregate field heatmap and the force overlays
+pub fn field_overlay_ui(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<FieldOverlaySettings>,
+    mut force_settings: ResMut<ForceOverlaySettings>,
+    alchemy: Res<AlchemyRules>,
+) {
+    egui::Window::new("Field & Force Overlay").default_open(false).show(contexts.ctx_mut(), |ui| {
+        ui.checkbox(&mut settings.enabled, "Show Superposed Field");
+
+        // Name each field by the particle types emitting it
+        let mut field_ids: Vec<usize> = alchemy.particle_types.iter().filter_map(|def| def.emits_field).collect();
+        field_ids.sort_unstable();
+        field_ids.dedup();
+        let field_label = |id: usize| {
+            let emitters: Vec<&str> = alchemy.particle_types.iter()
+                .filter(|def| def.emits_field == Some(id))
+                .map(|def| def.name.as_str())
+                .collect();
+            format!("Field {} ({})", id, emitters.join(", "))
+        };
+
+        egui::ComboBox::from_label("Field Type")
+            .selected_text(field_label(settings.field_id))
+            .show_ui(ui, |ui| {
+                for id in field_ids.iter() {
+                    ui.selectable_value(&mut settings.field_id, *id, field_label(*id));
+                }
+            });
+
+        ui.horizontal(|ui| {
+            ui.label("Resolution:");
+            ui.add(egui::Slider::new(&mut settings.resolution, 16..=512));
+        });
+        ui.checkbox(&mut settings.auto_range, "Auto Range");
+        ui.add_enabled(
+            !settings.auto_range,
+            egui::DragValue::new(&mut settings.range).speed(1.0).clamp_range(0.001..=f32::MAX),
+        );
+        ui.checkbox(&mut settings.isolines, "Isolines");
+        if settings.isolines {
+            ui.horizontal(|ui| {
+                ui.label("Isoline Count:");
+                ui.add(egui::Slider::new(&mut settings.isoline_count, 2..=32));
+            });
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            color_swatch(ui, ColorRamp::Coolwarm.color(0.0));
+            ui.label("Negative (repels w > 0)");
+        });
+        ui.horizontal(|ui| {
+            color_swatch(ui, ColorRamp::Coolwarm.color(1.0));
+            ui.label("Positive (attracts w > 0)");
+        });
+
+        ui.separator();
+        ui.heading("Forces");
+        ui.checkbox(&mut force_settings.particle_forces, "Net Force Arrows");
+        ui.checkbox(&mut force_settings.vector_field, "Test Particle Vector Field");
+        ui.checkbox(&mut force_settings.streamlines, "Streamlines");
+
+        let test_name = alchemy.particle_types.get(force_settings.test_type)
+            .map(|def| def.name.clone())
+            .unwrap_or_else(|| "-".to_string());
+        egui::ComboBox::from_label("Test Particle")
+            .selected_text(test_name)
+            .show_ui(ui, |ui| {
+                for (idx, def) in alchemy.particle_types.iter().enumerate() {
+                    ui.selectable_value(&mut force_settings.test_type, idx, &def.name);
+                }
+            });
+        ui.horizontal(|ui| {
+            ui.label("Grid Spacing:");
+            ui.add(egui::Slider::new(&mut force_settings.grid_spacing, 10.0..=200.0));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Arrow Scale:");
+            ui.add(egui::Slider::new(&mut force_settings.arrow_scale, 0.001..=1.0).logarithmic(true));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Streamline Steps:");
+            ui.add(egui::Slider::new(&mut force_settings.streamline_steps, 5..=300));
+        });
+    });
+}
+
+#[derive(Default)]
+pub struct ShareUiState {
+    export_code: String,
+    import_code: String,
+    // Ok(message) or Err(message) from the last import
+    status: Option<Result<String, String>>,
+}
+
+/// Export / import of the full ruleset as a copy-paste share code
+pub fn share_code_ui(
+    mut contexts: EguiContexts,
+    mut alchemy: ResMut<AlchemyRules>,
+    mut global_consts: ResMut<GlobalConstants>,
+    mut trail_settings: ResMut<TrailSettings>,
+    mut commands: Commands,
+    mut particle_query: Query<(Entity, &mut ParticleTypeID, &mut Mass), With<Particle>>,
+    mut state: Local<ShareUiState>,
+) {
+    egui::Window::new("Share Ruleset").default_open(false).show(contexts.ctx_mut(), |ui| {
+        if ui.button("Export Code (Copy to Clipboard)").clicked() {
+            state.export_code = share::encode(&alchemy, &global_consts);
+            let code = state.export_code.clone();
+            ui.output_mut(|o| o.copied_text = code);
+        }
+        if !state.export_code.is_empty() {
+            let mut code = state.export_code.as_str();
+            ui.add(egui::TextEdit::multiline(&mut code).desired_rows(3).desired_width(300.0));
+        }
+
+        ui.separator();
+        ui.label("Paste a code to load it:");
+        ui.add(egui::TextEdit::multiline(&mut state.import_code).desired_rows(3).desired_width(300.0));
+
+        if ui.button("Import").clicked() {
+            state.status = Some(match share::decode(&state.import_code) {
+                Ok(shared) => {
+                    replace_rules(&mut alchemy, shared.rules, &mut global_consts, Some(shared.constants), &mut trail_settings, &mut commands, &mut particle_query);
+                    Ok(format!("Loaded {} particle types", alchemy.particle_types.len()))
+                }
+                Err(e) => Err(e.to_string()),
+            });
+        }
+
+        match &state.status {
+            Some(Ok(message)) => { ui.colored_label(egui::Color32::GREEN, message); }
+            Some(Err(message)) => { ui.colored_label(egui::Color32::RED, message); }
+            None => {}
+        }
+    });
+}
+
+/// Per-type on/off flags moved to the new type indices; types new to the rules start on.
+fn remap_per_type(per_type: &[bool], remap: &[Option<usize>], num_types: usize) -> Vec<bool> {
+    let mut remapped = vec![true; num_types];
+    for (old_id, new_id) in remap.iter().enumerate() {
+        if let (Some(new_id), Some(&enabled)) = (new_id, per_type.get(old_id)) {
+            remapped[*new_id] = enabled;
+        }
+    }
+    remapped
+}
+
+/// New index of every old particle type: the type of the same name, or else the type at the same
+/// index if no old type claims it by name. `None` when neither exists.
+fn type_remap(old_types: &[ParticleTypeDefinition], new_types: &[ParticleTypeDefinition]) -> Vec<Option<usize>> {
+    let by_name: Vec<Option<usize>> = old_types.iter()
+        .map(|old_def| new_types.iter().position(|def| def.name == old_def.name))
+        .collect();
+    by_name.iter().enumerate()
+        .map(|(idx, found)| found.or_else(|| {
+            (idx < new_types.len() && !by_name.contains(&Some(idx))).then_some(idx)
+        }))
+        .collect()
+}
+
+/// Swaps in a new ruleset and remaps the particles onto it (see `type_remap`);
+/// particles left without a type are despawned.
+/// Masses still at their type's old default follow the new default.
+/// `new_constants` travel with the rules (saved or shared together), so their per-type lists
+/// already match them; with `None` the current constants stay and their lists are remapped.
+fn replace_rules(
+    alchemy: &mut AlchemyRules,
+    new_rules: AlchemyRules,
+    global_consts: &mut GlobalConstants,
+    new_constants: Option<GlobalConstants>,
+    trail_settings: &mut TrailSettings,
+    commands: &mut Commands,
+    particle_query: &mut Query<(Entity, &mut ParticleTypeID, &mut Mass), With<Particle>>,
+) {
+    let num_types = new_rules.particle_types.len();
+    let remap = type_remap(&alchemy.particle_types, &new_rules.particle_types);
+
+    trail_settings.per_type = remap_per_type(&trail_settings.per_type, &remap, num_types);
+    match new_constants {
+        Some(constants) => *global_consts = constants,
+        None => {
+            let thermostat = &mut global_consts.thermostat;
+            thermostat.per_type = remap_per_type(&thermostat.per_type, &remap, num_types);
+        }
+    }
+
+    for (entity, mut type_id, mut mass) in particle_query.iter_mut() {
+        match remap.get(type_id.0).copied().flatten() {
+            Some(new_id) => {
+                if mass.0 == alchemy.particle_types[type_id.0].default_mass {
+                    mass.0 = new_rules.particle_types[new_id].default_mass;
+                }
+                if type_id.0 != new_id {
+                    type_id.0 = new_id;
+                }
+            }
+            None => commands.entity(entity).despawn_recursive(),
+        }
+    }
+
+    *alchemy = new_rules;
+}
+
+/// Load / save the ruleset as a RON file, reloading it whenever it changes on disk
+pub fn rules_file_ui(
+    mut contexts: EguiContexts,
+    mut rules_file: ResMut<RulesFile>,
+    mut alchemy: ResMut<AlchemyRules>,
+    mut global_consts: ResMut<GlobalConstants>,
+    mut trail_settings: ResMut<TrailSettings>,
+    mut commands: Commands,
+    mut particle_query: Query<(Entity, &mut ParticleTypeID, &mut Mass), With<Particle>>,
+) {
+    let mut load = rules_file.poll_changed();
+    let reloading = load;
+
+    egui::Window::new("Rules File").default_open(false).show(contexts.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            ui.label("File:");
+            ui.text_edit_singleline(&mut rules_file.path);
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("Load").clicked() {
+                load = true;
+            }
+            if ui.button("Save").clicked() {
+                let path = std::path::PathBuf::from(&rules_file.path);
+                rules_file.status = Some(match share::save_rules(&path, &alchemy, &global_consts) {
+                    Ok(()) => {
+                        rules_file.mark_current();
+                        Ok(format!("Saved to {}", path.display()))
+                    }
+                    Err(e) => Err(e.to_string()),
+                });
+            }
+            ui.checkbox(&mut rules_file.watch, "Reload on change");
+        });
+
+        match &rules_file.status {
+            Some(Ok(message)) => { ui.colored_label(egui::Color32::GREEN, message); }
+            Some(Err(message)) => { ui.colored_label(egui::Color32::RED, message); }
+            None => {}
+        }
+    });
+
+    if !load {
+        return;
+    }
+    // On failure the current rules stay in place
+    let path = std::path::PathBuf::from(&rules_file.path);
+    rules_file.status = Some(match share::load_rules(&path) {
+        Ok(shared) => {
+            rules_file.mark_current();
+            replace_rules(&mut alchemy, shared.rules, &mut global_consts, Some(shared.constants), &mut trail_settings, &mut commands, &mut particle_query);
+            let verb = if reloading { "Reloaded" } else { "Loaded" };
+            Ok(format!("{} {} ({} particle types)", verb, path.display(), alchemy.particle_types.len()))
+        }
+        Err(e) => Err(format!("{}: {}", path.display(), e)),
+    });
+}
+
+/// Random ruleset generation and mutation
+pub fn randomizer_ui(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<RandomizerSettings>,
+    mut alchemy: ResMut<AlchemyRules>,
+    mut global_consts: ResMut<GlobalConstants>,
+    mut trail_settings: ResMut<TrailSettings>,
+    mut commands: Commands,
+    mut particle_query: Query<(Entity, &mut ParticleTypeID, &mut Mass), With<Particle>>,
+) {
+    egui::Window::new("Randomize Universe").default_open(false).show(contexts.ctx_mut(), |ui| {
+        egui::Grid::new("randomizer_settings").num_columns(2).show(ui, |ui| {
+            ui.label("Particle Types:");
+            ui.add(egui::Slider::new(&mut settings.num_types, 1..=16));
+            ui.end_row();
+
+            ui.label("Weight Range (±):");
+            ui.add(egui::Slider::new(&mut settings.weight_range, 0.1..=10.0));
+            ui.end_row();
+
+            ui.label("Sparsity:");
+            ui.add(egui::Slider::new(&mut settings.sparsity, 0.0..=1.0));
+            ui.end_row();
+
+            ui.label("Symmetric:");
+            ui.checkbox(&mut settings.symmetric, "w[a,b] = w[b,a]");
+            ui.end_row();
+
+            ui.label("Curve Family:");
+            egui::ComboBox::from_id_source("curve_family")
+                .selected_text(settings.curve_family.label())
+                .show_ui(ui, |ui| {
+                    for family in CurveFamily::ALL {
+                        ui.selectable_value(&mut settings.curve_family, family, family.label());
+                    }
+                });
+            ui.end_row();
+
+            ui.label("Curve Points:");
+            ui.add(egui::Slider::new(&mut settings.curve_points, 2..=12));
+            ui.end_row();
+
+            ui.label("Radius:");
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut settings.radius_min).speed(1.0).clamp_range(1.0..=2000.0));
+                ui.label("to");
+                ui.add(egui::DragValue::new(&mut settings.radius_max).speed(1.0).clamp_range(1.0..=2000.0));
+            });
+            ui.end_row();
+
+            ui.label("Strength:");
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut settings.strength_min).speed(10.0).clamp_range(0.0..=10000.0));
+                ui.label("to");
+                ui.add(egui::DragValue::new(&mut settings.strength_max).speed(10.0).clamp_range(0.0..=10000.0));
+            });
+            ui.end_row();
+
+            ui.label("Seed:");
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut settings.seed));
+                if ui.button("New Seed").clicked() {
+                    settings.seed = rand::random();
+                }
             });
+            ui.end_row();
+        });
+
+        if ui.button("Randomize Universe").clicked() {
+            let rules = generator::generate(&settings);
+            replace_rules(&mut alchemy, rules, &mut global_consts, None, &mut trail_settings, &mut commands, &mut particle_query);
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Mutation Amount:");
+            ui.add(egui::Slider::new(&mut settings.mutation_amount, 0.01..=0.5));
+        });
+        if ui.button("Mutate Current Rules Slightly").clicked() {
+            let rules = generator::mutate(&alchemy, &settings);
+            replace_rules(&mut alchemy, rules, &mut global_consts, None, &mut trail_settings, &mut commands, &mut particle_query);
+            // Step the seed so repeated clicks keep exploring, but stay reproducible
+            settings.seed = settings.seed.wrapping_add(1);
         }
     });
 }
+
+/// Live cluster list and the "periodic table" of recurring compositions
+pub fn clusters_ui(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<ClusterSettings>,
+    mut cluster_analysis: ResMut<ClusterAnalysis>,
+    alchemy: Res<AlchemyRules>,
+) {
+    egui::Window::new("Clusters").default_open(false).show(contexts.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            ui.label("Link Distance:");
+            ui.add(egui::Slider::new(&mut settings.link_distance, 1.0..=200.0));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Min Size:");
+            ui.add(egui::Slider::new(&mut settings.min_size, 1..=50));
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Compute").clicked() {
+                settings.compute_requested = true;
+            }
+            ui.checkbox(&mut settings.live, "Live");
+        });
+
+        ui.separator();
+        ui.label(format!("{} clusters", cluster_analysis.clusters.len()));
+        egui::ScrollArea::vertical().id_source("cluster_list").max_height(200.0).show(ui, |ui| {
+            egui::Grid::new("cluster_list_grid").striped(true).show(ui, |ui| {
+                ui.label("Size");
+                ui.label("Composition");
+                ui.label("Centre");
+                ui.label("Radius");
+                ui.label("|v|");
+                ui.label("Spin");
+                ui.end_row();
+
+                for cluster in cluster_analysis.clusters.iter().take(100) {
+                    ui.label(cluster.size.to_string());
+                    ui.label(analysis::composition_label(&cluster.composition, &alchemy));
+                    ui.label(format!("({:.0}, {:.0})", cluster.center_of_mass.x, cluster.center_of_mass.y));
+                    ui.label(format!("{:.1}", cluster.radius));
+                    ui.label(format!("{:.1}", cluster.velocity.length()));
+                    ui.label(format!("{:+.3}", cluster.spin));
+                    ui.end_row();
+                }
+            });
+        });
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.heading("Periodic Table");
+            if ui.button("Reset").clicked() {
+                cluster_analysis.catalog.clear();
+            }
+        });
+
+        let mut entries: Vec<_> = cluster_analysis.catalog.iter().collect();
+        entries.sort_by_key(|(_, record)| std::cmp::Reverse(record.frames_observed));
+        egui::ScrollArea::vertical().id_source("periodic_table").max_height(200.0).show(ui, |ui| {
+            egui::Grid::new("periodic_table_grid").striped(true).show(ui, |ui| {
+                ui.label("Structure");
+                ui.label("Frames Seen");
+                ui.label("Peak Count");
+                ui.end_row();
+
+                for (composition, record) in entries.into_iter().take(100) {
+                    ui.label(analysis::composition_label(composition, &alchemy));
+                    ui.label(record.frames_observed.to_string());
+                    ui.label(record.peak_count.to_string());
+                    ui.end_row();
+                }
+            });
+        });
+    });
+}
+
+/// g(r), nearest-neighbor and ψ6 analysis with plots and CSV export
+pub fn structure_ui(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<StructureSettings>,
+    structure: Res<StructureAnalysis>,
+    alchemy: Res<AlchemyRules>,
+    mut export_status: Local<Option<Result<String, String>>>,
+) {
+    egui::Window::new("Structure Analysis").default_open(false).show(contexts.ctx_mut(), |ui| {
+        egui::Grid::new("structure_settings").num_columns(2).show(ui, |ui| {
+            ui.label("g(r) Range:");
+            ui.add(egui::Slider::new(&mut settings.r_max, 10.0..=1000.0));
+            ui.end_row();
+            ui.label("Bins:");
+            ui.add(egui::Slider::new(&mut settings.bins, 10..=300));
+            ui.end_row();
+            ui.label("Neighbor Cutoff:");
+            ui.add(egui::Slider::new(&mut settings.neighbor_cutoff, 1.0..=200.0));
+            ui.end_row();
+            ui.label("Periodic Box:");
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut settings.periodic, "");
+                ui.add_enabled(settings.periodic, egui::DragValue::new(&mut settings.box_size.x).speed(1.0).clamp_range(1.0..=100_000.0));
+                ui.label("x");
+                ui.add_enabled(settings.periodic, egui::DragValue::new(&mut settings.box_size.y).speed(1.0).clamp_range(1.0..=100_000.0));
+            });
+            ui.end_row();
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("Compute").clicked() {
+                settings.compute_requested = true;
+            }
+            ui.checkbox(&mut settings.live, "Live");
+        });
+
+        ui.separator();
+        ui.label("Radial Distribution g(r)");
+        if structure.rdf.is_empty() && structure.particles.len() > 1 {
+            ui.colored_label(egui::Color32::YELLOW, "Undefined: the particles span no area. Use a periodic box.");
+        }
+        let (response, painter) = ui.allocate_painter(egui::Vec2::new(320.0, 140.0), egui::Sense::hover());
+        let rect = response.rect;
+        painter.rect_filled(rect, 0.0, egui::Color32::from_gray(20));
+
+        let g_max = structure.rdf.iter()
+            .flat_map(|curve| curve.g.iter().copied())
+            .fold(1.0_f32, f32::max);
+        // g = 1 (ideal gas) reference line
+        let one_y = rect.bottom() - rect.height() / g_max;
+        painter.line_segment(
+            [egui::Pos2::new(rect.left(), one_y), egui::Pos2::new(rect.right(), one_y)],
+            egui::Stroke::new(1.0, egui::Color32::GRAY),
+        );
+
+        let pair_color = |a: usize, b: usize| {
+            let [ra, ga, ba, _] = alchemy.particle_types.get(a).map_or(Color::WHITE, |d| d.default_color).as_rgba_u8();
+            let [rb, gb, bb, _] = alchemy.particle_types.get(b).map_or(Color::WHITE, |d| d.default_color).as_rgba_u8();
+            let mix = |x: u8, y: u8| ((x as u16 + y as u16) / 2) as u8;
+            egui::Color32::from_rgb(mix(ra, rb), mix(ga, gb), mix(ba, bb))
+        };
+
+        for curve in structure.rdf.iter() {
+            if curve.g.len() < 2 { continue; }
+            let points: Vec<egui::Pos2> = curve.g.iter().enumerate().map(|(i, &g)| {
+                let x = rect.left() + (i as f32 + 0.5) / curve.g.len() as f32 * rect.width();
+                let y = rect.bottom() - g / g_max * rect.height();
+                egui::Pos2::new(x, y)
+            }).collect();
+            painter.add(egui::Shape::line(points, egui::Stroke::new(1.5, pair_color(curve.type_a, curve.type_b))));
+        }
+        ui.horizontal(|ui| {
+            ui.label("0");
+            ui.add_space(260.0);
+            ui.label(format!("{:.0}", structure.bin_width * structure.rdf.first().map_or(0, |c| c.g.len()) as f32));
+        });
+        ui.horizontal_wrapped(|ui| {
+            for curve in structure.rdf.iter() {
+                let name = |t: usize| alchemy.particle_types.get(t).map_or("?", |d| d.name.as_str());
+                ui.colored_label(pair_color(curve.type_a, curve.type_b), format!("{}-{}", name(curve.type_a), name(curve.type_b)));
+            }
+        });
+
+        ui.separator();
+        ui.label("Nearest Neighbors");
+        let (response, painter) = ui.allocate_painter(egui::Vec2::new(320.0, 60.0), egui::Sense::hover());
+        let rect = response.rect;
+        painter.rect_filled(rect, 0.0, egui::Color32::from_gray(20));
+        let max_count = structure.neighbor_histogram.iter().copied().max().unwrap_or(0).max(1);
+        let bar_width = rect.width() / structure.neighbor_histogram.len().max(1) as f32;
+        for (k, &count) in structure.neighbor_histogram.iter().enumerate() {
+            let height = count as f32 / max_count as f32 * rect.height();
+            painter.rect_filled(
+                egui::Rect::from_min_max(
+                    egui::Pos2::new(rect.left() + k as f32 * bar_width + 1.0, rect.bottom() - height),
+                    egui::Pos2::new(rect.left() + (k + 1) as f32 * bar_width - 1.0, rect.bottom()),
+                ),
+                0.0,
+                egui::Color32::LIGHT_BLUE,
+            );
+        }
+        ui.label(format!("0 .. {} neighbors", structure.neighbor_histogram.len().saturating_sub(1)));
+        for (type_id, mean) in structure.mean_neighbors_per_type.iter().enumerate() {
+            let name = alchemy.particle_types.get(type_id).map_or("?", |d| d.name.as_str());
+            ui.label(format!("{}: {:.2} neighbors on average", name, mean));
+        }
+
+        ui.separator();
+        ui.label(format!("Global order |<ψ6>|: {:.3}", structure.psi6_global));
+        ui.label(format!("Local order <|ψ6|>: {:.3}", structure.psi6_local_mean));
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("CSV Prefix:");
+            ui.text_edit_singleline(&mut settings.export_prefix);
+        });
+        if ui.button("Export CSV").clicked() {
+            *export_status = Some(match structure.write_csv(&settings.export_prefix, &alchemy) {
+                Ok(()) => Ok(format!("Wrote {0}_rdf.csv and {0}_particles.csv", settings.export_prefix)),
+                Err(e) => Err(e.to_string()),
+            });
+        }
+        match export_status.as_ref() {
+            Some(Ok(message)) => { ui.colored_label(egui::Color32::GREEN, message); }
+            Some(Err(message)) => { ui.colored_label(egui::Color32::RED, message); }
+            None => {}
+        }
+    });
+}
+
+#[derive(Default)]
+pub struct SnapshotUiState {
+    path: String,
+    status: Option<Result<String, String>>,
+}
+
+/// Save / load the particle state to a RON snapshot file
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn snapshot_ui(
+    mut contexts: EguiContexts,
+    mut commands: Commands,
+    alchemy: Res<AlchemyRules>,
+    mut diagnostics: ResMut<SimDiagnostics>,
+    particle_query: Query<(Entity, &Transform, &Velocity, &Mass, &Momentum, &ParticleTypeID, &Age), With<Particle>>,
+    obstacle_query: Query<(Entity, &Obstacle)>,
+    external_query: Query<(Entity, &ExternalField)>,
+    source_query: Query<(Entity, &ParticleSource)>,
+    sink_query: Query<(Entity, &Sink)>,
+    mut state: Local<SnapshotUiState>,
+) {
+    if state.path.is_empty() {
+        state.path = "snapshot.ron".to_string();
+    }
+
+    egui::Window::new("Snapshot").default_open(false).show(contexts.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            ui.label("File:");
+            ui.text_edit_singleline(&mut state.path);
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("Save").clicked() {
+                let snapshot = Snapshot {
+                    step: diagnostics.step,
+                    particles: particle_query.iter()
+                        .map(|(_, transform, velocity, mass, momentum, type_id, age)| ParticleState {
+                            type_id: type_id.0,
+                            position: transform.translation.truncate(),
+                            velocity: velocity.0,
+                            mass: mass.0,
+                            momentum: Some(momentum.0),
+                            age: age.0,
+                        })
+                        .collect(),
+                    obstacles: obstacle_query.iter().map(|(_, obstacle)| obstacle.clone()).collect(),
+                    external_fields: external_query.iter().map(|(_, field)| field.clone()).collect(),
+                    sources: source_query.iter().map(|(_, source)| source.clone()).collect(),
+                    sinks: sink_query.iter().map(|(_, sink)| sink.clone()).collect(),
+                };
+                state.status = Some(match snapshot.save(std::path::Path::new(&state.path)) {
+                    Ok(()) => Ok(format!("Saved {} particles, {} obstacles", snapshot.particles.len(), snapshot.obstacles.len())),
+                    Err(e) => Err(e.to_string()),
+                });
+            }
+
+            if ui.button("Load").clicked() {
+                state.status = Some(match Snapshot::load(std::path::Path::new(&state.path)) {
+                    Ok(snapshot) => {
+                        let num_types = alchemy.particle_types.len();
+                        if let Some(bad) = snapshot.particles.iter().find(|p| p.type_id >= num_types) {
+                            Err(format!("Snapshot uses particle type {}, but only {} types exist", bad.type_id, num_types))
+                        } else {
+                            for (entity, ..) in particle_query.iter() {
+                                commands.entity(entity).despawn_recursive();
+                            }
+                            for (entity, _) in obstacle_query.iter() {
+                                commands.entity(entity).despawn();
+                            }
+                            for (entity, _) in external_query.iter() {
+                                commands.entity(entity).despawn();
+                            }
+                            for (entity, _) in source_query.iter() {
+                                commands.entity(entity).despawn();
+                            }
+                            for (entity, _) in sink_query.iter() {
+                                commands.entity(entity).despawn();
+                            }
+                            for p in snapshot.particles.iter() {
+                                commands.spawn(p.bundle());
+                            }
+                            for obstacle in snapshot.obstacles.iter() {
+                                commands.spawn(obstacle.clone());
+                            }
+                            for field in snapshot.external_fields.iter() {
+                                commands.spawn(field.clone());
+                            }
+                            for source in snapshot.sources.iter() {
+                                commands.spawn(source.clone());
+                            }
+                            for sink in snapshot.sinks.iter() {
+                                commands.spawn(sink.clone());
+                            }
+                            diagnostics.step = snapshot.step;
+                            Ok(format!("Loaded {} particles, {} obstacles", snapshot.particles.len(), snapshot.obstacles.len()))
+                        }
+                    }
+                    Err(e) => Err(e.to_string()),
+                });
+            }
+        });
+
+        match &state.status {
+            Some(Ok(message)) => { ui.colored_label(egui::Color32::GREEN, message); }
+            Some(Err(message)) => { ui.colored_label(egui::Color32::RED, message); }
+            None => {}
+        }
+    });
+}
+
+/// Tool selection and list for the obstacles drawn in the viewport
+pub fn obstacles_ui(
+    mut contexts: EguiContexts,
+    mut commands: Commands,
+    mut editor: ResMut<ObstacleEditor>,
+    alchemy: Res<AlchemyRules>,
+    obstacle_query: Query<(Entity, &Obstacle)>,
+) {
+    egui::Window::new("Obstacles").default_open(false).show(contexts.ctx_mut(), |ui| {
+        ui.horizontal_wrapped(|ui| {
+            for tool in ObstacleTool::ALL {
+                if ui.selectable_label(editor.tool == tool, tool.label()).clicked() && editor.tool != tool {
+                    editor.tool = tool;
+                    editor.cancel();
+                }
+            }
+        });
+        ui.label(match editor.tool {
+            ObstacleTool::Off => "Pick a tool to draw in the viewport.",
+            ObstacleTool::Segment | ObstacleTool::Rect => "Drag with the left button.",
+            ObstacleTool::Circle => "Drag from the centre with the left button.",
+            ObstacleTool::Polygon => "Click vertices, then right-click or Enter to close. Esc cancels.",
+            ObstacleTool::Erase => "Click an obstacle to remove it.",
+        });
+
+        ui.separator();
+        ui.label("New obstacles:");
+        ui.horizontal(|ui| {
+            ui.label("Restitution:");
+            ui.add(egui::Slider::new(&mut editor.restitution, 0.0..=1.0));
+        });
+
+        let mut field_ids: Vec<usize> = alchemy.particle_types.iter().filter_map(|def| def.emits_field).collect();
+        field_ids.sort_unstable();
+        field_ids.dedup();
+        let field_label = |id: Option<usize>| match id {
+            Some(id) => format!("Field {}", id),
+            None => "None".to_string(),
+        };
+        egui::ComboBox::from_label("Emits Field")
+            .selected_text(field_label(editor.emits_field))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut editor.emits_field, None, field_label(None));
+                for id in field_ids {
+                    ui.selectable_value(&mut editor.emits_field, Some(id), field_label(Some(id)));
+                }
+            });
+
+        ui.separator();
+        ui.label(format!("{} obstacles", obstacle_query.iter().len()));
+        egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+            for (entity, obstacle) in obstacle_query.iter() {
+                ui.horizontal(|ui| {
+                    let emits = obstacle.emits_field.map(|id| format!(", emits field {}", id)).unwrap_or_default();
+                    ui.label(format!("{} (e = {:.2}{})", obstacle.shape.describe(), obstacle.restitution, emits));
+                    if ui.small_button("X").clicked() {
+                        commands.entity(entity).despawn();
+                    }
+                });
+            }
+        });
+        if ui.button("Clear All Obstacles").clicked() {
+            for (entity, _) in obstacle_query.iter() {
+                commands.entity(entity).despawn();
+            }
+        }
+    });
+}
+
+fn vec2_edit(ui: &mut egui::Ui, label: &str, v: &mut Vec2) {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        ui.add(egui::DragValue::new(&mut v.x).speed(1.0).prefix("x "));
+        ui.add(egui::DragValue::new(&mut v.y).speed(1.0).prefix("y "));
+    });
+}
+
+fn scalar_edit(ui: &mut egui::Ui, label: &str, v: &mut f32) {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        ui.add(egui::DragValue::new(v).speed(1.0));
+    });
+}
+
+/// Kind selector and geometry of a region (shared by external fields and sinks)
+fn region_edit(ui: &mut egui::Ui, region: &mut Region) {
+    let region_name = |region: &Region| match region {
+        Region::Everywhere => "Everywhere",
+        Region::Rect { .. } => "Rectangle",
+        Region::Circle { .. } => "Circle",
+    };
+    egui::ComboBox::from_label("Region")
+        .selected_text(region_name(region))
+        .show_ui(ui, |ui| {
+            for option in [
+                Region::Everywhere,
+                Region::Rect { min: Vec2::splat(-200.0), max: Vec2::splat(200.0) },
+                Region::Circle { center: Vec2::ZERO, radius: 200.0 },
+            ] {
+                // Keep the current geometry when re-selecting the same kind
+                let selected = region_name(region) == region_name(&option);
+                if ui.selectable_label(selected, region_name(&option)).clicked() && !selected {
+                    *region = option;
+                }
+            }
+        });
+    match region {
+        Region::Everywhere => {}
+        Region::Rect { min, max } => {
+            vec2_edit(ui, "Min:", min);
+            vec2_edit(ui, "Max:", max);
+        }
+        Region::Circle { center, radius } => {
+            vec2_edit(ui, "Centre:", center);
+            scalar_edit(ui, "Radius:", radius);
+        }
+    }
+}
+
+/// One checkbox per particle type, toggling its index in `types`
+fn type_checkboxes(ui: &mut egui::Ui, alchemy: &AlchemyRules, types: &mut Vec<usize>) {
+    ui.horizontal_wrapped(|ui| {
+        for (idx, def) in alchemy.particle_types.iter().enumerate() {
+            let mut checked = types.contains(&idx);
+            if ui.checkbox(&mut checked, &def.name).changed() {
+                if checked {
+                    types.push(idx);
+                    types.sort_unstable();
+                } else {
+                    types.retain(|&t| t != idx);
+                }
+            }
+        }
+    });
+}
+
+#[derive(Default)]
+pub struct ExternalFieldsUiState {
+    // Index into `ExternalShape::NAMES` of the kind the "Add" button creates
+    new_kind: usize,
+}
+
+/// Adds, edits and removes the external force fields
+pub fn external_fields_ui(
+    mut contexts: EguiContexts,
+    mut commands: Commands,
+    alchemy: Res<AlchemyRules>,
+    mut field_query: Query<(Entity, &mut ExternalField)>,
+    mut state: Local<ExternalFieldsUiState>,
+) {
+    egui::Window::new("External Fields").default_open(false).show(contexts.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source("new_external_kind")
+                .selected_text(ExternalShape::NAMES[state.new_kind])
+                .show_ui(ui, |ui| {
+                    for (idx, name) in ExternalShape::NAMES.iter().enumerate() {
+                        ui.selectable_value(&mut state.new_kind, idx, *name);
+                    }
+                });
+            if ui.button("Add").clicked() {
+                commands.spawn(ExternalField {
+                    shape: ExternalShape::from_name(ExternalShape::NAMES[state.new_kind]),
+                    region: Region::Everywhere,
+                    weights: Vec::new(),
+                });
+            }
+        });
+        ui.separator();
+
+        for (entity, mut field) in field_query.iter_mut() {
+            let field = &mut *field;
+            ui.push_id(entity, |ui| {
+                ui.horizontal(|ui| {
+                    ui.strong(field.shape.name());
+                    if ui.small_button("Remove").clicked() {
+                        commands.entity(entity).despawn();
+                    }
+                });
+
+                match &mut field.shape {
+                    ExternalShape::Uniform { force } => vec2_edit(ui, "Force:", force),
+                    ExternalShape::Central { center, strength } | ExternalShape::Vortex { center, strength } => {
+                        vec2_edit(ui, "Centre:", center);
+                        scalar_edit(ui, "Strength:", strength);
+                    }
+                    ExternalShape::Shear { center, rate } => {
+                        vec2_edit(ui, "Centre:", center);
+                        scalar_edit(ui, "Rate:", rate);
+                    }
+                }
+                region_edit(ui, &mut field.region);
+
+                ui.label("Weight per type:");
+                egui::Grid::new("external_weights").show(ui, |ui| {
+                    for (idx, def) in alchemy.particle_types.iter().enumerate() {
+                        let mut weight = field.weight(idx);
+                        ui.label(&def.name);
+                        if ui.add(egui::DragValue::new(&mut weight).speed(0.05)).changed() {
+                            if field.weights.len() <= idx {
+                                field.weights.resize(idx + 1, 1.0);
+                            }
+                            field.weights[idx] = weight;
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+            ui.separator();
+        }
+    });
+}
+
+/// Adds and edits particle sources and sinks, and the lifetime of each particle type
+pub fn sources_ui(
+    mut contexts: EguiContexts,
+    mut commands: Commands,
+    mut alchemy: ResMut<AlchemyRules>,
+    mut source_query: Query<(Entity, &mut ParticleSource)>,
+    mut sink_query: Query<(Entity, &mut Sink)>,
+    camera_query: Query<&Transform, With<Camera>>,
+) {
+    // New sources and sinks appear in the middle of the view
+    let view_center = camera_query.get_single().map_or(Vec2::ZERO, |t| t.translation.truncate());
+
+    egui::Window::new("Sources & Sinks").default_open(false).show(contexts.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            if ui.button("Add Source").clicked() {
+                commands.spawn(ParticleSource {
+                    position: view_center,
+                    radius: 10.0,
+                    types: vec![0],
+                    rate: 5.0,
+                    velocity: VelocityDistribution::from_name("Fixed"),
+                    pending: 0.0,
+                });
+            }
+            if ui.button("Add Sink").clicked() {
+                commands.spawn(Sink {
+                    region: Region::Circle { center: view_center, radius: 50.0 },
+                    types: Vec::new(),
+                });
+            }
+        });
+
+        ui.separator();
+        ui.heading("Sources");
+        for (entity, mut source) in source_query.iter_mut() {
+            let source = &mut *source;
+            ui.push_id(entity, |ui| {
+                ui.horizontal(|ui| {
+                    ui.strong(format!("Source at ({:.0}, {:.0})", source.position.x, source.position.y));
+                    if ui.small_button("Remove").clicked() {
+                        commands.entity(entity).despawn();
+                    }
+                });
+                vec2_edit(ui, "Position:", &mut source.position);
+                ui.horizontal(|ui| {
+                    ui.label("Radius:");
+                    ui.add(egui::DragValue::new(&mut source.radius).speed(1.0).clamp_range(0.0..=f32::MAX));
+                    ui.label("Rate (per s):");
+                    ui.add(egui::DragValue::new(&mut source.rate).speed(0.1).clamp_range(0.0..=f32::MAX));
+                });
+                ui.label("Types:");
+                type_checkboxes(ui, &alchemy, &mut source.types);
+
+                egui::ComboBox::from_label("Velocity")
+                    .selected_text(source.velocity.name())
+                    .show_ui(ui, |ui| {
+                        for name in VelocityDistribution::NAMES {
+                            let selected = source.velocity.name() == name;
+                            if ui.selectable_label(selected, name).clicked() && !selected {
+                                source.velocity = VelocityDistribution::from_name(name);
+                            }
+                        }
+                    });
+                match &mut source.velocity {
+                    VelocityDistribution::Fixed { velocity } => vec2_edit(ui, "Velocity:", velocity),
+                    VelocityDistribution::Cone { direction, spread, min_speed, max_speed } => {
+                        ui.horizontal(|ui| {
+                            ui.label("Direction:");
+                            ui.drag_angle(direction);
+                            ui.label("Spread:");
+                            ui.drag_angle(spread);
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Speed:");
+                            ui.add(egui::DragValue::new(min_speed).speed(1.0).clamp_range(0.0..=f32::MAX));
+                            ui.label("to");
+                            ui.add(egui::DragValue::new(max_speed).speed(1.0).clamp_range(0.0..=f32::MAX));
+                        });
+                    }
+                    VelocityDistribution::Thermal { temperature } => {
+                        ui.horizontal(|ui| {
+                            ui.label("Temperature (½mv²):");
+                            ui.add(egui::DragValue::new(temperature).speed(10.0).clamp_range(0.0..=f32::MAX));
+                        });
+                    }
+                }
+            });
+            ui.separator();
+        }
+
+        ui.heading("Sinks");
+        for (entity, mut sink) in sink_query.iter_mut() {
+            let sink = &mut *sink;
+            ui.push_id(entity, |ui| {
+                ui.horizontal(|ui| {
+                    ui.strong("Sink");
+                    if ui.small_button("Remove").clicked() {
+                        commands.entity(entity).despawn();
+                    }
+                });
+                region_edit(ui, &mut sink.region);
+                ui.label("Absorbs (none checked = every type):");
+                type_checkboxes(ui, &alchemy, &mut sink.types);
+            });
+            ui.separator();
+        }
+
+        ui.heading("Lifetimes");
+        ui.label("Particles of a type with a lifetime despawn after that many simulated seconds.");
+        egui::Grid::new("lifetimes").show(ui, |ui| {
+            // Only touch the rules on an actual edit, so change detection stays quiet
+            let mut edits = Vec::new();
+            for (idx, def) in alchemy.particle_types.iter().enumerate() {
+                let mut limited = def.lifetime.is_some();
+                let mut seconds = def.lifetime.unwrap_or(10.0);
+                ui.label(&def.name);
+                let mut changed = ui.checkbox(&mut limited, "").changed();
+                if limited {
+                    changed |= ui.add(egui::DragValue::new(&mut seconds).speed(0.1).suffix(" s").clamp_range(0.01..=f32::MAX)).changed();
+                }
+                if changed {
+                    edits.push((idx, limited.then_some(seconds)));
+                }
+                ui.end_row();
+            }
+            for (idx, lifetime) in edits {
+                alchemy.particle_types[idx].lifetime = lifetime;
+            }
+        });
+    });
+}
+
+#[derive(Default)]
+pub struct ScriptUiState {
+    path: String,
+}
+
+/// Load, unload and monitor the Rhai script driving the simulation
+pub fn script_ui(
+    mut contexts: EguiContexts,
+    mut host: ResMut<ScriptHost>,
+    mut state: Local<ScriptUiState>,
+) {
+    if state.path.is_empty() {
+        state.path = "script.rhai".to_string();
+    }
+
+    egui::Window::new("Script").default_open(false).show(contexts.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            ui.label("File:");
+            ui.text_edit_singleline(&mut state.path);
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("Load").clicked() {
+                host.request_load(std::path::PathBuf::from(&state.path));
+            }
+            if ui.add_enabled(host.loaded_path().is_some(), egui::Button::new("Unload")).clicked() {
+                host.unload();
+            }
+            ui.checkbox(&mut host.hot_reload, "Reload on change");
+        });
+
+        match host.loaded_path() {
+            Some(path) => ui.label(format!("Running: {}", path.display())),
+            None => ui.label("No script loaded"),
+        };
+        if let Some(status) = &host.status {
+            ui.colored_label(egui::Color32::GREEN, status);
+        }
+        if let Some(error) = &host.error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+
+        ui.collapsing("API", |ui| {
+            ui.label("Hooks: top-level code runs on every (re)load, fn setup() on first load, fn on_step(step) before each step.");
+            ui.monospace("particle_count()  particles()  particles_of_type(t)");
+            ui.monospace("spawn_particle(t, x, y[, vx, vy])  despawn_particle(id)");
+            ui.monospace("type_count()  get/set_interaction(a, b[, w])");
+            ui.monospace("get/set_field_radius(t[, r])  get/set_field_strength(t[, s])");
+            ui.monospace("get/set_light_speed([c])  get/set_time_scale([s])");
+            ui.label("Changes a script makes to rules and constants are not recorded in the edit history and cannot be undone.");
+        });
+    });
+}
+
+/// Marks the start of a frame for the edit history, so changes made outside the editors are not recorded
+pub fn history_begin_system(
+    mut history: ResMut<EditHistory>,
+    alchemy: Res<AlchemyRules>,
+    global_consts: Res<GlobalConstants>,
+) {
+    history.begin_frame(&alchemy, &global_consts);
+}
+
+/// Undo / redo of rule and constant edits (Ctrl+Z, Ctrl+Shift+Z) and the history panel
+pub fn history_ui(
+    mut contexts: EguiContexts,
+    mut history: ResMut<EditHistory>,
+    mut alchemy: ResMut<AlchemyRules>,
+    mut global_consts: ResMut<GlobalConstants>,
+    mut trail_settings: ResMut<TrailSettings>,
+    mut commands: Commands,
+    mut particle_query: Query<(Entity, &mut ParticleTypeID, &mut Mass), With<Particle>>,
+) {
+    let ctx = contexts.ctx_mut();
+
+    // Commit this frame's edits first, unless a drag or text entry is still in progress
+    let interacting = ctx.input(|i| i.pointer.any_down()) || ctx.wants_keyboard_input();
+    history.end_frame(&alchemy, &global_consts, interacting);
+
+    // Positive = redo that many entries, negative = undo
+    let mut steps: isize = 0;
+    if !ctx.wants_keyboard_input() {
+        ctx.input_mut(|i| {
+            // Most specific shortcut first, Ctrl+Z would also match Ctrl+Shift+Z
+            if i.consume_key(egui::Modifiers::COMMAND | egui::Modifiers::SHIFT, egui::Key::Z)
+                || i.consume_key(egui::Modifiers::COMMAND, egui::Key::Y) {
+                steps = 1;
+            } else if i.consume_key(egui::Modifiers::COMMAND, egui::Key::Z) {
+                steps = -1;
+            }
+        });
+    }
+
+    egui::Window::new("History").default_open(false).show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            if ui.add_enabled(!history.undo_entries.is_empty(), egui::Button::new("Undo")).clicked() {
+                steps = -1;
+            }
+            if ui.add_enabled(!history.redo_entries.is_empty(), egui::Button::new("Redo")).clicked() {
+                steps = 1;
+            }
+            ui.label("Ctrl+Z / Ctrl+Shift+Z");
+        });
+        ui.separator();
+
+        egui::ScrollArea::vertical().id_source("edit_history").max_height(300.0).show(ui, |ui| {
+            // Oldest first; clicking an entry jumps back to just before that edit
+            let undo_len = history.undo_entries.len();
+            for (i, entry) in history.undo_entries.iter().enumerate() {
+                if ui.selectable_label(false, &entry.label).clicked() {
+                    steps = -((undo_len - i) as isize);
+                }
+            }
+            ui.label(egui::RichText::new("● Current").strong());
+            for (i, entry) in history.redo_entries.iter().rev().enumerate() {
+                let label = egui::RichText::new(&entry.label).weak();
+                if ui.selectable_label(false, label).clicked() {
+                    steps = i as isize + 1;
+                }
+            }
+        });
+    });
+
+    let mut restored: Option<EditState> = None;
+    for _ in 0..steps.unsigned_abs() {
+        let state = if steps < 0 { history.undo() } else { history.redo() };
+        if state.is_some() {
+            restored = state;
+        }
+    }
+
+    if let Some(mut state) = restored {
+        // Textures may have been redrawn for newer shapes since; let them be recreated
+        for def in state.rules.particle_types.iter_mut() {
+            def.field_texture = None;
+        }
+        replace_rules(&mut alchemy, state.rules, &mut global_consts, Some(state.constants), &mut trail_settings, &mut commands, &mut particle_query);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn per_type_flags_follow_reordered_and_removed_types() {
+        // Old types 0, 1, 2: type 0 moves to 1, type 1 is gone, type 2 moves to 0
+        let remap = [Some(1), None, Some(0)];
+        assert_eq!(remap_per_type(&[false, false, true], &remap, 2), vec![true, false]);
+        // A new third type starts on; missing old entries count as on
+        assert_eq!(remap_per_type(&[false], &remap, 3), vec![true, false, true]);
+    }
+
+    fn types(names: &[&str]) -> Vec<ParticleTypeDefinition> {
+        let template = AlchemyRules::default().particle_types[0].clone();
+        names.iter().map(|name| ParticleTypeDefinition { name: name.to_string(), ..template.clone() }).collect()
+    }
+
+    #[test]
+    fn types_are_remapped_by_name_first() {
+        assert_eq!(type_remap(&types(&["A", "B", "C"]), &types(&["C", "A"])), vec![Some(1), None, Some(0)]);
+        // Renamed types keep their index
+        assert_eq!(type_remap(&types(&["A", "B"]), &types(&["X", "Y", "Z"])), vec![Some(0), Some(1)]);
+    }
+
+    #[test]
+    fn index_fallback_never_takes_a_type_claimed_by_name() {
+        // B moves to index 0, so A must not fall back onto it
+        assert_eq!(type_remap(&types(&["A", "B"]), &types(&["B", "C"])), vec![None, Some(0)]);
+        assert_eq!(type_remap(&types(&["A", "B", "D"]), &types(&["B", "C", "E"])), vec![None, Some(0), Some(2)]);
+    }
+}